@@ -0,0 +1 @@
+ALTER TABLE user_certificates ADD COLUMN serial TEXT;
\ No newline at end of file
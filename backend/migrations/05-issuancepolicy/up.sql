@@ -0,0 +1,10 @@
+CREATE TABLE issuance_policies (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    user_id INTEGER,
+    role INTEGER,
+    type INTEGER,
+    rules TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE user_certificates ADD COLUMN revoked_on INTEGER;
\ No newline at end of file
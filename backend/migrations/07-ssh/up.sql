@@ -0,0 +1,30 @@
+CREATE TABLE ssh_ca (
+    id INTEGER PRIMARY KEY,
+    created_on INTEGER NOT NULL,
+    private_key TEXT NOT NULL,
+    public_key TEXT NOT NULL
+);
+
+CREATE TABLE ssh_public_keys (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_on INTEGER NOT NULL,
+    public_key TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE ssh_certificates (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_on INTEGER NOT NULL,
+    valid_until INTEGER NOT NULL,
+    type INTEGER NOT NULL,
+    serial TEXT NOT NULL,
+    principals TEXT NOT NULL,
+    certificate TEXT NOT NULL,
+    ssh_ca_id INTEGER,
+    user_id INTEGER,
+    FOREIGN KEY(ssh_ca_id) REFERENCES ssh_ca(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
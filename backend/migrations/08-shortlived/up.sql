@@ -0,0 +1,11 @@
+CREATE TABLE short_lived_certificates (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_on INTEGER NOT NULL,
+    valid_until INTEGER NOT NULL,
+    serial TEXT NOT NULL,
+    ca_id INTEGER,
+    user_id INTEGER,
+    FOREIGN KEY(ca_id) REFERENCES ca_certificates(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,22 @@
+CREATE TABLE ca_backups (
+    id INTEGER PRIMARY KEY,
+    created_on INTEGER NOT NULL,
+    threshold INTEGER NOT NULL,
+    share_count INTEGER NOT NULL,
+    backup TEXT NOT NULL,
+    ca_id INTEGER,
+    user_id INTEGER,
+    FOREIGN KEY(ca_id) REFERENCES ca_certificates(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE TABLE ca_backup_shares (
+    id INTEGER PRIMARY KEY,
+    backup_id INTEGER NOT NULL,
+    share_index INTEGER NOT NULL,
+    share TEXT,
+    downloaded_on INTEGER,
+    downloaded_by INTEGER,
+    FOREIGN KEY(backup_id) REFERENCES ca_backups(id) ON DELETE CASCADE,
+    FOREIGN KEY(downloaded_by) REFERENCES users(id) ON DELETE SET NULL
+);
\ No newline at end of file
@@ -0,0 +1,27 @@
+CREATE TABLE server_certificate_requests (
+    id INTEGER PRIMARY KEY,
+    created_on INTEGER NOT NULL,
+    expires_on INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    validity_in_years INTEGER,
+    key_algorithm INTEGER,
+    system_generated_password INTEGER NOT NULL,
+    pkcs12_password TEXT,
+    status INTEGER NOT NULL DEFAULT 0,
+    certificate_id INTEGER,
+    user_id INTEGER NOT NULL,
+    FOREIGN KEY(certificate_id) REFERENCES user_certificates(id) ON DELETE SET NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE domain_validations (
+    id INTEGER PRIMARY KEY,
+    request_id INTEGER NOT NULL,
+    dns_name TEXT NOT NULL,
+    method INTEGER NOT NULL,
+    token TEXT NOT NULL,
+    status INTEGER NOT NULL DEFAULT 0,
+    validated_on INTEGER,
+    error TEXT,
+    FOREIGN KEY(request_id) REFERENCES server_certificate_requests(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,10 @@
+CREATE TABLE api_tokens (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_on INTEGER NOT NULL,
+    expires_on INTEGER,
+    last_used_on INTEGER,
+    token_hash TEXT NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
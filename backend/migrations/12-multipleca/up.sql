@@ -0,0 +1,5 @@
+ALTER TABLE ca_certificates ADD COLUMN name TEXT NOT NULL DEFAULT '';
+ALTER TABLE ca_certificates ADD COLUMN purpose INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE ca_certificates ADD COLUMN active INTEGER NOT NULL DEFAULT 1;
+-- Only the most recent CA issued certificates so far
+UPDATE ca_certificates SET active = 0 WHERE id != (SELECT MAX(id) FROM ca_certificates);
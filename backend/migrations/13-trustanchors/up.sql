@@ -0,0 +1,11 @@
+CREATE TABLE trust_anchors (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_on INTEGER NOT NULL,
+    valid_until INTEGER NOT NULL,
+    subject TEXT NOT NULL,
+    fingerprint TEXT NOT NULL UNIQUE,
+    purpose INTEGER NOT NULL DEFAULT 0,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    certificate BLOB NOT NULL
+);
@@ -0,0 +1,23 @@
+CREATE TABLE ct_log_keys (
+    id INTEGER PRIMARY KEY,
+    created_on INTEGER NOT NULL,
+    private_key BLOB NOT NULL
+);
+
+CREATE TABLE ct_log_entries (
+    leaf_index INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    leaf_hash BLOB NOT NULL,
+    leaf_input BLOB NOT NULL,
+    extra_data BLOB NOT NULL
+);
+
+CREATE TRIGGER ct_log_entries_no_update BEFORE UPDATE ON ct_log_entries
+BEGIN
+    SELECT RAISE(ABORT, 'The certificate transparency log is append-only');
+END;
+
+CREATE TRIGGER ct_log_entries_no_delete BEFORE DELETE ON ct_log_entries
+BEGIN
+    SELECT RAISE(ABORT, 'The certificate transparency log is append-only');
+END;
@@ -0,0 +1,2 @@
+ALTER TABLE user_certificates ADD COLUMN valid_from INTEGER;
+UPDATE user_certificates SET valid_from = created_on WHERE valid_from IS NULL;
@@ -0,0 +1,19 @@
+CREATE TABLE enrollment_tokens (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_on INTEGER NOT NULL,
+    expires_on INTEGER NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    type INTEGER NOT NULL,
+    profile INTEGER NOT NULL DEFAULT 0,
+    validity_in_years INTEGER,
+    key_algorithm INTEGER,
+    dns_names TEXT NOT NULL,
+    upn TEXT,
+    ca_id INTEGER,
+    used_on INTEGER,
+    certificate_id INTEGER,
+    user_id INTEGER NOT NULL,
+    FOREIGN KEY(certificate_id) REFERENCES user_certificates(id) ON DELETE SET NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+ALTER TABLE user_certificates ADD COLUMN revocation_reason INTEGER;
+ALTER TABLE user_certificates ADD COLUMN superseded_by INTEGER REFERENCES user_certificates(id) ON DELETE SET NULL;
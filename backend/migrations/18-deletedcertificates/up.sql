@@ -0,0 +1 @@
+ALTER TABLE user_certificates ADD COLUMN deleted_on INTEGER;
@@ -1,3 +1,4 @@
 pub(crate) mod password_auth;
 pub(crate) mod session_auth;
-pub(crate) mod oidc_auth;
\ No newline at end of file
+pub(crate) mod oidc_auth;
+pub(crate) mod token_auth;
\ No newline at end of file
@@ -21,21 +21,22 @@ pub(crate) struct Claims {
 
 /// Rocket guard implementation
 /// Authenticate user through auth_token cookie
+/// API tokens are only accepted by the TokenAuthenticated guard
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for Authenticated {
     type Error = ();
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let token = match request.cookies().get_private("auth_token") {
-            Some(cookie) => cookie.value().to_string(),
-            _ => return Outcome::Error((Status::Unauthorized, ())),
-        };
-
         let config = match request.rocket().state::<AppState>() {
             Some(c) => c,
             None => return Outcome::Error((Status::InternalServerError, ())),
         };
 
+        let token = match request.cookies().get_private("auth_token") {
+            Some(cookie) => cookie.value().to_string(),
+            _ => return Outcome::Error((Status::Unauthorized, ())),
+        };
+
         let settings = config.settings.lock().await;
         let jwt_key = match settings.get_jwt_key() {
             Ok(k) => k,
@@ -44,7 +45,7 @@ impl<'r> FromRequest<'r> for Authenticated {
         let decoding_key = DecodingKey::from_secret(&jwt_key);
         let validation = Validation::default();
 
-        let claims = match decode::<Claims>(&*token, &decoding_key, &validation) {
+        let claims = match decode::<Claims>(&token, &decoding_key, &validation) {
             Ok(c) => c.claims,
             Err(_) => return Outcome::Error((Status::Unauthorized, ())),
         };
@@ -0,0 +1,105 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::hash::{hash, MessageDigest};
+use openssl::rand::rand_bytes;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::Serialize;
+use crate::{ApiError, AppState};
+use crate::auth::session_auth::{Authenticated, Claims};
+use crate::constants::{API_TOKEN_DEFAULT_DAYS, API_TOKEN_MAX_DAYS};
+
+/// Prefix of API tokens, so that leaked tokens are easy to recognize.
+const TOKEN_PREFIX: &str = "vaultls_";
+
+/// API token authenticating a user without a session, e.g. for automation.
+/// Only the hash of the token is stored, the token itself is returned once on creation.
+#[derive(Serialize, Clone, Default)]
+pub(crate) struct ApiToken {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    pub(crate) created_on: i64,
+    pub(crate) expires_on: Option<i64>,
+    pub(crate) last_used_on: Option<i64>,
+    pub(crate) user_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) token: Option<String>,
+    #[serde(skip)]
+    pub(crate) token_hash: String,
+}
+
+/// Rocket guard of the endpoints meant for automation, such as the Vault PKI API and certificate renewal.
+/// Besides a session, it accepts an API token in the Authorization (Bearer) or X-Vault-Token header.
+/// All other endpoints only accept sessions, so a leaked token cannot manage users, settings or CAs.
+pub struct TokenAuthenticated {
+    pub claims: Claims,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TokenAuthenticated {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request.headers().get_one("Authorization")
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .or_else(|| request.headers().get_one("X-Vault-Token"));
+        let Some(token) = token.filter(|token| is_api_token(token)) else {
+            return Authenticated::from_request(request).await
+                .map(|authentication| TokenAuthenticated { claims: authentication.claims })
+        };
+
+        let config = match request.rocket().state::<AppState>() {
+            Some(c) => c,
+            None => return Outcome::Error((Status::InternalServerError, ())),
+        };
+        let Ok(token_hash) = hash_api_token(token) else {
+            return Outcome::Error((Status::InternalServerError, ()))
+        };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let db = config.db.lock().await;
+        match db.use_api_token(&token_hash, now.as_millis() as i64) {
+            Ok(Some(user)) => Outcome::Success(TokenAuthenticated {
+                claims: Claims { id: user.id, role: user.role, exp: now.as_secs() as usize + 60 }
+            }),
+            Ok(None) => Outcome::Error((Status::Unauthorized, ())),
+            Err(_) => Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
+}
+
+/// Creates a new random API token for the user.
+pub(crate) fn create_api_token(name: &str, user_id: i64, expires_in_days: Option<u64>) -> Result<ApiToken, ApiError> {
+    if name.trim().is_empty() {
+        return Err(ApiError::BadRequest("Token name is required".to_string()))
+    }
+    let expires_in_days = expires_in_days.unwrap_or(API_TOKEN_DEFAULT_DAYS);
+    if expires_in_days == 0 || expires_in_days > API_TOKEN_MAX_DAYS {
+        return Err(ApiError::BadRequest(format!("Expiry must be between 1 and {} days", API_TOKEN_MAX_DAYS)))
+    }
+    let mut secret = [0u8; 32];
+    rand_bytes(&mut secret)?;
+    let token = format!("{}{}", TOKEN_PREFIX, secret.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+
+    let created_on = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    Ok(ApiToken {
+        name: name.trim().to_string(),
+        created_on,
+        expires_on: Some(created_on + expires_in_days as i64 * 24 * 60 * 60 * 1000),
+        user_id,
+        token_hash: hash_api_token(&token)?,
+        token: Some(token),
+        ..Default::default()
+    })
+}
+
+/// Returns the hex SHA-256 hash under which a token is stored.
+/// Tokens are random, so they do not need a slow password hash.
+pub(crate) fn hash_api_token(token: &str) -> Result<String, ApiError> {
+    let digest = hash(MessageDigest::sha256(), token.trim().as_bytes())?;
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Checks whether a credential looks like an API token rather than a session.
+pub(crate) fn is_api_token(token: &str) -> bool {
+    token.trim().starts_with(TOKEN_PREFIX)
+}
@@ -0,0 +1,371 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::base64;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use crate::ApiError;
+use crate::cert::{asn1_time_to_millis, get_common_name, Certificate};
+use crate::data::enums::CertificateType;
+use crate::helper::get_secret;
+
+const BACKUP_VERSION: u8 = 1;
+const SHARE_PREFIX: &str = "vaultls-share";
+
+/// CA key backup. The key encrypting the CA is split into shares of which `threshold` are
+/// needed for a restore. Shares are only stored until they are downloaded, and are sealed with
+/// the share key until then, so that the database alone does not contain a quorum of shares.
+#[derive(Serialize, Clone, Default)]
+pub(crate) struct CaBackup {
+    pub(crate) id: i64,
+    pub(crate) created_on: i64,
+    pub(crate) ca_id: i64,
+    pub(crate) user_id: i64,
+    pub(crate) threshold: u8,
+    pub(crate) share_count: u8,
+    pub(crate) shares: Vec<CaBackupShare>,
+    #[serde(skip)]
+    pub(crate) backup: String,
+}
+
+#[derive(Serialize, Clone, Default)]
+pub(crate) struct CaBackupShare {
+    pub(crate) index: u8,
+    pub(crate) downloaded_on: Option<i64>,
+    pub(crate) downloaded_by: Option<i64>,
+    #[serde(skip)]
+    pub(crate) share: Option<String>,
+}
+
+/// Encrypted CA as downloaded by admins. Without a quorum of shares it is useless.
+#[derive(Serialize, Deserialize)]
+struct EncryptedCa {
+    version: u8,
+    backup_id: String,
+    threshold: u8,
+    nonce: String,
+    ciphertext: String,
+    tag: String,
+}
+
+/// Plaintext of the backup.
+#[derive(Serialize, Deserialize)]
+struct CaKeyMaterial {
+    certificate: String,
+    key: String,
+}
+
+/// Returns the key sealing stored shares, derived from VAULTLS_API_SECRET which is not stored in the database.
+/// Shares that have not been downloaded cannot be opened anymore once the secret changes.
+pub(crate) fn share_key() -> Result<Vec<u8>, ApiError> {
+    let secret = get_secret("VAULTLS_API_SECRET")?;
+    let mut data = SHARE_PREFIX.as_bytes().to_vec();
+    data.extend_from_slice(secret.trim().as_bytes());
+    Ok(hash(MessageDigest::sha256(), &data)?.to_vec())
+}
+
+/// Encrypts the CA with a random key and splits the key into `share_count` shares,
+/// any `threshold` of which can restore it. The shares are sealed with the share key for storage.
+pub(crate) fn create_backup(ca: &Certificate, threshold: u8, share_count: u8, share_key: &[u8]) -> Result<CaBackup, ApiError> {
+    if threshold < 2 || threshold > share_count {
+        return Err(ApiError::BadRequest("Threshold must be at least 2 and at most the number of shares".to_string()))
+    }
+
+    let mut backup_id = [0u8; 8];
+    rand_bytes(&mut backup_id)?;
+    let backup_id = to_hex(&backup_id);
+    let mut key = [0u8; 32];
+    rand_bytes(&mut key)?;
+    let mut nonce = [0u8; 12];
+    rand_bytes(&mut nonce)?;
+
+    let plaintext = serde_json::to_vec(&CaKeyMaterial {
+        certificate: base64::encode_block(&ca.cert),
+        key: base64::encode_block(&ca.key),
+    }).map_err(|e| ApiError::Other(e.to_string()))?;
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(Cipher::aes_256_gcm(), &key, Some(&nonce), backup_id.as_bytes(), &plaintext, &mut tag)?;
+
+    let encrypted = EncryptedCa {
+        version: BACKUP_VERSION,
+        backup_id: backup_id.clone(),
+        threshold,
+        nonce: base64::encode_block(&nonce),
+        ciphertext: base64::encode_block(&ciphertext),
+        tag: base64::encode_block(&tag),
+    };
+
+    let shares = split_secret(&key, threshold, share_count)?
+        .into_iter()
+        .map(|(index, share)| Ok(CaBackupShare {
+            index,
+            share: Some(seal_share(&format!("{}-{}-{}-{}-{}", SHARE_PREFIX, backup_id, threshold, index, to_hex(&share)), share_key)?),
+            ..Default::default()
+        }))
+        .collect::<Result<Vec<CaBackupShare>, ApiError>>()?;
+
+    Ok(CaBackup {
+        created_on: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64,
+        ca_id: ca.id,
+        threshold,
+        share_count,
+        shares,
+        backup: serde_json::to_string_pretty(&encrypted).map_err(|e| ApiError::Other(e.to_string()))?,
+        ..Default::default()
+    })
+}
+
+/// Reconstructs the key from the shares and decrypts the CA of the backup.
+pub(crate) fn restore_backup(backup: &str, shares: &[String]) -> Result<Certificate, ApiError> {
+    let encrypted: EncryptedCa = serde_json::from_str(backup)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid backup: {}", e)))?;
+    if encrypted.version != BACKUP_VERSION {
+        return Err(ApiError::BadRequest(format!("Unsupported backup version {}", encrypted.version)))
+    }
+
+    let mut parsed_shares: Vec<(u8, Vec<u8>)> = Vec::new();
+    for share in shares {
+        let (index, value) = parse_share(share, &encrypted.backup_id)?;
+        if parsed_shares.iter().any(|(existing, _)| *existing == index) {
+            return Err(ApiError::BadRequest(format!("Share {} was given more than once", index)))
+        }
+        parsed_shares.push((index, value));
+    }
+    if parsed_shares.len() < encrypted.threshold as usize {
+        return Err(ApiError::BadRequest(format!("At least {} shares are required", encrypted.threshold)))
+    }
+
+    let key = combine_shares(&parsed_shares);
+    let decode = |value: &str| base64::decode_block(value)
+        .map_err(|_| ApiError::BadRequest("Invalid backup encoding".to_string()));
+    let plaintext = decrypt_aead(
+        Cipher::aes_256_gcm(),
+        &key,
+        Some(&decode(&encrypted.nonce)?),
+        encrypted.backup_id.as_bytes(),
+        &decode(&encrypted.ciphertext)?,
+        &decode(&encrypted.tag)?
+    ).map_err(|_| ApiError::BadRequest("Failed to decrypt backup, are the shares correct?".to_string()))?;
+
+    let material: CaKeyMaterial = serde_json::from_slice(&plaintext)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid backup content: {}", e)))?;
+    let cert = decode(&material.certificate)?;
+    let key = decode(&material.key)?;
+
+    let ca_cert = X509::from_der(&cert)?;
+    let ca_key = PKey::private_key_from_der(&key)?;
+    if !ca_cert.public_key()?.public_eq(&ca_key) {
+        return Err(ApiError::BadRequest("CA key does not match the certificate".to_string()))
+    }
+
+    Ok(Certificate {
+        name: get_common_name(&ca_cert).unwrap_or_default(),
+        created_on: asn1_time_to_millis(ca_cert.not_before())?,
+        valid_until: asn1_time_to_millis(ca_cert.not_after())?,
+        certificate_type: CertificateType::CA,
+        cert,
+        key,
+        ..Default::default()
+    })
+}
+
+/// Seals a share for storage, the result is the base64 encoded nonce, tag and ciphertext.
+fn seal_share(share: &str, share_key: &[u8]) -> Result<String, ApiError> {
+    let mut nonce = [0u8; 12];
+    rand_bytes(&mut nonce)?;
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(Cipher::aes_256_gcm(), share_key, Some(&nonce), SHARE_PREFIX.as_bytes(), share.as_bytes(), &mut tag)?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&tag);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(base64::encode_block(&sealed))
+}
+
+/// Opens a stored share for its download.
+pub(crate) fn open_share(sealed: &str, share_key: &[u8]) -> Result<String, ApiError> {
+    let unreadable = || ApiError::Other("Share cannot be opened, has VAULTLS_API_SECRET changed?".to_string());
+    let sealed = base64::decode_block(sealed).map_err(|_| unreadable())?;
+    if sealed.len() < 28 {
+        return Err(unreadable())
+    }
+    let (nonce, rest) = sealed.split_at(12);
+    let (tag, ciphertext) = rest.split_at(16);
+    let share = decrypt_aead(Cipher::aes_256_gcm(), share_key, Some(nonce), SHARE_PREFIX.as_bytes(), ciphertext, tag)
+        .map_err(|_| unreadable())?;
+    String::from_utf8(share).map_err(|_| unreadable())
+}
+
+fn parse_share(share: &str, backup_id: &str) -> Result<(u8, Vec<u8>), ApiError> {
+    let invalid = || ApiError::BadRequest("Invalid share format".to_string());
+    let rest = share.trim().strip_prefix(SHARE_PREFIX).and_then(|rest| rest.strip_prefix('-')).ok_or_else(invalid)?;
+    let parts: Vec<&str> = rest.split('-').collect();
+    let [share_backup_id, _threshold, index, value] = parts.as_slice() else { return Err(invalid()) };
+
+    if *share_backup_id != backup_id {
+        return Err(ApiError::BadRequest("Share does not belong to this backup".to_string()))
+    }
+    let index: u8 = index.parse().map_err(|_| invalid())?;
+    let value = from_hex(value).ok_or_else(invalid)?;
+    if index == 0 || value.len() != 32 {
+        return Err(invalid())
+    }
+    Ok((index, value))
+}
+
+/// Splits the secret byte-wise with Shamir's secret sharing over GF(256).
+/// Returns the shares with their x coordinates 1 to `share_count`.
+fn split_secret(secret: &[u8], threshold: u8, share_count: u8) -> Result<Vec<(u8, Vec<u8>)>, ApiError> {
+    let mut shares: Vec<(u8, Vec<u8>)> = (1..=share_count).map(|x| (x, Vec::with_capacity(secret.len()))).collect();
+    let mut coefficients = vec![0u8; threshold as usize - 1];
+
+    for byte in secret {
+        rand_bytes(&mut coefficients)?;
+        for (x, share) in shares.iter_mut() {
+            // Horner's method for secret + c1 * x + ... + c(t-1) * x^(t-1)
+            let y = coefficients.iter().rev().fold(0u8, |acc, coefficient| gf_mul(acc, *x) ^ coefficient);
+            share.push(gf_mul(y, *x) ^ byte);
+        }
+    }
+    Ok(shares)
+}
+
+/// Reconstructs the secret with Lagrange interpolation at x = 0.
+fn combine_shares(shares: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let length = shares[0].1.len();
+    let mut secret = vec![0u8; length];
+
+    for (i, (x_i, y_i)) in shares.iter().enumerate() {
+        let basis = shares.iter().enumerate()
+            .filter(|(j, _)| *j != i)
+            .fold(1u8, |acc, (_, (x_j, _))| gf_mul(acc, gf_mul(*x_j, gf_inv(x_j ^ x_i))));
+        for (byte, y) in secret.iter_mut().zip(y_i) {
+            *byte ^= gf_mul(*y, basis);
+        }
+    }
+    secret
+}
+
+/// Multiplication in GF(256) with the AES polynomial.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(256), a^254.
+fn gf_inv(a: u8) -> u8 {
+    (0..253).fold(a, |acc, _| gf_mul(acc, a))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cert::create_ca;
+
+    const SHARE_KEY: [u8; 32] = [7u8; 32];
+
+    fn shares_of(backup: &CaBackup) -> Vec<String> {
+        backup.shares.iter()
+            .map(|share| open_share(share.share.as_deref().unwrap(), &SHARE_KEY).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn gf_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1, "inverse of {}", a);
+        }
+    }
+
+    #[test]
+    fn split_and_combine() {
+        let secret: Vec<u8> = (0..32).collect();
+        let shares = split_secret(&secret, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|(_, share)| *share != secret));
+
+        for i in 0..5 {
+            for j in i + 1..5 {
+                for k in j + 1..5 {
+                    let subset = [shares[i].clone(), shares[j].clone(), shares[k].clone()];
+                    assert_eq!(combine_shares(&subset), secret);
+                }
+                assert_ne!(combine_shares(&[shares[i].clone(), shares[j].clone()]), secret);
+            }
+        }
+    }
+
+    #[test]
+    fn backup_round_trip() {
+        let ca = create_ca("backup test", 1).unwrap();
+        let backup = create_backup(&ca, 2, 3, &SHARE_KEY).unwrap();
+        let shares = shares_of(&backup);
+
+        let restored = restore_backup(&backup.backup, &shares[1..]).unwrap();
+        assert_eq!(restored.cert, ca.cert);
+        assert_eq!(restored.key, ca.key);
+    }
+
+    #[test]
+    fn restore_fails_below_threshold() {
+        let ca = create_ca("backup test", 1).unwrap();
+        let backup = create_backup(&ca, 3, 4, &SHARE_KEY).unwrap();
+        let shares = shares_of(&backup);
+
+        assert!(restore_backup(&backup.backup, &shares[..2]).is_err());
+        // Even if the threshold of the backup is lowered, k-1 shares do not reconstruct the key
+        let mut encrypted: EncryptedCa = serde_json::from_str(&backup.backup).unwrap();
+        encrypted.threshold = 2;
+        assert!(restore_backup(&serde_json::to_string(&encrypted).unwrap(), &shares[..2]).is_err());
+        assert!(restore_backup(&backup.backup, &shares[1..]).is_ok());
+    }
+
+    #[test]
+    fn restore_fails_for_tampered_backup() {
+        let ca = create_ca("backup test", 1).unwrap();
+        let backup = create_backup(&ca, 2, 2, &SHARE_KEY).unwrap();
+        let mut encrypted: EncryptedCa = serde_json::from_str(&backup.backup).unwrap();
+        let mut ciphertext = base64::decode_block(&encrypted.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        encrypted.ciphertext = base64::encode_block(&ciphertext);
+
+        assert!(restore_backup(&serde_json::to_string(&encrypted).unwrap(), &shares_of(&backup)).is_err());
+    }
+
+    #[test]
+    fn stored_shares_are_sealed() {
+        let ca = create_ca("backup test", 1).unwrap();
+        let backup = create_backup(&ca, 2, 2, &SHARE_KEY).unwrap();
+        let sealed = backup.shares[0].share.as_deref().unwrap();
+
+        assert!(!sealed.contains(SHARE_PREFIX));
+        assert!(open_share(sealed, &SHARE_KEY).unwrap().starts_with(SHARE_PREFIX));
+        assert!(open_share(sealed, &[8u8; 32]).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+//! Renewal agent for hosts using VaulTLS certificates.
+//! It renews the configured certificates ahead of their expiry, writes certificate, key and chain files
+//! and runs a reload command, such as `systemctl reload nginx`.
+
+use std::{env, fs};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, bail, Context};
+use openidconnect::reqwest;
+use openssl::asn1::Asn1Time;
+use openssl::base64;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::sign::Signer;
+use openssl::x509::{X509, X509NameBuilder, X509ReqBuilder};
+use serde::Deserialize;
+use backend::{IssuedCertificateResponse, RenewCertificateRequest};
+
+const USAGE: &str = "Usage: vaultls-agent <config.json> [--once]";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct AgentConfig {
+    /// Base URL of VaulTLS, such as https://vaultls.example.com
+    url: String,
+    /// API token authenticating renewals. Without it, the current certificate authenticates them.
+    api_token: Option<String>,
+    /// PEM file with additional CAs to trust for the connection to VaulTLS
+    ca_file: Option<PathBuf>,
+    #[serde(default = "default_renew_before_days")]
+    renew_before_days: u32,
+    #[serde(default = "default_check_interval_secs")]
+    check_interval_secs: u64,
+    certificates: Vec<ManagedCertificate>,
+}
+
+/// Certificate kept up to date by the agent.
+#[derive(Deserialize)]
+struct ManagedCertificate {
+    /// Id of the certificate in VaulTLS
+    id: i64,
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    chain_file: Option<PathBuf>,
+    /// Shell command run after the files were replaced
+    reload_command: Option<String>,
+}
+
+fn default_renew_before_days() -> u32 { 30 }
+fn default_check_interval_secs() -> u64 { 60 * 60 }
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config_path = args.iter().find(|arg| !arg.starts_with("--")).ok_or(anyhow!(USAGE))?;
+    let once = args.iter().any(|arg| arg == "--once");
+
+    let config: AgentConfig = serde_json::from_slice(&fs::read(config_path).context("Failed to read config")?)
+        .context("Failed to parse config")?;
+    let client = create_client(&config)?;
+
+    loop {
+        let mut failed = false;
+        for managed in &config.certificates {
+            match renew_if_due(&config, &client, managed).await {
+                Ok(true) => println!("Certificate {} renewed", managed.id),
+                Ok(false) => {}
+                Err(e) => {
+                    eprintln!("Failed to renew certificate {}: {:#}", managed.id, e);
+                    failed = true;
+                }
+            }
+        }
+        if once {
+            if failed { bail!("Not all certificates could be renewed") }
+            return Ok(())
+        }
+        tokio::time::sleep(Duration::from_secs(config.check_interval_secs)).await;
+    }
+}
+
+fn create_client(config: &AgentConfig) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::ClientBuilder::new().timeout(HTTP_TIMEOUT);
+    if let Some(ca_file) = &config.ca_file {
+        for ca in X509::stack_from_pem(&fs::read(ca_file).context("Failed to read CA file")?)? {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_der(&ca.to_der()?)?);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Renews the certificate if it is missing or expires within the renewal window.
+async fn renew_if_due(config: &AgentConfig, client: &reqwest::Client, managed: &ManagedCertificate) -> anyhow::Result<bool> {
+    let current_cert = match managed.cert_file.exists() {
+        true => Some(X509::from_pem(&fs::read(&managed.cert_file)?).context("Failed to parse current certificate")?),
+        false => None
+    };
+    let current_key = match managed.key_file.exists() {
+        true => Some(PKey::private_key_from_pem(&fs::read(&managed.key_file)?).context("Failed to parse current key")?),
+        false => None
+    };
+
+    if let Some(cert) = &current_cert {
+        if cert.not_after() > Asn1Time::days_from_now(config.renew_before_days)? {
+            return Ok(false)
+        }
+    }
+
+    let new_key = generate_key(current_key.as_ref())?;
+    let mut payload = RenewCertificateRequest {
+        csr: Some(create_csr(&new_key)?),
+        ..Default::default()
+    };
+    if config.api_token.is_none() {
+        let (Some(cert), Some(key)) = (&current_cert, &current_key) else {
+            bail!("Without an API token, the current certificate and key are required to authenticate")
+        };
+        payload.certificate = Some(String::from_utf8(cert.to_pem()?)?);
+        payload.timestamp = Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64);
+        let mut signer = Signer::new(MessageDigest::sha256(), key)?;
+        signer.update(&payload.signed_data(managed.id))?;
+        payload.signature = Some(base64::encode_block(&signer.sign_to_vec()?));
+    }
+
+    let url = format!("{}/api/certificates/{}/renew", config.url.trim_end_matches('/'), managed.id);
+    let mut request = client.post(url)
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_vec(&payload)?);
+    if let Some(api_token) = &config.api_token {
+        request = request.bearer_auth(api_token);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        bail!("VaulTLS responded with {}: {}", status, body)
+    }
+    let issued: IssuedCertificateResponse = serde_json::from_str(&body).context("Failed to parse response")?;
+
+    let issued_cert = X509::from_pem(issued.certificate.as_bytes())?;
+    if !issued_cert.public_key()?.public_eq(&new_key) {
+        bail!("Issued certificate does not match the new key")
+    }
+
+    let mut files = vec![
+        (managed.key_file.as_path(), new_key.private_key_to_pem_pkcs8()?, 0o600),
+        (managed.cert_file.as_path(), issued.certificate.into_bytes(), 0o644),
+    ];
+    if let Some(chain_file) = &managed.chain_file {
+        files.push((chain_file.as_path(), issued.chain.into_bytes(), 0o644));
+    }
+    replace_files(&files)?;
+
+    if let Some(reload_command) = &managed.reload_command {
+        let status = Command::new("sh").arg("-c").arg(reload_command).status()
+            .context("Failed to run reload command")?;
+        if !status.success() {
+            bail!("Reload command failed with {}", status)
+        }
+    }
+    Ok(true)
+}
+
+/// Generates a key of the same type and size as the current key, or an ECDSA P-256 key.
+fn generate_key(current_key: Option<&PKey<Private>>) -> anyhow::Result<PKey<Private>> {
+    match current_key {
+        Some(key) if key.id() == Id::RSA => Ok(PKey::from_rsa(Rsa::generate(key.bits())?)?),
+        Some(key) if key.id() == Id::EC => {
+            let group = key.ec_key()?.group().curve_name()
+                .map(EcGroup::from_curve_name)
+                .transpose()?
+                .ok_or(anyhow!("Unsupported curve of the current key"))?;
+            Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+        }
+        _ => {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+            Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+        }
+    }
+}
+
+/// Creates a CSR for the key. VaulTLS takes subject and SANs from the existing certificate.
+fn create_csr(key: &PKey<Private>) -> anyhow::Result<String> {
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", "vaultls-agent")?;
+    let mut builder = X509ReqBuilder::new()?;
+    builder.set_subject_name(&name.build())?;
+    builder.set_pubkey(key)?;
+    builder.sign(key, MessageDigest::sha256())?;
+    Ok(String::from_utf8(builder.build().to_pem()?)?)
+}
+
+/// Replaces files with the given content and mode. All files are written to temporary files first
+/// and only renamed once every write succeeded, so a failed write leaves the current files untouched.
+fn replace_files(files: &[(&Path, Vec<u8>, u32)]) -> anyhow::Result<()> {
+    let mut staged = Vec::new();
+    for (path, content, mode) in files {
+        match stage_file(path, content, *mode) {
+            Ok(temp_path) => staged.push((temp_path, *path)),
+            Err(e) => {
+                for (temp_path, _) in &staged {
+                    let _ = fs::remove_file(temp_path);
+                }
+                return Err(e)
+            }
+        }
+    }
+    for (temp_path, path) in staged {
+        fs::rename(&temp_path, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Writes the content to a temporary file with the given mode next to the path and returns its path.
+fn stage_file(path: &Path, content: &[u8], mode: u32) -> anyhow::Result<PathBuf> {
+    let file_name = path.file_name().ok_or(anyhow!("Invalid path {}", path.display()))?;
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(&temp_path)
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    // The mode of open only applies to new files
+    file.set_permissions(fs::Permissions::from_mode(mode))?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    Ok(temp_path)
+}
@@ -1,23 +1,38 @@
 use std::fs;
 use std::time::{SystemTime, UNIX_EPOCH};
+use der::asn1::{Ia5String, OctetString, Utf8StringRef};
+use der::oid::{AssociatedOid, ObjectIdentifier};
+use der::oid::db::rfc5280::{ID_AD_CA_ISSUERS, ID_AD_OCSP};
+use der::{Decode, Encode};
 
-use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::asn1::{Asn1Integer, Asn1Object, Asn1OctetString, Asn1Time, Asn1TimeRef};
 use openssl::bn::BigNum;
 use openssl::ec::{EcGroup, EcKey};
 use openssl::error::ErrorStack;
-use openssl::hash::MessageDigest;
+use openssl::hash::{hash, MessageDigest};
 use openssl::nid::Nid;
 use openssl::pkcs12::Pkcs12;
-use openssl::pkey::{PKey, Private};
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
+use openssl::rsa::Rsa;
 use openssl::stack::Stack;
-use openssl::x509::{X509Name, X509NameBuilder, X509};
-use openssl::x509::extension::{AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName, SubjectKeyIdentifier};
+use openssl::x509::{X509CrlBuilder, X509Extension, X509Name, X509NameBuilder, X509Ref, X509Req, X509RevokedBuilder, X509VerifyResult, X509};
+use openssl::x509::extension::{AuthorityKeyIdentifier, BasicConstraints, CrlNumber, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName, SubjectKeyIdentifier};
 use openssl::x509::X509Builder;
 use passwords::PasswordGenerator;
+use x509_cert::ext::pkix::{AccessDescription, AuthorityInfoAccessSyntax, CrlDistributionPoints, SubjectAltName};
+use x509_cert::ext::pkix::crl::dp::DistributionPoint;
+use x509_cert::ext::pkix::name::{DistributionPointName, GeneralName};
+
 use crate::ApiError;
-use crate::constants::CA_FILE_PATH;
-use crate::data::enums::CertificateType;
-use crate::data::enums::CertificateType::{Client, Server, CA};
+use crate::data::api::{CreateUserCertificateRequest, IssuedCertificateResponse};
+use crate::constants::{CA_FILE_PATH, CRL_VALIDITY_SECS};
+use crate::data::enums::{CaPurpose, CertificateProfile, CertificateType, KeyAlgorithm, RevocationReason};
+use crate::data::enums::CertificateType::{Client, Server, Svid, Timestamping, CA};
+
+/// Extended key usage for 802.1X EAP authentication (RFC 4334)
+const OID_KP_EAP_OVER_LAN: &str = "1.3.6.1.5.5.7.3.14";
+/// Microsoft user principal name otherName, used by supplicants and RADIUS servers to map a certificate to an account
+const OID_MS_UPN: &str = "1.3.6.1.4.1.311.20.2.3";
 
 #[derive(Default, Clone, rocket::serde::Serialize)]
 /// Certificate can be either CA or user certificate.
@@ -27,9 +42,16 @@ pub(crate) struct Certificate {
     pub(crate) id: i64,
     pub(crate) name: String,
     pub(crate) created_on: i64,
+    /// notBefore, which can lie after created_on for scheduled certificates
+    pub(crate) valid_from: i64,
     pub(crate) valid_until: i64,
     pub(crate) certificate_type: CertificateType,
     pub(crate) user_id: i64,
+    pub(crate) serial: String,
+    pub(crate) revoked_on: Option<i64>,
+    pub(crate) revocation_reason: Option<RevocationReason>,
+    /// Id of the certificate that replaced this one when it was re-keyed or renewed
+    pub(crate) superseded_by: Option<i64>,
     #[serde(skip)]
     pub(crate) pkcs12: Vec<u8>,
     #[serde(skip)]
@@ -42,6 +64,38 @@ pub(crate) struct Certificate {
     pub(crate) ca_id: i64,
 }
 
+/// Metadata of a short-lived certificate. The certificate itself is not stored.
+#[derive(Default, Clone, rocket::serde::Serialize)]
+pub(crate) struct ShortLivedCertificate {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    pub(crate) created_on: i64,
+    pub(crate) valid_until: i64,
+    pub(crate) serial: String,
+    pub(crate) user_id: i64,
+    #[serde(skip)]
+    pub(crate) ca_id: i64,
+}
+
+/// Metadata of a CA. Several CAs can be active at once, each issuing the certificates of its purpose.
+#[derive(Default, Clone, rocket::serde::Serialize)]
+pub(crate) struct CertificateAuthority {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    pub(crate) created_on: i64,
+    pub(crate) valid_until: i64,
+    pub(crate) purpose: CaPurpose,
+    /// Inactive CAs do not issue certificates, but their CRLs are still published
+    pub(crate) active: bool,
+}
+
+/// Key of a short-lived certificate: either the public key of a CSR or a generated key.
+pub(crate) enum ShortLivedKey {
+    Csr(X509Req),
+    /// Key generated by VaulTLS, returned with the certificate
+    Generated(PKey<Private>),
+}
+
 /// Creates a new CA certificate.
 pub(crate) fn create_ca(
     ca_name: &str,
@@ -54,9 +108,10 @@ pub(crate) fn create_ca(
     let (created_on_unix, created_on_openssl) = get_timestamp(0)?;
     let (valid_until_unix, valid_until_openssl) = get_timestamp(ca_validity_in_years)?;
 
-    let basic_constraints = BasicConstraints::new().ca().build()?;
+    let basic_constraints = BasicConstraints::new().critical().ca().build()?;
 
     let key_usage = KeyUsage::new()
+        .critical()
         .key_cert_sign()
         .crl_sign()
         .build()?;
@@ -82,7 +137,9 @@ pub(crate) fn create_ca(
     let certificate = ca_builder.build();
     
     Ok(Certificate{
+        name: ca_name.to_string(),
         created_on: created_on_unix,
+        valid_from: created_on_unix,
         valid_until: valid_until_unix,
         certificate_type: CA,
         cert: certificate.to_der()?,
@@ -91,35 +148,121 @@ pub(crate) fn create_ca(
     })
 }
 
+/// Options for the key, validity and PKCS#12 of a newly issued leaf certificate.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct IssuanceOptions {
+    pub(crate) validity_in_years: u64,
+    pub(crate) key_algorithm: KeyAlgorithm,
+    pub(crate) system_generated_password: bool,
+    pub(crate) pkcs12_password: Option<String>,
+    /// Base URL for the CRL distribution point and authority information access extensions
+    pub(crate) pki_url: Option<String>,
+    /// Key taken from the key pool before the database was locked, the key is generated directly if None
+    pub(crate) private_key: Option<PKey<Private>>,
+    pub(crate) profile: CertificateProfile,
+    /// User principal name SAN of EAP-TLS client certificates
+    pub(crate) upn: Option<String>,
+    /// notBefore as UNIX timestamp in milliseconds, defaults to now.
+    /// The validity in years is counted from it.
+    pub(crate) not_before: Option<i64>,
+    /// Exact validity in milliseconds, which replaces the validity in years if set.
+    pub(crate) lifetime: Option<i64>,
+    /// Public key of a CSR for client and server certificates.
+    /// No key is generated then, and the PKCS#12 does not contain the private key.
+    pub(crate) public_key: Option<PKey<Public>>,
+}
+
+impl IssuanceOptions {
+    /// Returns a new private key with the key algorithm of the options.
+    fn new_key(&self) -> Result<PKey<Private>, ErrorStack> {
+        match &self.private_key {
+            Some(private_key) => Ok(private_key.clone()),
+            None => generate_key(self.key_algorithm)
+        }
+    }
+
+    /// Returns the public key of a new certificate and its private key, which is None for a CSR.
+    fn new_key_pair(&self) -> Result<(PKey<Public>, Option<PKey<Private>>), ApiError> {
+        match &self.public_key {
+            Some(public_key) => Ok((public_key.clone(), None)),
+            None => {
+                let private_key = self.new_key()?;
+                let public_key = PKey::public_key_from_der(&private_key.public_key_to_der()?)?;
+                Ok((public_key, Some(private_key)))
+            }
+        }
+    }
+}
+
 /// Creates a new user certificate.
 pub(crate) fn create_user_cert(
     ca: &Certificate,
     name: &str,
-    validity_in_years: u64,
     user_id: i64,
     user_email: &str,
-    system_generated_password: bool,
-    pkcs12_password: &Option<String>
-) -> Result<Certificate, ErrorStack> {
+    options: &IssuanceOptions
+) -> Result<Certificate, ApiError> {
+    let ca_cert = X509::from_der(&ca.cert)?;
+    let (public_key, user_key) = options.new_key_pair()?;
+
+    let (created_on_unix, (valid_from_unix, not_before), (valid_until_unix, not_after)) = get_validity(options)?;
+
+    let user_cert = sign_client_cert(ca, name, user_email, &public_key, &not_before, &not_after, options)?;
+
+    let mut ca_stack = Stack::new()?;
+    ca_stack.push(ca_cert.clone())?;
+    
+    let password = get_password(options.system_generated_password, &options.pkcs12_password);
+
+    // Create the PKCS#12 structure
+    let mut pkcs12 = Pkcs12::builder();
+    pkcs12.name(name).ca(ca_stack).cert(&user_cert);
+    if let Some(user_key) = &user_key {
+        pkcs12.pkey(user_key);
+    }
+    let pkcs12 = pkcs12.build2(&password)?;
+
+    Ok(Certificate{
+        name: name.to_string(),
+        created_on: created_on_unix,
+        valid_from: valid_from_unix,
+        valid_until: valid_until_unix,
+        certificate_type: Client,
+        serial: get_serial_hex(&user_cert)?,
+        pkcs12: pkcs12.to_der()?,
+        pkcs12_password: password.to_string(),
+        ca_id: ca.id,
+        user_id,
+        ..Default::default()
+    })
+}
+
+/// Signs a client certificate for the public key with the user's e-mail address as SAN.
+/// EAP-TLS client certificates additionally carry the UPN of the options as otherName SAN.
+fn sign_client_cert<T: HasPublic>(
+    ca: &Certificate,
+    name: &str,
+    user_email: &str,
+    public_key: &PKeyRef<T>,
+    not_before: &Asn1TimeRef,
+    not_after: &Asn1TimeRef,
+    options: &IssuanceOptions
+) -> Result<X509, ApiError> {
     let ca_cert = X509::from_der(&ca.cert)?;
     let ca_key = PKey::private_key_from_der(&ca.key)?;
 
-    let user_key = generate_private_key()?;
     let subject_name = create_cn(name)?;
 
-    let key_usage = KeyUsage::new()
-        .digital_signature()
-        .key_encipherment()
-        .build()?;
+    let key_usage = create_tls_key_usage(public_key)?;
 
-    let ext_key_usage = ExtendedKeyUsage::new()
-        .client_auth()
-        .build()?;
-
-    let basic_constraints = BasicConstraints::new().build()?;
+    let mut ext_key_usage = ExtendedKeyUsage::new();
+    ext_key_usage.client_auth();
+    if options.profile == CertificateProfile::EapTls {
+        ext_key_usage.other(OID_KP_EAP_OVER_LAN);
+    }
+    let ext_key_usage = ext_key_usage.build()?;
 
-    let (created_on_unix, created_on_openssl) = get_timestamp(0)?;
-    let (valid_until_unix, valid_until_openssl) = get_timestamp(validity_in_years)?;
+    let basic_constraints = BasicConstraints::new().critical().build()?;
 
     let serial = generate_serial_number()?;
 
@@ -128,40 +271,229 @@ pub(crate) fn create_user_cert(
     user_cert_builder.set_subject_name(&subject_name)?;
     user_cert_builder.set_issuer_name(ca_cert.subject_name())?;
     user_cert_builder.set_serial_number(&serial)?;
-    user_cert_builder.set_pubkey(&user_key)?;
-    user_cert_builder.set_not_before(created_on_openssl.as_ref())?;
-    user_cert_builder.set_not_after(valid_until_openssl.as_ref())?;
+    user_cert_builder.set_pubkey(public_key)?;
+    user_cert_builder.set_not_before(not_before)?;
+    user_cert_builder.set_not_after(not_after)?;
     user_cert_builder.append_extension(key_usage)?;
     user_cert_builder.append_extension(ext_key_usage)?;
     user_cert_builder.append_extension(basic_constraints)?;
+    if let Some(pki_url) = &options.pki_url {
+        for extension in create_pki_url_extensions(pki_url, ca.id)? {
+            user_cert_builder.append_extension(extension)?;
+        }
+    }
 
-    let san = SubjectAlternativeName::new()
-        .email(user_email)
-        .build(&user_cert_builder.x509v3_context(None, None))?;
+    let mut san_builder = SubjectAlternativeName::new();
+    san_builder.email(user_email);
+    if let (CertificateProfile::EapTls, Some(upn)) = (options.profile, &options.upn) {
+        let upn = Utf8StringRef::new(upn)?.to_der()?;
+        san_builder.other_name2(Asn1Object::from_str(OID_MS_UPN)?, &upn);
+    }
+    let san = san_builder.build(&user_cert_builder.x509v3_context(None, None))?;
     user_cert_builder.append_extension(san)?;
-    
+    append_key_identifiers(&mut user_cert_builder, &ca_cert)?;
+
     user_cert_builder.sign(&ca_key, MessageDigest::sha256())?;
 
-    let user_cert = user_cert_builder.build();
+    Ok(user_cert_builder.build())
+}
+
+/// Creates a short-lived client certificate for a CSR or a newly generated key.
+/// Short-lived certificates are not revocable, so they do not point to the CRL or OCSP responder.
+/// Returns the certificate metadata, the certificate and the generated private key, if any.
+pub(crate) fn create_short_lived_cert(
+    ca: &Certificate,
+    name: &str,
+    user_id: i64,
+    user_email: &str,
+    validity_in_hours: u64,
+    key: ShortLivedKey
+) -> Result<(ShortLivedCertificate, X509, Option<PKey<Private>>), ApiError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let valid_until = now + std::time::Duration::from_secs(60 * 60 * validity_in_hours);
+    let not_before = Asn1Time::from_unix(now.as_secs() as i64)?;
+    let not_after = Asn1Time::from_unix(valid_until.as_secs() as i64)?;
+
+    let (cert, private_key) = match key {
+        ShortLivedKey::Csr(csr) => {
+            let public_key = csr.public_key()?;
+            if !csr.verify(&public_key)? {
+                return Err(ApiError::BadRequest("CSR signature is invalid".to_string()))
+            }
+            (sign_client_cert(ca, name, user_email, &public_key, &not_before, &not_after, &IssuanceOptions::default())?, None)
+        }
+        ShortLivedKey::Generated(private_key) => {
+            (sign_client_cert(ca, name, user_email, &private_key, &not_before, &not_after, &IssuanceOptions::default())?, Some(private_key))
+        }
+    };
+
+    let metadata = ShortLivedCertificate {
+        name: name.to_string(),
+        created_on: now.as_millis() as i64,
+        valid_until: valid_until.as_millis() as i64,
+        serial: get_serial_hex(&cert)?,
+        user_id,
+        ca_id: ca.id,
+        ..Default::default()
+    };
+    Ok((metadata, cert, private_key))
+}
+
+/// Creates a new server certificate.
+pub(crate) fn create_server_cert(
+    ca: &Certificate,
+    common_name: &str,
+    dns_names: &Vec<String>,
+    user_id: i64,
+    options: &IssuanceOptions
+) -> Result<Certificate, ApiError> {
+    let ca_cert = X509::from_der(&ca.cert)?;
+    let ca_key = PKey::private_key_from_der(&ca.key)?;
+
+    let (public_key, server_key) = options.new_key_pair()?;
+    let subject_name = create_cn(common_name)?;
+    let serial = generate_serial_number()?;
+    
+    let (created_on_unix, (valid_from_unix, not_before), (valid_until_unix, not_after)) = get_validity(options)?;
+
+    let key_usage = create_tls_key_usage(&public_key)?;
+
+    let mut ext_key_usage = ExtendedKeyUsage::new();
+    ext_key_usage.server_auth();
+    if options.profile == CertificateProfile::EapTls {
+        ext_key_usage.other(OID_KP_EAP_OVER_LAN);
+    }
+    let ext_key_usage = ext_key_usage.build()?;
+
+    let basic_constraints = BasicConstraints::new().critical().build()?;
+
+    let mut cert_builder = X509Builder::new()?;
+    cert_builder.set_version(2)?;
+    cert_builder.set_subject_name(&subject_name)?;
+    cert_builder.set_issuer_name(ca_cert.subject_name())?;
+    cert_builder.set_serial_number(&serial)?;
+    cert_builder.set_pubkey(&public_key)?;
+    cert_builder.set_not_before(not_before.as_ref())?;
+    cert_builder.set_not_after(not_after.as_ref())?;
+    cert_builder.append_extension(basic_constraints)?;
+    cert_builder.append_extension(key_usage)?;
+    cert_builder.append_extension(ext_key_usage)?;
+    if let Some(pki_url) = &options.pki_url {
+        for extension in create_pki_url_extensions(pki_url, ca.id)? {
+            cert_builder.append_extension(extension)?;
+        }
+    }
+
+    let mut san_builder = SubjectAlternativeName::new();
+    for dns in dns_names {
+        san_builder.dns(dns);
+    }
+    let san = san_builder.build(&cert_builder.x509v3_context(Some(&ca_cert), None))?;
+    cert_builder.append_extension(san)?;
+
+    append_key_identifiers(&mut cert_builder, &ca_cert)?;
+
+    cert_builder.sign(&ca_key, MessageDigest::sha256())?;
+    let server_cert = cert_builder.build();
 
     let mut ca_stack = Stack::new()?;
     ca_stack.push(ca_cert.clone())?;
-    
-    let password = get_password(system_generated_password, pkcs12_password);
+    let password = get_password(options.system_generated_password, &options.pkcs12_password);
+    let mut pkcs12 = Pkcs12::builder();
+    pkcs12.name(common_name).ca(ca_stack).cert(&server_cert);
+    if let Some(server_key) = &server_key {
+        pkcs12.pkey(server_key);
+    }
+    let pkcs12 = pkcs12.build2(password.as_ref())?;
 
-    // Create the PKCS#12 structure
+    Ok(Certificate {
+        name: common_name.to_string(),
+        created_on: created_on_unix,
+        valid_from: valid_from_unix,
+        valid_until: valid_until_unix,
+        certificate_type: Server,
+        serial: get_serial_hex(&server_cert)?,
+        pkcs12: pkcs12.to_der()?,
+        pkcs12_password: password.to_string(),
+        ca_id: ca.id,
+        user_id,
+        ..Default::default()
+    })
+}
+
+/// Creates a new X.509-SVID for a workload.
+/// The subject is empty and the SPIFFE ID is the only SAN, as required by the X.509-SVID specification.
+pub(crate) fn create_svid(
+    ca: &Certificate,
+    name: &str,
+    spiffe_id: &str,
+    user_id: i64,
+    options: &IssuanceOptions
+) -> Result<Certificate, ApiError> {
+    let ca_cert = X509::from_der(&ca.cert)?;
+    let ca_key = PKey::private_key_from_der(&ca.key)?;
+
+    let svid_key = options.new_key()?;
+    let subject_name = X509NameBuilder::new()?.build();
+    let serial = generate_serial_number()?;
+
+    let (created_on_unix, (valid_from_unix, not_before), (valid_until_unix, not_after)) = get_validity(options)?;
+
+    let key_usage = create_tls_key_usage(&svid_key)?;
+
+    let ext_key_usage = ExtendedKeyUsage::new()
+        .server_auth()
+        .client_auth()
+        .build()?;
+
+    let basic_constraints = BasicConstraints::new().critical().build()?;
+
+    let mut cert_builder = X509Builder::new()?;
+    cert_builder.set_version(2)?;
+    cert_builder.set_subject_name(&subject_name)?;
+    cert_builder.set_issuer_name(ca_cert.subject_name())?;
+    cert_builder.set_serial_number(&serial)?;
+    cert_builder.set_pubkey(&svid_key)?;
+    cert_builder.set_not_before(not_before.as_ref())?;
+    cert_builder.set_not_after(not_after.as_ref())?;
+    cert_builder.append_extension(basic_constraints)?;
+    cert_builder.append_extension(key_usage)?;
+    cert_builder.append_extension(ext_key_usage)?;
+    if let Some(pki_url) = &options.pki_url {
+        for extension in create_pki_url_extensions(pki_url, ca.id)? {
+            cert_builder.append_extension(extension)?;
+        }
+    }
+
+    // The SAN must be critical since the subject is empty
+    let san = SubjectAlternativeName::new()
+        .critical()
+        .uri(spiffe_id)
+        .build(&cert_builder.x509v3_context(Some(&ca_cert), None))?;
+    cert_builder.append_extension(san)?;
+
+    append_key_identifiers(&mut cert_builder, &ca_cert)?;
+
+    cert_builder.sign(&ca_key, MessageDigest::sha256())?;
+    let svid = cert_builder.build();
+
+    let mut ca_stack = Stack::new()?;
+    ca_stack.push(ca_cert.clone())?;
+    let password = get_password(options.system_generated_password, &options.pkcs12_password);
     let pkcs12 = Pkcs12::builder()
-        .name(&name)
+        .name(name)
         .ca(ca_stack)
-        .cert(&user_cert)
-        .pkey(&user_key)
-        .build2(&password)?;
+        .cert(&svid)
+        .pkey(&svid_key)
+        .build2(password.as_ref())?;
 
-    Ok(Certificate{
+    Ok(Certificate {
         name: name.to_string(),
         created_on: created_on_unix,
+        valid_from: valid_from_unix,
         valid_until: valid_until_unix,
-        certificate_type: Client,
+        certificate_type: Svid,
+        serial: get_serial_hex(&svid)?,
         pkcs12: pkcs12.to_der()?,
         pkcs12_password: password.to_string(),
         ca_id: ca.id,
@@ -170,33 +502,32 @@ pub(crate) fn create_user_cert(
     })
 }
 
-/// Creates a new server certificate.
-pub(crate) fn create_server_cert(
+/// Creates a new timestamping certificate for the RFC 3161 timestamping authority.
+/// As required by RFC 3161, timeStamping is the only extended key usage and the extension is critical.
+pub(crate) fn create_timestamping_cert(
     ca: &Certificate,
-    common_name: &str,
-    dns_names: &Vec<String>,
-    validity_in_years: u64,
-    system_generated_password: bool,
-    pkcs12_password: &Option<String>,
-    user_id: i64
-) -> Result<Certificate, ErrorStack> {
+    name: &str,
+    user_id: i64,
+    options: &IssuanceOptions
+) -> Result<Certificate, ApiError> {
     let ca_cert = X509::from_der(&ca.cert)?;
     let ca_key = PKey::private_key_from_der(&ca.key)?;
 
-    let server_key = generate_private_key()?;
-    let subject_name = create_cn(common_name)?;
+    let tsa_key = options.new_key()?;
+    let subject_name = create_cn(name)?;
     let serial = generate_serial_number()?;
-    
-    let (created_on_unix, not_before) = get_timestamp(0)?;
-    let (valid_until_unix, not_after) = get_timestamp(validity_in_years)?;
+
+    let (created_on_unix, (valid_from_unix, not_before), (valid_until_unix, not_after)) = get_validity(options)?;
 
     let key_usage = KeyUsage::new()
+        .critical()
         .digital_signature()
-        .key_encipherment()
+        .non_repudiation()
         .build()?;
 
     let ext_key_usage = ExtendedKeyUsage::new()
-        .server_auth()
+        .critical()
+        .time_stamping()
         .build()?;
 
     let basic_constraints = BasicConstraints::new().critical().build()?;
@@ -206,38 +537,40 @@ pub(crate) fn create_server_cert(
     cert_builder.set_subject_name(&subject_name)?;
     cert_builder.set_issuer_name(ca_cert.subject_name())?;
     cert_builder.set_serial_number(&serial)?;
-    cert_builder.set_pubkey(&server_key)?;
+    cert_builder.set_pubkey(&tsa_key)?;
     cert_builder.set_not_before(not_before.as_ref())?;
     cert_builder.set_not_after(not_after.as_ref())?;
     cert_builder.append_extension(basic_constraints)?;
     cert_builder.append_extension(key_usage)?;
     cert_builder.append_extension(ext_key_usage)?;
-
-    let mut san_builder = SubjectAlternativeName::new();
-    for dns in dns_names {
-        san_builder.dns(dns);
+    if let Some(pki_url) = &options.pki_url {
+        for extension in create_pki_url_extensions(pki_url, ca.id)? {
+            cert_builder.append_extension(extension)?;
+        }
     }
-    let san = san_builder.build(&cert_builder.x509v3_context(Some(&ca_cert), None))?;
-    cert_builder.append_extension(san)?;
+
+    append_key_identifiers(&mut cert_builder, &ca_cert)?;
 
     cert_builder.sign(&ca_key, MessageDigest::sha256())?;
-    let server_cert = cert_builder.build();
+    let tsa_cert = cert_builder.build();
 
     let mut ca_stack = Stack::new()?;
     ca_stack.push(ca_cert.clone())?;
-    let password = get_password(system_generated_password, pkcs12_password);
+    let password = get_password(options.system_generated_password, &options.pkcs12_password);
     let pkcs12 = Pkcs12::builder()
-        .name(common_name)
+        .name(name)
         .ca(ca_stack)
-        .cert(&server_cert)
-        .pkey(&server_key)
+        .cert(&tsa_cert)
+        .pkey(&tsa_key)
         .build2(password.as_ref())?;
 
     Ok(Certificate {
-        name: common_name.to_string(),
+        name: name.to_string(),
         created_on: created_on_unix,
+        valid_from: valid_from_unix,
         valid_until: valid_until_unix,
-        certificate_type: Server,
+        certificate_type: Timestamping,
+        serial: get_serial_hex(&tsa_cert)?,
         pkcs12: pkcs12.to_der()?,
         pkcs12_password: password.to_string(),
         ca_id: ca.id,
@@ -246,6 +579,208 @@ pub(crate) fn create_server_cert(
     })
 }
 
+/// Describes a certificate with the identity of an existing certificate, so that it can be re-keyed or renewed.
+/// Subject, SANs, type, profile and key algorithm are taken from the existing certificate.
+/// The validity in years is rounded up for the issuance policies, the exact lifetime is set by lifetime().
+pub(crate) fn rekey_request(cert: &Certificate) -> Result<CreateUserCertificateRequest, ApiError> {
+    let leaf = get_pkcs12_certificate(&cert.pkcs12, &cert.pkcs12_password)?;
+    let year_in_ms = 1000 * 60 * 60 * 24 * 365;
+    let lifetime = lifetime(cert)?;
+    let (profile, upn) = get_profile(&leaf)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+
+    let subject_alt_names = leaf.subject_alt_names();
+    let dns_names: Vec<String> = subject_alt_names.iter().flatten()
+        .filter_map(|name| name.dnsname().map(str::to_string))
+        .collect();
+    let spiffe_path = match cert.certificate_type {
+        Svid => {
+            let spiffe_id = subject_alt_names.iter().flatten()
+                .find_map(|name| name.uri())
+                .ok_or(ApiError::Other("Certificate does not contain a SPIFFE ID".to_string()))?;
+            spiffe_id.strip_prefix("spiffe://")
+                .and_then(|id| id.find('/').map(|index| id[index..].to_string()))
+        }
+        CA => return Err(ApiError::BadRequest("Cannot re-key CA certificate".to_string())),
+        _ => None
+    };
+
+    Ok(CreateUserCertificateRequest {
+        cert_name: cert.name.clone(),
+        validity_in_years: Some(((lifetime + year_in_ms - 1) / year_in_ms) as u64),
+        user_id: cert.user_id,
+        notify_user: None,
+        system_generated_password: true,
+        pkcs12_password: None,
+        cert_type: Some(cert.certificate_type),
+        dns_names: Some(dns_names),
+        key_algorithm: Some(get_key_algorithm(&leaf.public_key()?)?),
+        spiffe_path,
+        profile: Some(profile),
+        upn,
+        csr: None,
+        ca_id: Some(cert.ca_id),
+        // Scheduled certificates stay scheduled
+        not_before: (cert.valid_from > now).then_some(cert.valid_from),
+    })
+}
+
+/// Returns the lifetime of a certificate in milliseconds, which re-keyed and renewed certificates keep.
+pub(crate) fn lifetime(cert: &Certificate) -> Result<i64, ApiError> {
+    match cert.valid_until - cert.valid_from {
+        lifetime if lifetime > 0 => Ok(lifetime),
+        _ => Err(ApiError::BadRequest("Certificate has no validity period to keep".to_string()))
+    }
+}
+
+/// Reads the profile and the UPN of an issued certificate, so that re-keying keeps them.
+fn get_profile(leaf: &X509) -> Result<(CertificateProfile, Option<String>), ApiError> {
+    let der = leaf.to_der()?;
+    let parsed = x509_cert::Certificate::from_der(&der)?;
+    let eap_over_lan = ObjectIdentifier::new_unwrap(OID_KP_EAP_OVER_LAN);
+    let ms_upn = ObjectIdentifier::new_unwrap(OID_MS_UPN);
+
+    let mut profile = CertificateProfile::Default;
+    let mut upn = None;
+    for extension in parsed.tbs_certificate.extensions.iter().flatten() {
+        if extension.extn_id == x509_cert::ext::pkix::ExtendedKeyUsage::OID {
+            let ext_key_usage = x509_cert::ext::pkix::ExtendedKeyUsage::from_der(extension.extn_value.as_bytes())?;
+            if ext_key_usage.0.contains(&eap_over_lan) {
+                profile = CertificateProfile::EapTls;
+            }
+        } else if extension.extn_id == SubjectAltName::OID {
+            let san = SubjectAltName::from_der(extension.extn_value.as_bytes())?;
+            upn = san.0.iter().find_map(|name| match name {
+                GeneralName::OtherName(other_name) if other_name.type_id == ms_upn => {
+                    other_name.value.decode_as::<Utf8StringRef>().ok().map(|upn| upn.to_string())
+                }
+                _ => None
+            });
+        }
+    }
+    Ok((profile, upn))
+}
+
+/// Rebuilds the PKCS#12 of a certificate with a new password, keeping certificate, key and chain.
+/// Certificates issued for a CSR have no key in their PKCS#12, which then only contains certificate and chain.
+pub(crate) fn repackage_pkcs12(cert: &Certificate, password: &str) -> Result<Vec<u8>, ApiError> {
+    let parsed = Pkcs12::from_der(&cert.pkcs12)?.parse2(&cert.pkcs12_password)?;
+    let leaf = get_pkcs12_certificate(&cert.pkcs12, &cert.pkcs12_password)?;
+    let leaf_der = leaf.to_der()?;
+
+    // Without a key, OpenSSL returns the certificate as part of the chain
+    let mut ca_stack = Stack::new()?;
+    for ca_cert in parsed.ca.into_iter().flatten() {
+        if ca_cert.to_der()? != leaf_der {
+            ca_stack.push(ca_cert)?;
+        }
+    }
+
+    let mut builder = Pkcs12::builder();
+    builder.name(&cert.name).cert(&leaf).ca(ca_stack);
+    if let Some(key) = &parsed.pkey {
+        builder.pkey(key);
+    }
+    Ok(builder.build2(password)?.to_der()?)
+}
+
+/// Creates the CRL distribution point and authority information access (CA issuer and OCSP) extensions.
+fn create_pki_url_extensions(pki_url: &str, ca_id: i64) -> Result<Vec<X509Extension>, ApiError> {
+    let uri = |path: String| -> Result<GeneralName, ApiError> {
+        let url = Ia5String::new(&format!("{}{}", pki_url, path))
+            .map_err(|_| ApiError::BadRequest("PKI URL must only contain ASCII characters".to_string()))?;
+        Ok(GeneralName::UniformResourceIdentifier(url))
+    };
+
+    let crl_distribution_points = CrlDistributionPoints(vec![DistributionPoint {
+        distribution_point: Some(DistributionPointName::FullName(vec![uri(format!("/api/certificates/ca/{}/crl", ca_id))?])),
+        reasons: None,
+        crl_issuer: None,
+    }]);
+
+    let authority_info_access = AuthorityInfoAccessSyntax(vec![
+        AccessDescription { access_method: ID_AD_CA_ISSUERS, access_location: uri(format!("/api/certificates/ca/{}/der", ca_id))? },
+        AccessDescription { access_method: ID_AD_OCSP, access_location: uri("/api/ocsp".to_string())? },
+    ]);
+
+    Ok(vec![
+        create_der_extension(&crl_distribution_points, false)?,
+        create_der_extension(&authority_info_access, false)?,
+    ])
+}
+
+/// Converts an extension encoded with the der crate into an OpenSSL extension.
+fn create_der_extension<T: AssociatedOid + Encode>(extension: &T, critical: bool) -> Result<X509Extension, ApiError> {
+    let oid = Asn1Object::from_str(&T::OID.to_string())?;
+    let contents = Asn1OctetString::new_from_bytes(&extension.to_der()?)?;
+    Ok(X509Extension::new_from_der(&oid, critical, &contents)?)
+}
+
+/// Creates a CRL for the CA containing all given revoked serials with their revocation time.
+pub(crate) fn create_crl(ca: &Certificate, revoked: &[(String, i64)]) -> Result<Vec<u8>, ApiError> {
+    let ca_cert = X509::from_der(&ca.cert)?;
+    let ca_key = PKey::private_key_from_der(&ca.key)?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let key_identifier = match ca_cert.subject_key_id() {
+        Some(key_id) => key_id.as_slice().to_vec(),
+        None => hash(MessageDigest::sha1(), ca_cert.public_key()?.public_key_to_der()?.as_slice())?.to_vec()
+    };
+    let authority_key_identifier = x509_cert::ext::pkix::AuthorityKeyIdentifier {
+        key_identifier: Some(OctetString::new(key_identifier)?),
+        ..Default::default()
+    };
+
+    let last_update = Asn1Time::from_unix(now)?;
+    let next_update = Asn1Time::from_unix(now + CRL_VALIDITY_SECS)?;
+
+    let mut crl_builder = X509CrlBuilder::new()?;
+    crl_builder.set_issuer_name(ca_cert.subject_name())?;
+    crl_builder.set_last_update(&last_update)?;
+    crl_builder.set_next_update(&next_update)?;
+    crl_builder.append_extension(create_der_extension(&authority_key_identifier, false)?)?;
+    crl_builder.append_extension(CrlNumber::new(BigNum::from_u32(now as u32)?)?.build()?)?;
+
+    for (serial, revoked_on) in revoked {
+        let serial = BigNum::from_hex_str(serial)?.to_asn1_integer()?;
+        let revocation_date = Asn1Time::from_unix(revoked_on / 1000)?;
+        let mut revoked_builder = X509RevokedBuilder::new()?;
+        revoked_builder.set_serial_number(&serial)?;
+        revoked_builder.set_revocation_date(&revocation_date)?;
+        crl_builder.add_revoked(revoked_builder.build())?;
+    }
+    crl_builder.sort()?;
+    crl_builder.sign(&ca_key, MessageDigest::sha256())?;
+
+    Ok(crl_builder.build()?.to_der()?)
+}
+
+/// Generates a new private key for a leaf certificate.
+pub(crate) fn generate_key(key_algorithm: KeyAlgorithm) -> Result<PKey<Private>, ErrorStack> {
+    match key_algorithm {
+        KeyAlgorithm::EcdsaP256 => generate_private_key(),
+        KeyAlgorithm::EcdsaP384 => {
+            let group = EcGroup::from_curve_name(Nid::SECP384R1)?;
+            PKey::from_ec_key(EcKey::generate(&group)?)
+        }
+        KeyAlgorithm::Rsa2048 => PKey::from_rsa(Rsa::generate(2048)?),
+        KeyAlgorithm::Rsa4096 => PKey::from_rsa(Rsa::generate(4096)?),
+    }
+}
+
+/// Determines the key algorithm of an existing public key.
+pub(crate) fn get_key_algorithm(key: &PKey<Public>) -> Result<KeyAlgorithm, ApiError> {
+    match key.id() {
+        Id::EC => match key.ec_key()?.group().curve_name() {
+            Some(Nid::SECP384R1) => Ok(KeyAlgorithm::EcdsaP384),
+            _ => Ok(KeyAlgorithm::EcdsaP256)
+        },
+        Id::RSA if key.bits() >= 4096 => Ok(KeyAlgorithm::Rsa4096),
+        Id::RSA => Ok(KeyAlgorithm::Rsa2048),
+        _ => Err(ApiError::Other("Unsupported key type".to_string()))
+    }
+}
+
 /// Generates a new private key.
 fn generate_private_key() -> Result<PKey<Private>, ErrorStack> {
     let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
@@ -262,7 +797,7 @@ fn create_cn(ca_name: &str) -> Result<X509Name, ErrorStack> {
 }
 
 /// Returns the password for the PKCS#12.
-fn get_password(system_generated_password: bool, pkcs12_password: &Option<String>) -> String {
+pub(crate) fn get_password(system_generated_password: bool, pkcs12_password: &Option<String>) -> String {
     if system_generated_password {
         // Create password for the PKCS#12
         let pg = PasswordGenerator {
@@ -284,14 +819,100 @@ fn get_password(system_generated_password: bool, pkcs12_password: &Option<String
     }
 }
 
-/// Generates a random serial number.
+/// Generates a random positive serial number of 16 bytes.
 fn generate_serial_number() -> Result<Asn1Integer, ErrorStack> {
     let mut big_serial = BigNum::new()?;
-    big_serial.rand(64, openssl::bn::MsbOption::MAYBE_ZERO, false)?;
+    big_serial.rand(127, openssl::bn::MsbOption::ONE, false)?;
     let asn1_serial = big_serial.to_asn1_integer()?;
     Ok(asn1_serial)
 }
 
+/// Creates the key usage of a TLS certificate. keyEncipherment is only valid for RSA keys (RFC 5480).
+fn create_tls_key_usage<T: HasPublic>(key: &PKeyRef<T>) -> Result<X509Extension, ErrorStack> {
+    let mut key_usage = KeyUsage::new();
+    key_usage.critical().digital_signature();
+    if key.id() == Id::RSA {
+        key_usage.key_encipherment();
+    }
+    key_usage.build()
+}
+
+/// Appends the subject and authority key identifier extensions of a certificate issued by the CA.
+fn append_key_identifiers(builder: &mut X509Builder, ca_cert: &X509Ref) -> Result<(), ErrorStack> {
+    let subject_key_identifier = SubjectKeyIdentifier::new().build(&builder.x509v3_context(Some(ca_cert), None))?;
+    builder.append_extension(subject_key_identifier)?;
+    let authority_key_identifier = AuthorityKeyIdentifier::new().keyid(false).build(&builder.x509v3_context(Some(ca_cert), None))?;
+    builder.append_extension(authority_key_identifier)?;
+    Ok(())
+}
+
+/// Returns the serial number of a certificate as uppercase hex string.
+pub(crate) fn get_serial_hex(cert: &X509Ref) -> Result<String, ErrorStack> {
+    Ok(cert.serial_number().to_bn()?.to_hex_str()?.to_string())
+}
+
+/// Returns the first common name of the subject of a certificate.
+pub(crate) fn get_common_name(cert: &X509Ref) -> Option<String> {
+    cert.subject_name().entries_by_nid(Nid::COMMONNAME).next()
+        .and_then(|entry| entry.data().to_string().ok())
+}
+
+/// Extracts the leaf certificate from a PKCS#12 bundle.
+pub(crate) fn get_pkcs12_certificate(pkcs12: &[u8], password: &str) -> Result<X509, ApiError> {
+    let parsed = Pkcs12::from_der(pkcs12)?.parse2(password)?;
+    if let Some(cert) = parsed.cert {
+        return Ok(cert)
+    }
+
+    // Without a key, e.g. for certificates issued for a CSR, OpenSSL returns the certificate
+    // together with the chain. It is the only one that did not issue another certificate.
+    let certs: Vec<X509> = parsed.ca.map(|ca| ca.into_iter().collect()).unwrap_or_default();
+    certs.iter()
+        .enumerate()
+        .find(|(index, cert)| !certs.iter().enumerate()
+            .any(|(other_index, other)| other_index != *index && cert.issued(other) == X509VerifyResult::OK))
+        .map(|(_, cert)| cert.clone())
+        .ok_or(ApiError::Other("PKCS#12 does not contain a certificate".to_string()))
+}
+
+/// Returns an issued certificate and its CA in PEM, with the private key unless it was issued for a CSR.
+pub(crate) fn get_pem_bundle(cert: &Certificate, ca: &Certificate) -> Result<IssuedCertificateResponse, ApiError> {
+    let parsed = Pkcs12::from_der(&cert.pkcs12)?.parse2(&cert.pkcs12_password)?;
+    let leaf = get_pkcs12_certificate(&cert.pkcs12, &cert.pkcs12_password)?;
+    let private_key = match parsed.pkey {
+        Some(key) => Some(String::from_utf8_lossy(&key.private_key_to_pem_pkcs8()?).into_owned()),
+        None => None
+    };
+
+    Ok(IssuedCertificateResponse {
+        id: cert.id,
+        name: cert.name.clone(),
+        serial: cert.serial.clone(),
+        valid_until: cert.valid_until,
+        certificate: String::from_utf8_lossy(&leaf.to_pem()?).into_owned(),
+        chain: String::from_utf8_lossy(&get_pem(ca)?).into_owned(),
+        private_key,
+    })
+}
+
+/// Parses a PEM encoded CSR and returns its public key after checking the CSR signature.
+pub(crate) fn get_csr_public_key(csr: &str) -> Result<PKey<Public>, ApiError> {
+    let csr = X509Req::from_pem(csr.as_bytes())
+        .map_err(|_| ApiError::BadRequest("CSR is not a valid PEM certificate request".to_string()))?;
+    let public_key = csr.public_key()?;
+    if !csr.verify(&public_key)? {
+        return Err(ApiError::BadRequest("CSR signature is invalid".to_string()))
+    }
+    Ok(public_key)
+}
+
+/// Converts an OpenSSL time to a UNIX timestamp in milliseconds.
+pub(crate) fn asn1_time_to_millis(time: &Asn1TimeRef) -> Result<i64, ErrorStack> {
+    let epoch = Asn1Time::from_unix(0)?;
+    let diff = epoch.diff(time)?;
+    Ok((diff.days as i64 * 86400 + diff.secs as i64) * 1000)
+}
+
 /// Returns the current UNIX timestamp in milliseconds and an OpenSSL Asn1Time object.
 fn get_timestamp(from_now_in_years: u64) -> Result<(i64, Asn1Time), ErrorStack> {
     let time = SystemTime::now() + std::time::Duration::from_secs(60 * 60 * 24 * 365 * from_now_in_years);
@@ -301,6 +922,22 @@ fn get_timestamp(from_now_in_years: u64) -> Result<(i64, Asn1Time), ErrorStack>
     Ok((time_unix, time_openssl))
 }
 
+/// UNIX timestamp in milliseconds and the same point in time as OpenSSL Asn1Time object.
+type Timestamp = (i64, Asn1Time);
+
+/// Returns the current UNIX timestamp in milliseconds and the notBefore and notAfter of a new certificate.
+fn get_validity(options: &IssuanceOptions) -> Result<(i64, Timestamp, Timestamp), ErrorStack> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let valid_from = options.not_before.unwrap_or(now);
+    let valid_until = valid_from + options.lifetime.unwrap_or(1000 * 60 * 60 * 24 * 365 * options.validity_in_years as i64);
+
+    Ok((
+        now,
+        (valid_from, Asn1Time::from_unix(valid_from / 1000)?),
+        (valid_until, Asn1Time::from_unix(valid_until / 1000)?)
+    ))
+}
+
 /// Convert a CA certificate to PEM format.
 pub(crate) fn get_pem(ca: &Certificate) -> Result<Vec<u8>, ErrorStack> {
     let cert = X509::from_der(&ca.cert)?;
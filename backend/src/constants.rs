@@ -2,5 +2,17 @@ pub(crate) const SETTINGS_FILE_PATH: &str = "settings.json";
 pub(crate) const DB_FILE_PATH: &str = "database.db3";
 pub(crate) const TEMP_DB_FILE_PATH: &str = "encrypted.db3";
 pub(crate) const CA_FILE_PATH: &str = "ca.cert";
+pub(crate) const CRL_VALIDITY_SECS: i64 = 60 * 60 * 24;
+pub(crate) const SHORT_LIVED_MAX_HOURS: u64 = 24;
+pub(crate) const SSH_CERT_MAX_DAYS: u64 = 365;
+pub(crate) const SPIFFE_REFRESH_HINT_SECS: i64 = 60 * 60;
+pub(crate) const DOMAIN_VALIDATION_VALIDITY_SECS: i64 = 60 * 60 * 24 * 7;
+pub(crate) const NOT_BEFORE_MAX_BACKDATE_SECS: i64 = 60 * 60;
+pub(crate) const NOT_BEFORE_MAX_FUTURE_SECS: i64 = 60 * 60 * 24 * 90;
+pub(crate) const ENROLLMENT_TOKEN_DEFAULT_HOURS: u64 = 24;
+pub(crate) const ENROLLMENT_TOKEN_MAX_HOURS: u64 = 24 * 30;
+pub(crate) const API_TOKEN_DEFAULT_DAYS: u64 = 90;
+pub(crate) const API_TOKEN_MAX_DAYS: u64 = 365 * 2;
+pub(crate) const RENEWAL_PROOF_MAX_AGE_SECS: i64 = 5 * 60;
 pub(crate) const API_PORT: u16 = 3737;
 pub(crate) const VAULTLS_VERSION: &str = "v0.7.0";
\ No newline at end of file
@@ -0,0 +1,383 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::base64;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::x509::X509Ref;
+use serde::Serialize;
+use crate::ApiError;
+use crate::cert::Certificate;
+use crate::db::VaulTLSDB;
+
+/// Maximum number of entries returned by a single get-entries request.
+const MAX_ENTRIES_PER_REQUEST: u64 = 256;
+
+/// Key signing the tree heads of the log.
+#[derive(Default, Clone)]
+pub(crate) struct CtLogKey {
+    pub(crate) id: i64,
+    pub(crate) created_on: i64,
+    pub(crate) private_key: Vec<u8>,
+}
+
+/// Entry of the log, stored with its RFC 6962 encodings.
+#[derive(Default, Clone)]
+pub(crate) struct CtLogEntry {
+    pub(crate) leaf_index: u64,
+    pub(crate) timestamp: i64,
+    pub(crate) leaf_hash: Vec<u8>,
+    /// Encoded MerkleTreeLeaf
+    pub(crate) leaf_input: Vec<u8>,
+    /// Encoded certificate chain of the issuing CA
+    pub(crate) extra_data: Vec<u8>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct SignedTreeHead {
+    tree_size: u64,
+    timestamp: i64,
+    sha256_root_hash: String,
+    tree_head_signature: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LogEntries {
+    entries: Vec<LogEntry>,
+}
+
+#[derive(Serialize)]
+struct LogEntry {
+    leaf_input: String,
+    extra_data: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct InclusionProof {
+    leaf_index: u64,
+    audit_path: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ConsistencyProof {
+    consistency: Vec<String>,
+}
+
+/// Public key of the log, with the log id as in the log lists of public CT logs.
+#[derive(Serialize)]
+pub(crate) struct LogPublicKey {
+    log_id: String,
+    key: String,
+}
+
+/// Creates a new ECDSA P-256 key for signing tree heads.
+pub(crate) fn create_log_key() -> Result<CtLogKey, ApiError> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+    Ok(CtLogKey {
+        created_on: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64,
+        private_key: key.private_key_to_der()?,
+        ..Default::default()
+    })
+}
+
+/// Appends a newly issued certificate to the log.
+pub(crate) fn append(db: &VaulTLSDB, cert: &X509Ref, ca: &Certificate) -> Result<u64, ApiError> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let leaf_input = encode_leaf(timestamp, &cert.to_der()?)?;
+    let mut extra_data = Vec::new();
+    let mut ca_cert = Vec::new();
+    push_opaque(&mut ca_cert, &ca.cert, 3)?;
+    push_opaque(&mut extra_data, &ca_cert, 3)?;
+
+    let mut entry = CtLogEntry {
+        timestamp,
+        leaf_hash: leaf_hash(&leaf_input)?,
+        leaf_input,
+        extra_data,
+        ..Default::default()
+    };
+    db.insert_ct_log_entry(&mut entry)?;
+    Ok(entry.leaf_index)
+}
+
+/// Returns the signed head of the current tree.
+pub(crate) fn get_sth(db: &VaulTLSDB) -> Result<SignedTreeHead, ApiError> {
+    let leaves = db.get_ct_log_leaf_hashes(None)?;
+    let root_hash = tree_hash(&leaves)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+
+    // TreeHeadSignature: version v1, signature type tree_hash, timestamp, tree size and root hash
+    let mut signed = vec![0u8, 1u8];
+    signed.extend_from_slice(&(timestamp as u64).to_be_bytes());
+    signed.extend_from_slice(&(leaves.len() as u64).to_be_bytes());
+    signed.extend_from_slice(&root_hash);
+
+    let key = PKey::private_key_from_der(&db.get_ct_log_key()?.private_key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(&signed)?;
+    let signature = signer.sign_to_vec()?;
+
+    // DigitallySigned with hash algorithm sha256 and signature algorithm ecdsa
+    let mut digitally_signed = vec![4u8, 3u8];
+    push_opaque(&mut digitally_signed, &signature, 2)?;
+
+    Ok(SignedTreeHead {
+        tree_size: leaves.len() as u64,
+        timestamp,
+        sha256_root_hash: base64::encode_block(&root_hash),
+        tree_head_signature: base64::encode_block(&digitally_signed),
+    })
+}
+
+/// Returns the entries from start to end, both inclusive.
+pub(crate) fn get_entries(db: &VaulTLSDB, start: u64, end: u64) -> Result<LogEntries, ApiError> {
+    if start > end {
+        return Err(ApiError::BadRequest("start must not be greater than end".to_string()))
+    }
+    let end = end.min(start.saturating_add(MAX_ENTRIES_PER_REQUEST - 1));
+    let entries = db.get_ct_log_entries(start, end)?.into_iter()
+        .map(|entry| LogEntry {
+            leaf_input: base64::encode_block(&entry.leaf_input),
+            extra_data: base64::encode_block(&entry.extra_data),
+        })
+        .collect();
+    Ok(LogEntries { entries })
+}
+
+/// Returns the audit path of the leaf with the base64 encoded hash in the tree of the given size.
+pub(crate) fn get_proof_by_hash(db: &VaulTLSDB, leaf_hash: &str, tree_size: u64) -> Result<InclusionProof, ApiError> {
+    let leaf_hash = base64::decode_block(leaf_hash.trim())
+        .map_err(|_| ApiError::BadRequest("hash is not valid base64".to_string()))?;
+    let leaves = get_tree(db, tree_size)?;
+    let leaf_index = leaves.iter().position(|leaf| *leaf == leaf_hash)
+        .ok_or(ApiError::BadRequest(format!("No entry with this hash in the tree of size {}", tree_size)))?;
+
+    let audit_path = audit_path(leaf_index, &leaves)?;
+    Ok(InclusionProof {
+        leaf_index: leaf_index as u64,
+        audit_path: audit_path.iter().map(|node| base64::encode_block(node)).collect(),
+    })
+}
+
+/// Returns the proof that the tree of size second is an extension of the tree of size first.
+pub(crate) fn get_consistency_proof(db: &VaulTLSDB, first: u64, second: u64) -> Result<ConsistencyProof, ApiError> {
+    if first == 0 || first > second {
+        return Err(ApiError::BadRequest("first must be between 1 and second".to_string()))
+    }
+    let leaves = get_tree(db, second)?;
+    let proof = subproof(first as usize, &leaves, true)?;
+    Ok(ConsistencyProof {
+        consistency: proof.iter().map(|node| base64::encode_block(node)).collect(),
+    })
+}
+
+/// Returns the public key and log id, the SHA-256 hash of the public key.
+pub(crate) fn get_public_key(db: &VaulTLSDB) -> Result<LogPublicKey, ApiError> {
+    let key = PKey::private_key_from_der(&db.get_ct_log_key()?.private_key)?;
+    let public_key = key.public_key_to_der()?;
+    Ok(LogPublicKey {
+        log_id: base64::encode_block(&hash(MessageDigest::sha256(), &public_key)?),
+        key: base64::encode_block(&public_key),
+    })
+}
+
+/// Returns the leaf hashes of the tree of the given size.
+fn get_tree(db: &VaulTLSDB, tree_size: u64) -> Result<Vec<Vec<u8>>, ApiError> {
+    let leaves = db.get_ct_log_leaf_hashes(Some(tree_size))?;
+    if leaves.len() as u64 != tree_size {
+        return Err(ApiError::BadRequest(format!("tree_size {} is larger than the log", tree_size)))
+    }
+    Ok(leaves)
+}
+
+/// Encodes a MerkleTreeLeaf with a timestamped X.509 entry without extensions.
+fn encode_leaf(timestamp: i64, cert: &[u8]) -> Result<Vec<u8>, ApiError> {
+    // version v1, leaf type timestamped_entry
+    let mut leaf = vec![0u8, 0u8];
+    leaf.extend_from_slice(&(timestamp as u64).to_be_bytes());
+    // entry type x509_entry
+    leaf.extend_from_slice(&[0u8, 0u8]);
+    push_opaque(&mut leaf, cert, 3)?;
+    // no extensions
+    leaf.extend_from_slice(&[0u8, 0u8]);
+    Ok(leaf)
+}
+
+/// Appends a TLS opaque vector with a length prefix of the given number of bytes.
+fn push_opaque(buffer: &mut Vec<u8>, data: &[u8], length_bytes: usize) -> Result<(), ApiError> {
+    if data.len() >= 1 << (8 * length_bytes) {
+        return Err(ApiError::Other("Log entry is too large".to_string()))
+    }
+    buffer.extend_from_slice(&(data.len() as u32).to_be_bytes()[4 - length_bytes..]);
+    buffer.extend_from_slice(data);
+    Ok(())
+}
+
+fn leaf_hash(leaf_input: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let mut data = vec![0u8];
+    data.extend_from_slice(leaf_input);
+    Ok(hash(MessageDigest::sha256(), &data)?.to_vec())
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let mut data = vec![1u8];
+    data.extend_from_slice(left);
+    data.extend_from_slice(right);
+    Ok(hash(MessageDigest::sha256(), &data)?.to_vec())
+}
+
+/// Largest power of two smaller than n.
+fn split(n: usize) -> usize {
+    let mut k = 1;
+    while k << 1 < n {
+        k <<= 1;
+    }
+    k
+}
+
+/// Merkle tree hash of the leaves as defined by RFC 6962, section 2.1.
+fn tree_hash(leaves: &[Vec<u8>]) -> Result<Vec<u8>, ApiError> {
+    match leaves.len() {
+        0 => Ok(hash(MessageDigest::sha256(), &[])?.to_vec()),
+        1 => Ok(leaves[0].clone()),
+        n => {
+            let k = split(n);
+            node_hash(&tree_hash(&leaves[..k])?, &tree_hash(&leaves[k..])?)
+        }
+    }
+}
+
+/// Merkle audit path of leaf m as defined by RFC 6962, section 2.1.1.
+fn audit_path(m: usize, leaves: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, ApiError> {
+    if leaves.len() <= 1 {
+        return Ok(Vec::new())
+    }
+    let k = split(leaves.len());
+    let (mut path, sibling) = match m < k {
+        true => (audit_path(m, &leaves[..k])?, tree_hash(&leaves[k..])?),
+        false => (audit_path(m - k, &leaves[k..])?, tree_hash(&leaves[..k])?)
+    };
+    path.push(sibling);
+    Ok(path)
+}
+
+/// Merkle consistency proof of the first m leaves as defined by RFC 6962, section 2.1.2.
+fn subproof(m: usize, leaves: &[Vec<u8>], complete: bool) -> Result<Vec<Vec<u8>>, ApiError> {
+    let n = leaves.len();
+    if m == n {
+        return Ok(match complete {
+            true => Vec::new(),
+            false => vec![tree_hash(leaves)?]
+        })
+    }
+    let k = split(n);
+    let (mut proof, sibling) = match m <= k {
+        true => (subproof(m, &leaves[..k], complete)?, tree_hash(&leaves[k..])?),
+        false => (subproof(m - k, &leaves[k..], false)?, tree_hash(&leaves[..k])?)
+    };
+    proof.push(sibling);
+    Ok(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Leaf inputs of the reference test vectors of the certificate transparency implementation.
+    const LEAF_INPUTS: [&str; 8] = [
+        "",
+        "00",
+        "10",
+        "2021",
+        "3031",
+        "40414243",
+        "5051525354555657",
+        "606162636465666768696a6b6c6d6e6f",
+    ];
+
+    /// Root hashes of the trees of the first 1 to 8 leaves.
+    const ROOTS: [&str; 8] = [
+        "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+        "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+        "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+        "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+        "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+        "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+        "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+    ];
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn leaves(tree_size: usize) -> Vec<Vec<u8>> {
+        LEAF_INPUTS[..tree_size].iter()
+            .map(|input| leaf_hash(&from_hex(input)).unwrap())
+            .collect()
+    }
+
+    fn nodes(hashes: &[&str]) -> Vec<Vec<u8>> {
+        hashes.iter().map(|hash| from_hex(hash)).collect()
+    }
+
+    #[test]
+    fn empty_tree_hash() {
+        let expected = from_hex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(tree_hash(&[]).unwrap(), expected);
+    }
+
+    #[test]
+    fn tree_hashes() {
+        for (index, root) in ROOTS.iter().enumerate() {
+            assert_eq!(tree_hash(&leaves(index + 1)).unwrap(), from_hex(root), "tree of size {}", index + 1);
+        }
+    }
+
+    #[test]
+    fn audit_paths() {
+        assert!(audit_path(0, &leaves(1)).unwrap().is_empty());
+        assert_eq!(audit_path(0, &leaves(8)).unwrap(), nodes(&[
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+        ]));
+        assert_eq!(audit_path(5, &leaves(8)).unwrap(), nodes(&[
+            "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+            "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        ]));
+        assert_eq!(audit_path(2, &leaves(3)).unwrap(), nodes(&[
+            "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+        ]));
+        assert_eq!(audit_path(1, &leaves(5)).unwrap(), nodes(&[
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+        ]));
+    }
+
+    #[test]
+    fn consistency_proofs() {
+        assert!(subproof(1, &leaves(1), true).unwrap().is_empty());
+        assert_eq!(subproof(1, &leaves(8), true).unwrap(), nodes(&[
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+        ]));
+        assert_eq!(subproof(6, &leaves(8), true).unwrap(), nodes(&[
+            "0ebc5d3437fbe2db158b9f126a1d118e308181031d0a949f8dededebc558ef6a",
+            "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        ]));
+        assert_eq!(subproof(2, &leaves(5), true).unwrap(), nodes(&[
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+        ]));
+    }
+}
@@ -3,7 +3,9 @@ use rocket::{Request, Response};
 use rocket::http::{ContentType, Header, Status};
 use rocket::response::Responder;
 use rocket::serde::{Deserialize, Serialize};
-use crate::data::enums::{CertificateType, UserRole};
+use crate::helper::deserialize_comma_list;
+use crate::data::enums::{CaPurpose, CertificateFormat, CertificateProfile, CertificateType, CheckStatus, KeyAlgorithm, LintSeverity, RevocationStatus, SshCertificateType, UserRole, ValidationMethod};
+pub use backend::{IssuedCertificateResponse, RenewCertificateRequest};
 
 #[derive(Serialize)]
 pub struct IsSetupResponse {
@@ -27,11 +29,6 @@ pub struct LoginRequest {
     pub password: String
 }
 
-#[derive(Serialize)]
-pub struct LoginResponse {
-    pub token: String
-}
-
 #[derive(Deserialize)]
 pub struct ChangePasswordRequest {
     pub old_password: Option<String>,
@@ -54,11 +51,190 @@ pub struct CreateUserCertificateRequest {
     pub pkcs12_password: Option<String>,
     pub cert_type: Option<CertificateType>,
     pub dns_names: Option<Vec<String>>,
+    pub key_algorithm: Option<KeyAlgorithm>,
+    /// Workload path of an X.509-SVID, such as /ns/prod/sa/web
+    pub spiffe_path: Option<String>,
+    pub profile: Option<CertificateProfile>,
+    /// User principal name SAN of EAP-TLS client certificates, such as alice@corp.example
+    pub upn: Option<String>,
+    /// PEM certificate request of a client or server certificate. The certificate is issued
+    /// for its key, the subject and SANs are still taken from the request and the user.
+    pub csr: Option<String>,
+    /// Issuing CA, defaults to the most recent active CA for the certificate type
+    pub ca_id: Option<i64>,
+    /// notBefore as UNIX timestamp in milliseconds, either in the future for scheduled
+    /// certificates or slightly in the past to tolerate clock skew
+    pub not_before: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct CertificatePasswordRequest {
+    pub system_generated_password: bool,
+    pub pkcs12_password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BulkImportRequest {
+    /// CSV with a header row, used instead of rows if set
+    pub csv: Option<String>,
+    #[serde(default)]
+    pub rows: Vec<ImportRow>,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub notify_users: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ImportRow {
+    pub user_name: String,
+    pub user_email: String,
+    pub role: Option<UserRole>,
+    pub password: Option<String>,
+    /// Only a user is created if no certificate name is given
+    pub cert_name: Option<String>,
+    pub cert_type: Option<CertificateType>,
+    pub validity_in_years: Option<u64>,
+    #[serde(default)]
+    pub dns_names: Vec<String>,
+    pub key_algorithm: Option<KeyAlgorithm>,
+    pub spiffe_path: Option<String>,
+    pub system_generated_password: Option<bool>,
+    pub pkcs12_password: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct ImportRowResult {
+    pub row: usize,
+    pub user_id: Option<i64>,
+    pub user_created: bool,
+    pub certificate_id: Option<i64>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BulkImportResponse {
+    pub dry_run: bool,
+    pub committed: bool,
+    pub results: Vec<ImportRowResult>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCaRequest {
+    pub name: String,
+    pub validity_in_years: u64,
+    pub purpose: Option<CaPurpose>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCaRequest {
+    pub name: String,
+    pub purpose: CaPurpose,
+    pub active: bool,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTrustAnchorRequest {
+    pub name: String,
+    /// PEM encoded CA certificate
+    pub certificate: String,
+    pub purpose: Option<CaPurpose>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateTrustAnchorRequest {
+    pub name: String,
+    pub purpose: CaPurpose,
+    pub enabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    /// Days until the token expires, defaults to API_TOKEN_DEFAULT_DAYS
+    pub expires_in_days: Option<u64>,
+    /// Admins can create tokens for other users
+    pub user_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateSshPublicKeyRequest {
+    pub name: String,
+    pub public_key: String,
+    /// Admins can upload keys for other users
+    pub user_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateSshCertificateRequest {
+    pub cert_name: String,
+    pub user_id: i64,
+    pub cert_type: Option<SshCertificateType>,
+    /// Uploaded public key of the user to sign
+    pub public_key_id: Option<i64>,
+    /// Public key in OpenSSH format, used if no uploaded key is selected
+    pub public_key: Option<String>,
+    /// Host names of host certificates, user certificates use the principals of the user
+    pub principals: Option<Vec<String>>,
+    pub validity_in_days: Option<u64>,
+    pub force_command: Option<String>,
+    pub source_address: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateEnrollmentTokenRequest {
+    pub cert_name: String,
+    pub user_id: i64,
+    pub cert_type: Option<CertificateType>,
+    pub profile: Option<CertificateProfile>,
+    pub validity_in_years: Option<u64>,
+    /// Key algorithm of the generated key, ignored if the device sends a CSR
+    pub key_algorithm: Option<KeyAlgorithm>,
+    pub dns_names: Option<Vec<String>>,
+    pub upn: Option<String>,
+    pub ca_id: Option<i64>,
+    /// Hours until the token can no longer be redeemed, defaults to 24
+    pub expires_in_hours: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct EnrollRequest {
+    pub token: String,
+    /// PEM encoded CSR of a key generated on the device, a key is generated if not provided
+    pub csr: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateShortLivedCertificateRequest {
+    pub cert_name: String,
+    /// Admins can issue certificates for other users, defaults to the authenticated user
+    pub user_id: Option<i64>,
+    pub validity_in_hours: Option<u64>,
+    /// PEM encoded CSR, a key is generated if not provided
+    pub csr: Option<String>,
+    pub key_algorithm: Option<KeyAlgorithm>,
+    /// Issuing CA, defaults to the most recent active CA for client certificates
+    pub ca_id: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ShortLivedCertificateResponse {
+    pub id: i64,
+    pub name: String,
+    pub serial: String,
+    pub valid_until: i64,
+    /// PEM encoded certificate
+    pub certificate: String,
+    /// PEM encoded CA certificate
+    pub chain: String,
+    /// PEM encoded PKCS#8 private key, only set if the key was generated
+    pub private_key: Option<String>,
 }
 
 pub struct DownloadResponse {
     pub content: Vec<u8>,
     pub filename: String,
+    pub content_type: ContentType,
 }
 
 impl DownloadResponse {
@@ -66,8 +242,15 @@ impl DownloadResponse {
         Self {
             content,
             filename: filename.to_string(),
+            content_type: ContentType::Text,
         }
     }
+
+    /// Sets a content type other than text, e.g. for files opened by the OS after download.
+    pub fn with_content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = content_type;
+        self
+    }
 }
 
 //todo: respect filename
@@ -75,7 +258,7 @@ impl<'r> Responder<'r, 'static> for DownloadResponse {
     fn respond_to(self, _req: &'r Request<'_>) -> rocket::response::Result<'static> {
         Response::build()
             .status(Status::Ok)
-            .header(ContentType::Text)
+            .header(self.content_type)
             .header(Header::new(
                 "Content-Disposition",
                 format!("attachment; filename=\"{}\"", self.filename),
@@ -93,3 +276,247 @@ pub struct CreateUserRequest {
     pub password: Option<String>,
     pub role: UserRole
 }
+
+
+#[derive(Deserialize)]
+pub struct VerifyCertificateRequest {
+    /// PEM encoded certificate (optionally followed by its chain) or base64 encoded DER / PKCS#12
+    pub certificate: String,
+    pub password: Option<String>,
+    pub hostname: Option<String>,
+    pub purpose: Option<CertificateType>,
+}
+
+#[derive(Serialize)]
+pub struct VerificationCheck {
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl VerificationCheck {
+    pub fn new(status: CheckStatus, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+
+#[derive(Serialize)]
+pub struct VerificationReport {
+    pub valid: bool,
+    pub format: CertificateFormat,
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: i64,
+    pub not_after: i64,
+    pub subject_alt_names: Vec<String>,
+    pub extended_key_usage: Vec<String>,
+    pub chain: Vec<String>,
+    pub ca_id: Option<i64>,
+    pub certificate_id: Option<i64>,
+    pub revocation_status: RevocationStatus,
+    pub chain_check: VerificationCheck,
+    pub validity_check: VerificationCheck,
+    pub purpose_check: VerificationCheck,
+    pub revocation_check: VerificationCheck,
+    pub hostname_check: VerificationCheck,
+}
+
+#[derive(Deserialize)]
+pub struct LintCertificateRequest {
+    /// PEM encoded certificate or base64 encoded DER / PKCS#12, only the first certificate is linted
+    pub certificate: String,
+    pub password: Option<String>,
+    /// Profile to lint against, CAs are detected from the basic constraints if unset
+    pub certificate_type: Option<CertificateType>,
+}
+
+#[derive(Serialize)]
+pub struct LintFinding {
+    pub severity: LintSeverity,
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct LintReport {
+    pub passed: bool,
+    pub subject: String,
+    pub serial: String,
+    pub certificate_id: Option<i64>,
+    pub findings: Vec<LintFinding>,
+}
+
+#[derive(Serialize)]
+pub struct CertificateExtension {
+    pub oid: String,
+    pub name: String,
+    pub critical: bool,
+    pub value: String,
+}
+
+#[derive(Serialize)]
+pub struct CertificatePreview {
+    /// False if a policy is violated or linting blocks the certificate
+    pub issuable: bool,
+    pub ca_id: i64,
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: i64,
+    pub not_after: i64,
+    pub key_algorithm: KeyAlgorithm,
+    pub signature_algorithm: String,
+    pub subject_alt_names: Vec<String>,
+    pub extensions: Vec<CertificateExtension>,
+    pub policy_violations: Vec<String>,
+    pub lint_findings: Vec<LintFinding>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCaBackupRequest {
+    /// CA to back up, defaults to the current CA
+    pub ca_id: Option<i64>,
+    pub threshold: u8,
+    pub share_count: u8,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreCaBackupRequest {
+    pub backup: String,
+    pub shares: Vec<String>,
+    pub purpose: Option<CaPurpose>,
+    /// Whether the restored CA issues new certificates, it is restored inactive by default
+    #[serde(default)]
+    pub active: bool,
+}
+
+#[derive(Serialize)]
+pub struct KeyPoolStatus {
+    pub key_algorithm: KeyAlgorithm,
+    pub available: usize,
+    pub limit: usize,
+    /// Keys generated during a request because the pool was empty
+    pub generated_on_demand: u64,
+}
+
+#[derive(Deserialize)]
+pub struct EasyRsaImportRequest {
+    /// Path of the easy-rsa pki/ directory on the VaulTLS server
+    pub path: String,
+    /// Passphrase of the CA key and encrypted certificate keys
+    pub key_passphrase: Option<String>,
+    /// Owner of certificates whose CN or e-mail does not match a user, these are skipped if not set
+    pub fallback_user_id: Option<i64>,
+    #[serde(default)]
+    pub system_generated_password: bool,
+    pub pkcs12_password: Option<String>,
+    pub purpose: Option<CaPurpose>,
+    /// Whether the imported CA issues new certificates, it is imported inactive by default
+    #[serde(default)]
+    pub active: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct EasyRsaImportResult {
+    pub file: String,
+    pub name: String,
+    pub serial: String,
+    pub user_id: Option<i64>,
+    pub certificate_id: Option<i64>,
+    /// False if the key is not in the PKI, the PKCS#12 then only contains the certificate
+    pub has_key: bool,
+    pub revoked: bool,
+    /// Lint findings of the imported certificate, imported certificates are never rejected by linting
+    pub findings: Vec<LintFinding>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct EasyRsaImportResponse {
+    pub dry_run: bool,
+    pub committed: bool,
+    pub ca_id: i64,
+    /// Lint findings of the imported CA
+    pub ca_findings: Vec<LintFinding>,
+    pub results: Vec<EasyRsaImportResult>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateServerCertificateRequest {
+    pub cert_name: String,
+    pub dns_names: Vec<String>,
+    #[serde(default)]
+    pub validation_method: ValidationMethod,
+    pub validity_in_years: Option<u64>,
+    pub key_algorithm: Option<KeyAlgorithm>,
+    #[serde(default)]
+    pub system_generated_password: bool,
+    pub pkcs12_password: Option<String>,
+}
+
+/// Request of the Vault PKI issue and sign endpoints.
+#[derive(Deserialize)]
+pub struct VaultCertificateRequest {
+    #[serde(default)]
+    pub common_name: String,
+    #[serde(default, deserialize_with = "deserialize_comma_list")]
+    pub alt_names: Vec<String>,
+    #[serde(default, deserialize_with = "deserialize_comma_list")]
+    pub ip_sans: Vec<String>,
+    /// Duration such as 720h or seconds, rounded up to whole years
+    pub ttl: Option<VaultTtl>,
+    pub format: Option<String>,
+    #[serde(default)]
+    pub exclude_cn_from_sans: bool,
+    /// PEM certificate request, only for the sign endpoint
+    pub csr: Option<String>,
+    /// Id of the issuing CA or "default"
+    pub issuer_ref: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum VaultTtl {
+    Seconds(u64),
+    Duration(String),
+}
+
+#[derive(Deserialize)]
+pub struct VaultRevokeRequest {
+    pub serial_number: String,
+}
+
+/// Response envelope of the Vault HTTP API.
+#[derive(Serialize)]
+pub struct VaultResponse<T> {
+    pub request_id: String,
+    pub lease_id: String,
+    pub renewable: bool,
+    pub lease_duration: u64,
+    pub data: T,
+    pub wrap_info: Option<()>,
+    pub warnings: Option<Vec<String>>,
+    pub auth: Option<()>,
+}
+
+#[derive(Serialize)]
+pub struct VaultCertificateData {
+    pub certificate: String,
+    pub issuing_ca: String,
+    pub ca_chain: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key_type: Option<String>,
+    /// Colon separated lowercase hex, as used by Vault
+    pub serial_number: String,
+    /// Unix timestamp in seconds
+    pub expiration: i64,
+}
+
+#[derive(Serialize)]
+pub struct VaultRevokeData {
+    pub revocation_time: i64,
+    pub revocation_time_rfc3339: String,
+}
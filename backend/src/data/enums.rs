@@ -24,6 +24,7 @@ impl FromSql for UserRole {
 
 #[derive(Serialize_repr, Deserialize_repr, Clone, Debug, Copy, PartialEq, Eq, Default)]
 #[repr(u8)]
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) enum MailEncryption {
     #[default]
     None = 0,
@@ -46,7 +47,9 @@ pub(crate) enum CertificateType {
     #[default]
     Client = 0,
     Server = 1,
-    CA = 2
+    CA = 2,
+    Svid = 3,
+    Timestamping = 4
 }
 
 impl FromSql for CertificateType {
@@ -60,4 +63,214 @@ impl FromSql for CertificateType {
             _ => Err(FromSqlError::InvalidType),
         }
     }
-}
\ No newline at end of file
+}
+#[derive(Serialize_repr, Deserialize_repr, Clone, Debug, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub(crate) enum OpenVpnProtocol {
+    #[default]
+    Udp = 0,
+    Tcp = 1
+}
+
+/// Extension profile of client and server certificates.
+#[derive(Serialize_repr, Deserialize_repr, TryFromPrimitive, Clone, Debug, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub(crate) enum CertificateProfile {
+    #[default]
+    Default = 0,
+    /// 802.1X EAP-TLS: adds the eapOverLAN extended key usage and, for clients, an optional UPN
+    EapTls = 1
+}
+
+impl FromSql for CertificateProfile {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => {
+                let value = i as u8;
+                CertificateProfile::try_from(value)
+                    .map_err(|_| FromSqlError::InvalidType)
+            },
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[derive(Serialize_repr, Deserialize_repr, TryFromPrimitive, Clone, Debug, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub(crate) enum KeyAlgorithm {
+    #[default]
+    EcdsaP256 = 0,
+    EcdsaP384 = 1,
+    Rsa2048 = 2,
+    Rsa4096 = 3
+}
+
+impl FromSql for KeyAlgorithm {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => {
+                let value = i as u8;
+                KeyAlgorithm::try_from(value)
+                    .map_err(|_| FromSqlError::InvalidType)
+            },
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[derive(Serialize_repr, Deserialize_repr, Clone, Debug, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum CertificateFormat {
+    Pem = 0,
+    Der = 1,
+    Pkcs12 = 2
+}
+
+#[derive(Serialize_repr, Deserialize_repr, Clone, Debug, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum CheckStatus {
+    Pass = 0,
+    Warning = 1,
+    Fail = 2,
+    Skipped = 3
+}
+
+#[derive(Serialize_repr, Deserialize_repr, Clone, Debug, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub(crate) enum LintSeverity {
+    Notice = 0,
+    Warning = 1,
+    Error = 2
+}
+
+#[derive(Serialize_repr, Deserialize_repr, Clone, Debug, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum RevocationStatus {
+    Good = 0,
+    Revoked = 1,
+    Unknown = 2
+}
+
+#[derive(Serialize_repr, Deserialize_repr, TryFromPrimitive, Clone, Debug, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub(crate) enum SshCertificateType {
+    #[default]
+    User = 0,
+    Host = 1
+}
+
+impl FromSql for SshCertificateType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => {
+                let value = i as u8;
+                SshCertificateType::try_from(value)
+                    .map_err(|_| FromSqlError::InvalidType)
+            },
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// How control over a DNS name is proven.
+#[derive(Serialize_repr, Deserialize_repr, TryFromPrimitive, Clone, Debug, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub(crate) enum ValidationMethod {
+    #[default]
+    DnsTxt = 0,
+    Http = 1
+}
+
+impl FromSql for ValidationMethod {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => {
+                let value = i as u8;
+                ValidationMethod::try_from(value)
+                    .map_err(|_| FromSqlError::InvalidType)
+            },
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[derive(Serialize_repr, Deserialize_repr, TryFromPrimitive, Clone, Debug, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub(crate) enum ValidationStatus {
+    #[default]
+    Pending = 0,
+    Valid = 1,
+    Invalid = 2
+}
+
+impl FromSql for ValidationStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => {
+                let value = i as u8;
+                ValidationStatus::try_from(value)
+                    .map_err(|_| FromSqlError::InvalidType)
+            },
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// Which certificates a CA issues, so that e.g. a proxy trusting client certificates does not trust server certificates.
+#[derive(Serialize_repr, Deserialize_repr, TryFromPrimitive, Clone, Debug, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub(crate) enum CaPurpose {
+    #[default]
+    General = 0,
+    Client = 1,
+    Server = 2
+}
+
+impl CaPurpose {
+    /// General CAs issue all certificate types, the others only client or server certificates.
+    pub(crate) fn allows(&self, certificate_type: CertificateType) -> bool {
+        match self {
+            CaPurpose::General => true,
+            CaPurpose::Client => certificate_type == CertificateType::Client,
+            CaPurpose::Server => certificate_type == CertificateType::Server,
+        }
+    }
+}
+
+impl FromSql for CaPurpose {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => {
+                let value = i as u8;
+                CaPurpose::try_from(value)
+                    .map_err(|_| FromSqlError::InvalidType)
+            },
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// Reason of a revocation, with the reason codes of RFC 5280.
+#[derive(Serialize_repr, Deserialize_repr, TryFromPrimitive, Clone, Debug, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub(crate) enum RevocationReason {
+    #[default]
+    Unspecified = 0,
+    /// The certificate was replaced by a re-keyed or renewed certificate
+    Superseded = 4,
+    /// The certificate or its user was deleted
+    CessationOfOperation = 5
+}
+
+impl FromSql for RevocationReason {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => {
+                let value = i as u8;
+                RevocationReason::try_from(value)
+                    .map_err(|_| FromSqlError::InvalidType)
+            },
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
@@ -44,6 +44,18 @@ impl From<openssl::error::ErrorStack> for ApiError {
     }
 }
 
+impl From<der::Error> for ApiError {
+    fn from(error: der::Error) -> Self {
+        ApiError::Other(error.to_string())
+    }
+}
+
+impl From<ssh_key::Error> for ApiError {
+    fn from(error: ssh_key::Error) -> Self {
+        ApiError::Other(error.to_string())
+    }
+}
+
 impl From<argon2::password_hash::Error> for ApiError {
     fn from(error: argon2::password_hash::Error) -> Self {
         ApiError::Unauthorized(Some(error.to_string()))
@@ -4,16 +4,30 @@ use rusqlite::fallible_iterator::FallibleIterator;
 use std::str::FromStr;
 use anyhow::anyhow;
 use argon2::password_hash::PasswordHashString;
-use rusqlite::{params, Connection, Result};
+use rusqlite::{params, Connection, OptionalExtension, Result};
 use include_dir::{include_dir, Dir};
+use openssl::x509::X509;
 use rusqlite_migration::Migrations;
 use crate::{ApiError, Certificate, User};
+use crate::cert::{get_common_name, get_pkcs12_certificate, get_serial_hex, CertificateAuthority, ShortLivedCertificate};
 use crate::constants::{DB_FILE_PATH, TEMP_DB_FILE_PATH};
-use crate::data::enums::UserRole;
+use crate::data::enums::{CaPurpose, CertificateType, RevocationReason, UserRole};
 use crate::helper::get_secret;
+use crate::policy::IssuancePolicy;
+use crate::ssh::{SshCa, SshCertificate, SshPublicKey};
+use crate::backup::{CaBackup, CaBackupShare};
+use crate::data::enums::ValidationStatus;
+use crate::validation::{DomainValidation, ServerCertificateRequest};
+use crate::auth::token_auth::ApiToken;
+use crate::trust::TrustAnchor;
+use crate::ctlog::{CtLogEntry, CtLogKey};
+use crate::enrollment::EnrollmentToken;
 
 static MIGRATIONS_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/migrations");
 
+/// Revokes certificates with reason ?2 at ?1 unless they are revoked already, marks them as deleted and drops their PKCS12 data
+const DELETE_USER_CERTS: &str = "UPDATE user_certificates SET revoked_on = COALESCE(revoked_on, ?1), revocation_reason = CASE WHEN revoked_on IS NULL THEN ?2 ELSE revocation_reason END, deleted_on = COALESCE(deleted_on, ?1), pkcs12 = NULL, pkcs12_password = ''";
+
 pub(crate) struct VaulTLSDB {
     connection: Connection
 }
@@ -34,7 +48,7 @@ impl VaulTLSDB {
             }
 
         }
-        connection.pragma_update(None, "foreign_keys", &"ON")?;
+        connection.pragma_update(None, "foreign_keys", "ON")?;
         // This if statement can be removed in a future version
         if db_initialized {
             let user_version: i32 = connection
@@ -42,18 +56,20 @@ impl VaulTLSDB {
                 .expect("Failed to get PRAGMA user_version");
             // Database already initialized, update user_version to 1
             if user_version == 0 {
-                connection.pragma_update(None, "user_version", &"1")?;
+                connection.pragma_update(None, "user_version", "1")?;
             }
         }
         
         Self::migrate_database(&mut connection)?;
+        Self::backfill_certificate_serials(&connection)?;
+        Self::backfill_ca_names(&connection)?;
 
         if !db_encrypted {
             if let Ok(ref db_secret) = db_secret {
                 println!("Migrating to encrypted database");
                 Self::create_encrypt_db(&connection, db_secret)?;
                 drop(connection);
-                let conn = Self::migrate_to_encrypted_db(&db_secret)?;
+                let conn = Self::migrate_to_encrypted_db(db_secret)?;
                 return Ok(Self { connection: conn});
             }
         }
@@ -91,7 +107,7 @@ impl VaulTLSDB {
         fs::rename(TEMP_DB_FILE_PATH, DB_FILE_PATH)?;
         let conn = Connection::open(DB_FILE_PATH)?;
         conn.pragma_update(None, "key", db_secret)?;
-        conn.pragma_update(None, "foreign_keys", &"ON")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
         Ok(conn)
     }
 
@@ -102,36 +118,187 @@ impl VaulTLSDB {
         Ok(())
     }
 
+    /// Certificates created before serials were stored get them extracted from their PKCS#12
+    fn backfill_certificate_serials(conn: &Connection) -> Result<()> {
+        let mut stmt = conn.prepare("SELECT id, pkcs12, pkcs12_password FROM user_certificates WHERE serial IS NULL AND deleted_on IS NULL")?;
+        let missing: Vec<(i64, Vec<u8>, String)> = stmt
+            .query([])?
+            .map(|row| Ok((row.get(0)?, row.get(1)?, row.get(2).unwrap_or_default())))
+            .collect()?;
+
+        for (id, pkcs12, password) in missing {
+            let Ok(cert) = get_pkcs12_certificate(&pkcs12, &password) else { continue };
+            let Ok(serial) = get_serial_hex(&cert) else { continue };
+            conn.execute(
+                "UPDATE user_certificates SET serial = ?1 WHERE id = ?2",
+                params![serial, id]
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// CAs created before they had names are named after the common name of their certificate
+    fn backfill_ca_names(conn: &Connection) -> Result<()> {
+        let mut stmt = conn.prepare("SELECT id, certificate FROM ca_certificates WHERE name = ''")?;
+        let unnamed: Vec<(i64, Vec<u8>)> = stmt
+            .query([])?
+            .map(|row| Ok((row.get(0)?, row.get(1)?)))
+            .collect()?;
+
+        for (id, cert) in unnamed {
+            let Ok(cert) = X509::from_der(&cert) else { continue };
+            let Some(name) = get_common_name(&cert) else { continue };
+            conn.execute(
+                "UPDATE ca_certificates SET name = ?1 WHERE id = ?2",
+                params![name, id]
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Run all database operations of the closure in a single transaction
+    /// The closure returns its result and whether the changes should be committed
+    pub(crate) fn transaction<T>(&self, operations: impl FnOnce(&Self) -> Result<(T, bool), ApiError>) -> Result<T, ApiError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let (result, commit) = operations(self)?;
+        if commit {
+            transaction.commit()?;
+        }
+        Ok(result)
+    }
+
+    /// Runs the operations in a savepoint, which is rolled back if they fail
+    /// Unlike transactions, savepoints can be nested, e.g. inside an import or enrollment transaction
+    pub(crate) fn savepoint<T>(&self, operations: impl FnOnce(&Self) -> Result<T, ApiError>) -> Result<T, ApiError> {
+        self.connection.execute_batch("SAVEPOINT vaultls")?;
+        match operations(self) {
+            Ok(result) => {
+                self.connection.execute_batch("RELEASE vaultls")?;
+                Ok(result)
+            }
+            Err(e) => {
+                self.connection.execute_batch("ROLLBACK TO vaultls; RELEASE vaultls")?;
+                Err(e)
+            }
+        }
+    }
+
     /// Insert a new CA certificate into the database
     /// Adds id to the Certificate struct
     pub(crate) fn insert_ca(
         &self,
-        ca: &mut Certificate
+        ca: &mut Certificate,
+        purpose: CaPurpose,
+        active: bool
     ) -> Result<(), rusqlite::Error> {
         self.connection.execute(
-            "INSERT INTO ca_certificates (created_on, valid_until, certificate, key) VALUES (?1, ?2, ?3, ?4)",
-            params![ca.created_on, ca.valid_until, ca.cert, ca.key],
+            "INSERT INTO ca_certificates (name, created_on, valid_until, certificate, key, purpose, active) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![ca.name, ca.created_on, ca.valid_until, ca.cert, ca.key, purpose as u8, active],
         )?;
         
-        ca.ca_id = self.connection.last_insert_rowid();
+        ca.id = self.connection.last_insert_rowid();
 
         Ok(())
     }
 
-    /// Retrieve the most recent CA entry from the database
+    /// Retrieve the most recent active CA entry from the database
+    /// General purpose CAs are preferred, so that adding a dedicated CA does not change the default CA
     pub(crate) fn get_current_ca(&self) -> Result<Certificate, ApiError> {
-        let mut stmt = self.connection.prepare("SELECT * FROM ca_certificates ORDER BY id DESC LIMIT 1")?;
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, valid_until, certificate, key FROM ca_certificates WHERE active = 1 ORDER BY purpose = 0 DESC, id DESC LIMIT 1")?;
 
-        stmt.query_row([], |row| {
-            Ok(Certificate{
-                id: row.get(0)?,
-                created_on: row.get(1)?,
-                valid_until: row.get(2)?,
-                cert: row.get(3)?,
-                key: row.get(4)?,
-                ..Default::default()
-            })
-        }).map_err(|_| ApiError::BadRequest("VaulTLS has not been set-up yet".to_string()))
+        stmt.query_row([], Self::ca_from_row)
+            .map_err(|_| ApiError::BadRequest("VaulTLS has not been set-up yet".to_string()))
+    }
+
+    /// Retrieve the CA that issues a certificate of the given type
+    /// If ca_id is None, the most recent active CA dedicated to the type is used, otherwise the most recent general purpose CA
+    pub(crate) fn get_issuing_ca(&self, ca_id: Option<i64>, certificate_type: CertificateType) -> Result<Certificate, ApiError> {
+        let Some(ca_id) = ca_id else {
+            let mut stmt = self.connection.prepare("SELECT id, name, created_on, valid_until, certificate, key, purpose FROM ca_certificates WHERE active = 1 ORDER BY purpose != 0 DESC, id DESC")?;
+            let rows = stmt.query([])?;
+            let cas: Vec<(Certificate, CaPurpose)> = rows.map(|row| Ok((Self::ca_from_row(row)?, row.get(6)?))).collect()?;
+            return cas.into_iter()
+                .find(|(_, purpose)| purpose.allows(certificate_type))
+                .map(|(ca, _)| ca)
+                .ok_or(ApiError::BadRequest(format!("No active CA issues {:?} certificates", certificate_type)))
+        };
+
+        let authority = self.get_certificate_authority(ca_id)?;
+        if !authority.active {
+            return Err(ApiError::BadRequest(format!("CA {} is not active", authority.name)))
+        }
+        if !authority.purpose.allows(certificate_type) {
+            return Err(ApiError::BadRequest(format!("CA {} does not issue {:?} certificates", authority.name, certificate_type)))
+        }
+        self.get_ca(ca_id)
+    }
+
+    fn ca_from_row(row: &rusqlite::Row) -> Result<Certificate, rusqlite::Error> {
+        Ok(Certificate{
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_on: row.get(2)?,
+            valid_until: row.get(3)?,
+            cert: row.get(4)?,
+            key: row.get(5)?,
+            certificate_type: CertificateType::CA,
+            ..Default::default()
+        })
+    }
+
+    /// Retrieve a CA entry by id from the database
+    pub(crate) fn get_ca(&self, id: i64) -> Result<Certificate, ApiError> {
+        self.connection.query_row(
+            "SELECT id, name, created_on, valid_until, certificate, key FROM ca_certificates WHERE id = ?1",
+            params![id],
+            Self::ca_from_row
+        ).optional()?.ok_or(ApiError::BadRequest(format!("CA {} does not exist", id)))
+    }
+
+    /// Retrieve all CA entries from the database
+    pub(crate) fn get_all_ca(&self) -> Result<Vec<Certificate>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, valid_until, certificate, key FROM ca_certificates")?;
+        let rows = stmt.query([])?;
+        rows.map(Self::ca_from_row).collect()
+    }
+
+    /// Retrieve the metadata of all CAs from the database
+    pub(crate) fn get_all_certificate_authorities(&self) -> Result<Vec<CertificateAuthority>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, valid_until, purpose, active FROM ca_certificates")?;
+        let rows = stmt.query([])?;
+        rows.map(Self::certificate_authority_from_row).collect()
+    }
+
+    /// Retrieve the metadata of a CA by id from the database
+    pub(crate) fn get_certificate_authority(&self, id: i64) -> Result<CertificateAuthority, ApiError> {
+        self.connection.query_row(
+            "SELECT id, name, created_on, valid_until, purpose, active FROM ca_certificates WHERE id = ?1",
+            params![id],
+            Self::certificate_authority_from_row
+        ).optional()?.ok_or(ApiError::BadRequest(format!("CA {} does not exist", id)))
+    }
+
+    fn certificate_authority_from_row(row: &rusqlite::Row) -> Result<CertificateAuthority, rusqlite::Error> {
+        Ok(CertificateAuthority {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_on: row.get(2)?,
+            valid_until: row.get(3)?,
+            purpose: row.get(4)?,
+            active: row.get(5)?,
+        })
+    }
+
+    /// Update name, purpose and active state of a CA
+    pub(crate) fn update_certificate_authority(&self, authority: &CertificateAuthority) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "UPDATE ca_certificates SET name = ?1, purpose = ?2, active = ?3 WHERE id=?4",
+            params![authority.name, authority.purpose as u8, authority.active, authority.id]
+        )?;
+
+        Ok(())
     }
 
     /// Retrieve all user certificates from the database
@@ -139,8 +306,8 @@ impl VaulTLSDB {
     /// If user_id is None, all certificates are returned
     pub(crate) fn get_all_user_cert(&self, user_id: Option<i64>) -> Result<Vec<Certificate>, rusqlite::Error>{
         let query = match user_id {
-            Some(_) => "SELECT id, name, created_on, valid_until, pkcs12, pkcs12_password, user_id, type FROM user_certificates WHERE user_id = ?1",
-            None => "SELECT id, name, created_on, valid_until, pkcs12, pkcs12_password, user_id, type FROM user_certificates"
+            Some(_) => "SELECT id, name, created_on, valid_until, pkcs12, pkcs12_password, user_id, type, serial, revoked_on, valid_from, revocation_reason, superseded_by FROM user_certificates WHERE user_id = ?1 AND deleted_on IS NULL",
+            None => "SELECT id, name, created_on, valid_until, pkcs12, pkcs12_password, user_id, type, serial, revoked_on, valid_from, revocation_reason, superseded_by FROM user_certificates WHERE deleted_on IS NULL"
         };
         let mut stmt = self.connection.prepare(query)?;
         let rows = match user_id {
@@ -157,16 +324,46 @@ impl VaulTLSDB {
                     pkcs12_password: row.get(5).unwrap_or_default(),
                     user_id: row.get(6)?,
                     certificate_type: row.get(7)?,
+                    serial: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+                    revoked_on: row.get(9)?,
+                    valid_from: row.get(10)?,
+                    revocation_reason: row.get(11)?,
+                    superseded_by: row.get(12)?,
                     ..Default::default()
                 })
             })
             .collect()
     }
 
+    /// Retrieve a user certificate including its PKCS12 data from the database
+    pub(crate) fn get_user_cert(&self, id: i64) -> Result<Certificate, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, valid_until, pkcs12, pkcs12_password, user_id, type, serial, revoked_on, ca_id, valid_from, revocation_reason, superseded_by FROM user_certificates WHERE id = ?1 AND deleted_on IS NULL")?;
+
+        stmt.query_row(params![id], |row| {
+            Ok(Certificate {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                created_on: row.get(2)?,
+                valid_until: row.get(3)?,
+                pkcs12: row.get(4)?,
+                pkcs12_password: row.get(5).unwrap_or_default(),
+                user_id: row.get(6)?,
+                certificate_type: row.get(7)?,
+                serial: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+                revoked_on: row.get(9)?,
+                ca_id: row.get(10)?,
+                valid_from: row.get(11)?,
+                revocation_reason: row.get(12)?,
+                superseded_by: row.get(13)?,
+                ..Default::default()
+            })
+        })
+    }
+
     /// Retrieve the certificate's PKCS12 data with id from the database
     /// Returns the id of the user the certificate belongs to and the PKCS12 data
     pub(crate) fn get_user_cert_pkcs12(&self, id: i64) -> Result<(i64, String, Vec<u8>), rusqlite::Error> {
-        let mut stmt = self.connection.prepare("SELECT user_id, name, pkcs12 FROM user_certificates WHERE id = ?1")?;
+        let mut stmt = self.connection.prepare("SELECT user_id, name, pkcs12 FROM user_certificates WHERE id = ?1 AND deleted_on IS NULL")?;
 
         stmt.query_row(
             params![id],
@@ -177,7 +374,7 @@ impl VaulTLSDB {
     /// Retrieve the certificate's PKCS12 data with id from the database
     /// Returns the id of the user the certificate belongs to and the PKCS12 password
     pub(crate) fn get_user_cert_pkcs12_password(&self, id: i64) -> Result<(i64, String), rusqlite::Error> {
-        let mut stmt = self.connection.prepare("SELECT user_id, pkcs12_password FROM user_certificates WHERE id = ?1")?;
+        let mut stmt = self.connection.prepare("SELECT user_id, pkcs12_password FROM user_certificates WHERE id = ?1 AND deleted_on IS NULL")?;
         
         stmt.query_row(
             params![id],
@@ -185,12 +382,37 @@ impl VaulTLSDB {
         )
     }
 
+    /// Retrieve a user certificate by its serial number
+    /// Returns None if no certificate with that serial is stored
+    pub(crate) fn get_user_cert_by_serial(&self, serial: &str) -> Result<Option<Certificate>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, valid_until, user_id, type, ca_id, revoked_on, valid_from, revocation_reason, superseded_by FROM user_certificates WHERE serial = ?1")?;
+
+        stmt.query_row(params![serial], |row| {
+            Ok(Certificate {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                created_on: row.get(2)?,
+                valid_until: row.get(3)?,
+                // Certificates of deleted users are detached from them
+                user_id: row.get::<_, Option<i64>>(4)?.unwrap_or_default(),
+                certificate_type: row.get(5)?,
+                ca_id: row.get(6)?,
+                revoked_on: row.get(7)?,
+                valid_from: row.get(8)?,
+                revocation_reason: row.get(9)?,
+                superseded_by: row.get(10)?,
+                serial: serial.to_string(),
+                ..Default::default()
+            })
+        }).optional()
+    }
+
     /// Insert a new certificate into the database
     /// Adds id to Certificate struct
     pub(crate) fn insert_user_cert(&self, cert: &mut Certificate) -> Result<(), rusqlite::Error> {
         self.connection.execute(
-            "INSERT INTO user_certificates (name, created_on, valid_until, pkcs12, pkcs12_password, type, ca_id, user_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![cert.name, cert.created_on, cert.valid_until, cert.pkcs12, cert.pkcs12_password, cert.certificate_type as u8, cert.ca_id, cert.user_id],
+            "INSERT INTO user_certificates (name, created_on, valid_from, valid_until, pkcs12, pkcs12_password, type, ca_id, user_id, serial) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![cert.name, cert.created_on, cert.valid_from, cert.valid_until, cert.pkcs12, cert.pkcs12_password, cert.certificate_type as u8, cert.ca_id, cert.user_id, cert.serial],
         )?;
         
         cert.id = self.connection.last_insert_rowid();
@@ -198,10 +420,763 @@ impl VaulTLSDB {
         Ok(())
     }
 
-    /// Delete a certificate from the database
-    pub(crate) fn delete_user_cert(&self, id: i64) -> Result<(), rusqlite::Error> {
+    /// Replace the PKCS12 data and password of a certificate
+    pub(crate) fn update_user_cert_pkcs12(&self, id: i64, pkcs12: &[u8], pkcs12_password: &str) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "UPDATE user_certificates SET pkcs12 = ?1, pkcs12_password = ?2 WHERE id=?3",
+            params![pkcs12, pkcs12_password, id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Replace a certificate with its re-keyed or renewed version, which keeps the id
+    /// The superseded certificate is kept as a revoked copy linked to its successor, so that CRL and OCSP still know its serial
+    pub(crate) fn supersede_user_cert(&self, superseded: &Certificate, cert: &Certificate, revoked_on: i64) -> Result<(), ApiError> {
+        let archived = self.connection.execute(
+            "INSERT INTO user_certificates (name, created_on, valid_from, valid_until, pkcs12, pkcs12_password, type, ca_id, user_id, serial, revoked_on, revocation_reason, superseded_by) SELECT name, created_on, valid_from, valid_until, pkcs12, pkcs12_password, type, ca_id, user_id, serial, ?1, ?2, id FROM user_certificates WHERE id = ?3 AND serial = ?4 AND revoked_on IS NULL",
+            params![revoked_on, RevocationReason::Superseded as u8, superseded.id, superseded.serial]
+        )?;
+        if archived != 1 {
+            return Err(ApiError::BadRequest("Certificate does not exist, is revoked or was replaced in the meantime".to_string()))
+        }
+
+        self.connection.execute(
+            "UPDATE user_certificates SET pkcs12 = ?1, pkcs12_password = ?2, serial = ?3, created_on = ?4, valid_from = ?5, valid_until = ?6, ca_id = ?7 WHERE id=?8",
+            params![cert.pkcs12, cert.pkcs12_password, cert.serial, cert.created_on, cert.valid_from, cert.valid_until, cert.ca_id, cert.id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Mark a certificate as revoked
+    /// Certificates that are already revoked keep their original revocation time
+    pub(crate) fn revoke_user_cert(&self, id: i64, revoked_on: i64) -> Result<(), ApiError> {
+        let revoked = self.connection.execute(
+            "UPDATE user_certificates SET revoked_on = ?1 WHERE id=?2 AND revoked_on IS NULL",
+            params![revoked_on, id]
+        )?;
+        if revoked != 1 {
+            return Err(ApiError::BadRequest("Certificate does not exist or is already revoked".to_string()))
+        }
+
+        Ok(())
+    }
+
+    /// Count the certificates of a user that are neither revoked nor expired
+    pub(crate) fn count_active_user_certs(&self, user_id: i64, now: i64) -> Result<usize, rusqlite::Error> {
+        self.connection.query_row(
+            "SELECT COUNT(*) FROM user_certificates WHERE user_id = ?1 AND revoked_on IS NULL AND valid_until > ?2",
+            params![user_id, now],
+            |row| row.get(0)
+        )
+    }
+
+    /// Retrieve serial and revocation time of all revoked certificates issued by a CA
+    pub(crate) fn get_revoked_serials(&self, ca_id: i64) -> Result<Vec<(String, i64)>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT serial, revoked_on FROM user_certificates WHERE ca_id = ?1 AND revoked_on IS NOT NULL AND serial IS NOT NULL")?;
+        let rows = stmt.query(params![ca_id])?;
+        rows.map(|row| Ok((row.get(0)?, row.get(1)?))).collect()
+    }
+
+    /// Delete a certificate, which is revoked first unless it already is
+    /// The row is kept without its PKCS12 data, so that CRL and OCSP still know its serial
+    pub(crate) fn delete_user_cert(&self, id: i64, deleted_on: i64) -> Result<(), ApiError> {
+        let deleted = self.connection.execute(
+            &format!("{} WHERE id = ?3 AND deleted_on IS NULL", DELETE_USER_CERTS),
+            params![deleted_on, RevocationReason::CessationOfOperation as u8, id]
+        )?;
+        if deleted != 1 {
+            return Err(ApiError::BadRequest("Certificate does not exist".to_string()))
+        }
+
+        Ok(())
+    }
+
+    /// Insert the metadata of a short-lived certificate into the database
+    /// Adds id to ShortLivedCertificate struct
+    pub(crate) fn insert_short_lived_cert(&self, cert: &mut ShortLivedCertificate) -> Result<(), rusqlite::Error> {
         self.connection.execute(
-            "DELETE FROM user_certificates WHERE id=?1",
+            "INSERT INTO short_lived_certificates (name, created_on, valid_until, serial, ca_id, user_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![cert.name, cert.created_on, cert.valid_until, cert.serial, cert.ca_id, cert.user_id],
+        )?;
+
+        cert.id = self.connection.last_insert_rowid();
+
+        Ok(())
+    }
+
+    /// Retrieve the metadata of all short-lived certificates from the database
+    /// If user_id is Some, only certificates for that user are returned
+    pub(crate) fn get_all_short_lived_cert(&self, user_id: Option<i64>) -> Result<Vec<ShortLivedCertificate>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, valid_until, serial, user_id, ca_id FROM short_lived_certificates WHERE ?1 IS NULL OR user_id = ?1")?;
+        let rows = stmt.query(params![user_id])?;
+        rows.map(|row| {
+                Ok(ShortLivedCertificate {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    created_on: row.get(2)?,
+                    valid_until: row.get(3)?,
+                    serial: row.get(4)?,
+                    user_id: row.get(5)?,
+                    ca_id: row.get(6)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Insert a new SSH CA into the database
+    /// Adds id to SshCa struct
+    pub(crate) fn insert_ssh_ca(&self, ca: &mut SshCa) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "INSERT INTO ssh_ca (created_on, private_key, public_key) VALUES (?1, ?2, ?3)",
+            params![ca.created_on, ca.private_key, ca.public_key],
+        )?;
+
+        ca.id = self.connection.last_insert_rowid();
+
+        Ok(())
+    }
+
+    /// Retrieve the most recent SSH CA from the database
+    pub(crate) fn get_current_ssh_ca(&self) -> Result<SshCa, ApiError> {
+        self.connection.query_row(
+            "SELECT id, created_on, private_key, public_key FROM ssh_ca ORDER BY id DESC LIMIT 1",
+            [],
+            |row| {
+                Ok(SshCa {
+                    id: row.get(0)?,
+                    created_on: row.get(1)?,
+                    private_key: row.get(2)?,
+                    public_key: row.get(3)?,
+                })
+            }
+        ).map_err(|_| ApiError::BadRequest("SSH CA has not been set-up yet".to_string()))
+    }
+
+    /// Insert a new certificate transparency log key into the database
+    /// Adds id to CtLogKey struct
+    pub(crate) fn insert_ct_log_key(&self, key: &mut CtLogKey) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "INSERT INTO ct_log_keys (created_on, private_key) VALUES (?1, ?2)",
+            params![key.created_on, key.private_key],
+        )?;
+
+        key.id = self.connection.last_insert_rowid();
+
+        Ok(())
+    }
+
+    /// Retrieve the most recent certificate transparency log key from the database
+    pub(crate) fn get_ct_log_key(&self) -> Result<CtLogKey, ApiError> {
+        self.connection.query_row(
+            "SELECT id, created_on, private_key FROM ct_log_keys ORDER BY id DESC LIMIT 1",
+            [],
+            |row| {
+                Ok(CtLogKey {
+                    id: row.get(0)?,
+                    created_on: row.get(1)?,
+                    private_key: row.get(2)?,
+                })
+            }
+        ).map_err(|_| ApiError::BadRequest("Certificate transparency log has not been set-up yet".to_string()))
+    }
+
+    /// Append an entry to the certificate transparency log
+    /// Adds the leaf index to CtLogEntry struct
+    pub(crate) fn insert_ct_log_entry(&self, entry: &mut CtLogEntry) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "INSERT INTO ct_log_entries (leaf_index, timestamp, leaf_hash, leaf_input, extra_data) VALUES ((SELECT COUNT(*) FROM ct_log_entries), ?1, ?2, ?3, ?4)",
+            params![entry.timestamp, entry.leaf_hash, entry.leaf_input, entry.extra_data],
+        )?;
+
+        entry.leaf_index = self.connection.last_insert_rowid() as u64;
+
+        Ok(())
+    }
+
+    /// Retrieve the leaf hashes of the certificate transparency log in order
+    /// If tree_size is Some, only the first tree_size hashes are returned
+    pub(crate) fn get_ct_log_leaf_hashes(&self, tree_size: Option<u64>) -> Result<Vec<Vec<u8>>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT leaf_hash FROM ct_log_entries WHERE ?1 IS NULL OR leaf_index < ?1 ORDER BY leaf_index")?;
+        let rows = stmt.query(params![tree_size.map(|size| size as i64)])?;
+        rows.map(|row| row.get(0)).collect()
+    }
+
+    /// Retrieve the certificate transparency log entries from start to end, both inclusive
+    pub(crate) fn get_ct_log_entries(&self, start: u64, end: u64) -> Result<Vec<CtLogEntry>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT leaf_index, timestamp, leaf_hash, leaf_input, extra_data FROM ct_log_entries WHERE leaf_index BETWEEN ?1 AND ?2 ORDER BY leaf_index")?;
+        let rows = stmt.query(params![start as i64, end as i64])?;
+        rows.map(|row| {
+            Ok(CtLogEntry {
+                leaf_index: row.get::<_, i64>(0)? as u64,
+                timestamp: row.get(1)?,
+                leaf_hash: row.get(2)?,
+                leaf_input: row.get(3)?,
+                extra_data: row.get(4)?,
+            })
+        }).collect()
+    }
+
+    /// Retrieve all SSH public keys from the database
+    /// If user_id is Some, only keys of that user are returned
+    pub(crate) fn get_all_ssh_public_keys(&self, user_id: Option<i64>) -> Result<Vec<SshPublicKey>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, user_id, public_key FROM ssh_public_keys WHERE ?1 IS NULL OR user_id = ?1")?;
+        let rows = stmt.query(params![user_id])?;
+        rows.map(Self::ssh_public_key_from_row).collect()
+    }
+
+    /// Retrieve an SSH public key by id from the database
+    pub(crate) fn get_ssh_public_key(&self, id: i64) -> Result<SshPublicKey, rusqlite::Error> {
+        self.connection.query_row(
+            "SELECT id, name, created_on, user_id, public_key FROM ssh_public_keys WHERE id = ?1",
+            params![id],
+            Self::ssh_public_key_from_row
+        )
+    }
+
+    fn ssh_public_key_from_row(row: &rusqlite::Row) -> Result<SshPublicKey, rusqlite::Error> {
+        let public_key: String = row.get(4)?;
+        Ok(SshPublicKey {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_on: row.get(2)?,
+            user_id: row.get(3)?,
+            fingerprint: crate::ssh::parse_public_key(&public_key).map(|(_, fingerprint)| fingerprint).unwrap_or_default(),
+            public_key,
+        })
+    }
+
+    /// Insert a new SSH public key into the database
+    /// Adds id to SshPublicKey struct
+    pub(crate) fn insert_ssh_public_key(&self, key: &mut SshPublicKey) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "INSERT INTO ssh_public_keys (name, created_on, public_key, user_id) VALUES (?1, ?2, ?3, ?4)",
+            params![key.name, key.created_on, key.public_key, key.user_id],
+        )?;
+
+        key.id = self.connection.last_insert_rowid();
+
+        Ok(())
+    }
+
+    /// Delete an SSH public key from the database
+    pub(crate) fn delete_ssh_public_key(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "DELETE FROM ssh_public_keys WHERE id=?1",
+            params![id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Retrieve all SSH certificates from the database
+    /// If user_id is Some, only certificates for that user are returned
+    pub(crate) fn get_all_ssh_cert(&self, user_id: Option<i64>) -> Result<Vec<SshCertificate>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, valid_until, type, user_id, serial, principals, certificate, ssh_ca_id FROM ssh_certificates WHERE ?1 IS NULL OR user_id = ?1")?;
+        let rows = stmt.query(params![user_id])?;
+        rows.map(Self::ssh_cert_from_row).collect()
+    }
+
+    /// Retrieve an SSH certificate by id from the database
+    pub(crate) fn get_ssh_cert(&self, id: i64) -> Result<SshCertificate, rusqlite::Error> {
+        self.connection.query_row(
+            "SELECT id, name, created_on, valid_until, type, user_id, serial, principals, certificate, ssh_ca_id FROM ssh_certificates WHERE id = ?1",
+            params![id],
+            Self::ssh_cert_from_row
+        )
+    }
+
+    fn ssh_cert_from_row(row: &rusqlite::Row) -> Result<SshCertificate, rusqlite::Error> {
+        let principals: String = row.get(7)?;
+        Ok(SshCertificate {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_on: row.get(2)?,
+            valid_until: row.get(3)?,
+            certificate_type: row.get(4)?,
+            user_id: row.get(5)?,
+            serial: row.get(6)?,
+            principals: serde_json::from_str(&principals).unwrap_or_default(),
+            certificate: row.get(8)?,
+            ssh_ca_id: row.get(9)?,
+        })
+    }
+
+    /// Insert a new SSH certificate into the database
+    /// Adds id to SshCertificate struct
+    pub(crate) fn insert_ssh_cert(&self, cert: &mut SshCertificate) -> Result<(), rusqlite::Error> {
+        let principals = serde_json::to_string(&cert.principals).unwrap_or_default();
+        self.connection.execute(
+            "INSERT INTO ssh_certificates (name, created_on, valid_until, type, serial, principals, certificate, ssh_ca_id, user_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![cert.name, cert.created_on, cert.valid_until, cert.certificate_type as u8, cert.serial, principals, cert.certificate, cert.ssh_ca_id, cert.user_id],
+        )?;
+
+        cert.id = self.connection.last_insert_rowid();
+
+        Ok(())
+    }
+
+    /// Delete an SSH certificate from the database
+    pub(crate) fn delete_ssh_cert(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "DELETE FROM ssh_certificates WHERE id=?1",
+            params![id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Insert a new CA backup together with its shares into the database
+    /// Adds id to CaBackup struct
+    pub(crate) fn insert_ca_backup(&self, backup: &mut CaBackup) -> Result<(), ApiError> {
+        self.transaction(|db| {
+            db.connection.execute(
+                "INSERT INTO ca_backups (created_on, threshold, share_count, backup, ca_id, user_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![backup.created_on, backup.threshold, backup.share_count, backup.backup, backup.ca_id, backup.user_id],
+            )?;
+            backup.id = db.connection.last_insert_rowid();
+
+            for share in &backup.shares {
+                db.connection.execute(
+                    "INSERT INTO ca_backup_shares (backup_id, share_index, share) VALUES (?1, ?2, ?3)",
+                    params![backup.id, share.index, share.share],
+                )?;
+            }
+            Ok(((), true))
+        })
+    }
+
+    /// Retrieve all CA backups with the download state of their shares from the database
+    pub(crate) fn get_all_ca_backups(&self) -> Result<Vec<CaBackup>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, created_on, threshold, share_count, backup, ca_id, user_id FROM ca_backups")?;
+        let rows = stmt.query([])?;
+        let mut backups: Vec<CaBackup> = rows.map(Self::ca_backup_from_row).collect()?;
+        for backup in backups.iter_mut() {
+            backup.shares = self.get_ca_backup_shares(backup.id)?;
+        }
+        Ok(backups)
+    }
+
+    /// Retrieve a CA backup by id from the database
+    pub(crate) fn get_ca_backup(&self, id: i64) -> Result<CaBackup, ApiError> {
+        let mut backup = self.connection.query_row(
+            "SELECT id, created_on, threshold, share_count, backup, ca_id, user_id FROM ca_backups WHERE id = ?1",
+            params![id],
+            Self::ca_backup_from_row
+        ).optional()?.ok_or(ApiError::BadRequest(format!("CA backup {} does not exist", id)))?;
+        backup.shares = self.get_ca_backup_shares(id)?;
+        Ok(backup)
+    }
+
+    fn ca_backup_from_row(row: &rusqlite::Row) -> Result<CaBackup, rusqlite::Error> {
+        Ok(CaBackup {
+            id: row.get(0)?,
+            created_on: row.get(1)?,
+            threshold: row.get(2)?,
+            share_count: row.get(3)?,
+            backup: row.get(4)?,
+            ca_id: row.get(5)?,
+            user_id: row.get::<_, Option<i64>>(6)?.unwrap_or_default(),
+            shares: Vec::new(),
+        })
+    }
+
+    fn get_ca_backup_shares(&self, backup_id: i64) -> Result<Vec<CaBackupShare>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT share_index, downloaded_on, downloaded_by FROM ca_backup_shares WHERE backup_id = ?1 ORDER BY share_index")?;
+        let rows = stmt.query(params![backup_id])?;
+        rows.map(|row| {
+                Ok(CaBackupShare {
+                    index: row.get(0)?,
+                    downloaded_on: row.get(1)?,
+                    downloaded_by: row.get(2)?,
+                    share: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Hand out a stored share of a CA backup exactly once and record who downloaded it.
+    /// Each user may only download a single share of a backup. Runs in a savepoint, so the caller can still roll it back.
+    pub(crate) fn take_ca_backup_share(&self, backup_id: i64, index: u8, user_id: i64, downloaded_on: i64) -> Result<String, ApiError> {
+        self.savepoint(|db| {
+            let already_downloaded: bool = db.connection.query_row(
+                "SELECT EXISTS(SELECT 1 FROM ca_backup_shares WHERE backup_id = ?1 AND downloaded_by = ?2)",
+                params![backup_id, user_id],
+                |row| row.get(0)
+            )?;
+            if already_downloaded {
+                return Err(ApiError::Forbidden(Some("You already downloaded a share of this backup".to_string())))
+            }
+
+            let share: Option<String> = db.connection.query_row(
+                "SELECT share FROM ca_backup_shares WHERE backup_id = ?1 AND share_index = ?2",
+                params![backup_id, index],
+                |row| row.get(0)
+            ).optional()?
+                .ok_or(ApiError::BadRequest(format!("Share {} does not exist", index)))?;
+            let share = share.ok_or(ApiError::BadRequest(format!("Share {} has already been downloaded", index)))?;
+
+            db.connection.execute(
+                "UPDATE ca_backup_shares SET share = NULL, downloaded_on = ?1, downloaded_by = ?2 WHERE backup_id = ?3 AND share_index = ?4",
+                params![downloaded_on, user_id, backup_id, index]
+            )?;
+            Ok(share)
+        })
+    }
+
+    /// Delete a CA backup and its remaining shares from the database
+    pub(crate) fn delete_ca_backup(&self, id: i64) -> Result<(), ApiError> {
+        self.transaction(|db| {
+            db.connection.execute("DELETE FROM ca_backup_shares WHERE backup_id = ?1", params![id])?;
+            db.connection.execute("DELETE FROM ca_backups WHERE id = ?1", params![id])?;
+            Ok(((), true))
+        })
+    }
+
+    /// Insert a new server certificate request together with its domain validations into the database
+    /// Adds ids to the ServerCertificateRequest and DomainValidation structs
+    pub(crate) fn insert_server_cert_request(&self, request: &mut ServerCertificateRequest) -> Result<(), ApiError> {
+        self.transaction(|db| {
+            db.connection.execute(
+                "INSERT INTO server_certificate_requests (created_on, expires_on, name, validity_in_years, key_algorithm, system_generated_password, pkcs12_password, status, user_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![request.created_on, request.expires_on, request.name, request.validity_in_years, request.key_algorithm.map(|key_algorithm| key_algorithm as u8), request.system_generated_password, request.pkcs12_password, request.status as u8, request.user_id],
+            )?;
+            request.id = db.connection.last_insert_rowid();
+
+            for validation in request.validations.iter_mut() {
+                db.connection.execute(
+                    "INSERT INTO domain_validations (request_id, dns_name, method, token, status) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![request.id, validation.dns_name, validation.method as u8, validation.token, validation.status as u8],
+                )?;
+                validation.id = db.connection.last_insert_rowid();
+            }
+            Ok(((), true))
+        })
+    }
+
+    /// Retrieve all server certificate requests with their domain validations
+    /// If user_id is Some, only requests of that user are returned
+    pub(crate) fn get_all_server_cert_requests(&self, user_id: Option<i64>) -> Result<Vec<ServerCertificateRequest>, rusqlite::Error> {
+        let query = match user_id {
+            Some(_) => "SELECT id, created_on, expires_on, name, validity_in_years, key_algorithm, system_generated_password, pkcs12_password, status, certificate_id, user_id FROM server_certificate_requests WHERE user_id = ?1",
+            None => "SELECT id, created_on, expires_on, name, validity_in_years, key_algorithm, system_generated_password, pkcs12_password, status, certificate_id, user_id FROM server_certificate_requests"
+        };
+        let mut stmt = self.connection.prepare(query)?;
+        let rows = match user_id {
+            Some(id) => stmt.query(params![id])?,
+            None => stmt.query([])?,
+        };
+        let mut requests: Vec<ServerCertificateRequest> = rows.map(Self::server_cert_request_from_row).collect()?;
+        for request in requests.iter_mut() {
+            request.validations = self.get_domain_validations(request.id)?;
+        }
+        Ok(requests)
+    }
+
+    /// Retrieve a server certificate request by id from the database
+    pub(crate) fn get_server_cert_request(&self, id: i64) -> Result<ServerCertificateRequest, ApiError> {
+        let mut request = self.connection.query_row(
+            "SELECT id, created_on, expires_on, name, validity_in_years, key_algorithm, system_generated_password, pkcs12_password, status, certificate_id, user_id FROM server_certificate_requests WHERE id = ?1",
+            params![id],
+            Self::server_cert_request_from_row
+        ).optional()?.ok_or(ApiError::BadRequest(format!("Server certificate request {} does not exist", id)))?;
+        request.validations = self.get_domain_validations(id)?;
+        Ok(request)
+    }
+
+    fn server_cert_request_from_row(row: &rusqlite::Row) -> Result<ServerCertificateRequest, rusqlite::Error> {
+        Ok(ServerCertificateRequest {
+            id: row.get(0)?,
+            created_on: row.get(1)?,
+            expires_on: row.get(2)?,
+            name: row.get(3)?,
+            validity_in_years: row.get(4)?,
+            key_algorithm: row.get(5)?,
+            system_generated_password: row.get(6)?,
+            pkcs12_password: row.get(7)?,
+            status: row.get(8)?,
+            certificate_id: row.get(9)?,
+            user_id: row.get(10)?,
+            validations: Vec::new(),
+        })
+    }
+
+    fn get_domain_validations(&self, request_id: i64) -> Result<Vec<DomainValidation>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, dns_name, method, token, status, validated_on, error FROM domain_validations WHERE request_id = ?1 ORDER BY id")?;
+        let rows = stmt.query(params![request_id])?;
+        rows.map(|row| {
+                let mut validation = DomainValidation::new(row.get(1)?, row.get(2)?, row.get(3)?);
+                validation.id = row.get(0)?;
+                validation.status = row.get(4)?;
+                validation.validated_on = row.get(5)?;
+                validation.error = row.get(6)?;
+                Ok(validation)
+            })
+            .collect()
+    }
+
+    /// Record the result of checking a domain validation
+    pub(crate) fn update_domain_validation(&self, id: i64, status: ValidationStatus, validated_on: Option<i64>, error: Option<&str>) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "UPDATE domain_validations SET status = ?1, validated_on = ?2, error = ?3 WHERE id = ?4",
+            params![status as u8, validated_on, error, id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Set the status of a server certificate request and the certificate issued for it
+    pub(crate) fn update_server_cert_request(&self, id: i64, status: ValidationStatus, certificate_id: Option<i64>) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "UPDATE server_certificate_requests SET status = ?1, certificate_id = ?2 WHERE id = ?3",
+            params![status as u8, certificate_id, id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Delete a server certificate request and its domain validations from the database
+    pub(crate) fn delete_server_cert_request(&self, id: i64) -> Result<(), ApiError> {
+        self.transaction(|db| {
+            db.connection.execute("DELETE FROM domain_validations WHERE request_id = ?1", params![id])?;
+            db.connection.execute("DELETE FROM server_certificate_requests WHERE id = ?1", params![id])?;
+            Ok(((), true))
+        })
+    }
+
+    /// Insert a new trust anchor into the database
+    /// Adds id to TrustAnchor struct
+    pub(crate) fn insert_trust_anchor(&self, anchor: &mut TrustAnchor) -> Result<(), ApiError> {
+        let exists: bool = self.connection.query_row(
+            "SELECT EXISTS(SELECT 1 FROM trust_anchors WHERE fingerprint = ?1)",
+            params![anchor.fingerprint],
+            |row| row.get(0)
+        )?;
+        if exists {
+            return Err(ApiError::BadRequest("Trust anchor is already present".to_string()))
+        }
+
+        self.connection.execute(
+            "INSERT INTO trust_anchors (name, created_on, valid_until, subject, fingerprint, purpose, enabled, certificate) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![anchor.name, anchor.created_on, anchor.valid_until, anchor.subject, anchor.fingerprint, anchor.purpose as u8, anchor.enabled, anchor.certificate],
+        )?;
+
+        anchor.id = self.connection.last_insert_rowid();
+
+        Ok(())
+    }
+
+    /// Retrieve all trust anchors including their certificates from the database
+    pub(crate) fn get_all_trust_anchors(&self) -> Result<Vec<TrustAnchor>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, valid_until, subject, fingerprint, purpose, enabled, certificate FROM trust_anchors")?;
+        let rows = stmt.query([])?;
+        rows.map(Self::trust_anchor_from_row).collect()
+    }
+
+    /// Retrieve a trust anchor by id from the database
+    pub(crate) fn get_trust_anchor(&self, id: i64) -> Result<TrustAnchor, ApiError> {
+        self.connection.query_row(
+            "SELECT id, name, created_on, valid_until, subject, fingerprint, purpose, enabled, certificate FROM trust_anchors WHERE id = ?1",
+            params![id],
+            Self::trust_anchor_from_row
+        ).optional()?.ok_or(ApiError::BadRequest(format!("Trust anchor {} does not exist", id)))
+    }
+
+    fn trust_anchor_from_row(row: &rusqlite::Row) -> Result<TrustAnchor, rusqlite::Error> {
+        Ok(TrustAnchor {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_on: row.get(2)?,
+            valid_until: row.get(3)?,
+            subject: row.get(4)?,
+            fingerprint: row.get(5)?,
+            purpose: row.get(6)?,
+            enabled: row.get(7)?,
+            certificate: row.get(8)?,
+        })
+    }
+
+    /// Update name, purpose and enabled state of a trust anchor
+    pub(crate) fn update_trust_anchor(&self, anchor: &TrustAnchor) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "UPDATE trust_anchors SET name = ?1, purpose = ?2, enabled = ?3 WHERE id=?4",
+            params![anchor.name, anchor.purpose as u8, anchor.enabled, anchor.id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Delete a trust anchor from the database
+    pub(crate) fn delete_trust_anchor(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "DELETE FROM trust_anchors WHERE id=?1",
+            params![id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Insert a new API token into the database
+    /// Adds id to ApiToken struct
+    pub(crate) fn insert_api_token(&self, token: &mut ApiToken) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "INSERT INTO api_tokens (name, created_on, expires_on, token_hash, user_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![token.name, token.created_on, token.expires_on, token.token_hash, token.user_id],
+        )?;
+
+        token.id = self.connection.last_insert_rowid();
+
+        Ok(())
+    }
+
+    /// Retrieve all API tokens from the database
+    /// If user_id is Some, only tokens of that user are returned
+    pub(crate) fn get_all_api_tokens(&self, user_id: Option<i64>) -> Result<Vec<ApiToken>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, expires_on, last_used_on, user_id FROM api_tokens WHERE ?1 IS NULL OR user_id = ?1")?;
+        let rows = stmt.query(params![user_id])?;
+        rows.map(Self::api_token_from_row).collect()
+    }
+
+    /// Retrieve an API token by id from the database
+    pub(crate) fn get_api_token(&self, id: i64) -> Result<ApiToken, rusqlite::Error> {
+        self.connection.query_row(
+            "SELECT id, name, created_on, expires_on, last_used_on, user_id FROM api_tokens WHERE id = ?1",
+            params![id],
+            Self::api_token_from_row
+        )
+    }
+
+    fn api_token_from_row(row: &rusqlite::Row) -> Result<ApiToken, rusqlite::Error> {
+        Ok(ApiToken {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_on: row.get(2)?,
+            expires_on: row.get(3)?,
+            last_used_on: row.get(4)?,
+            user_id: row.get(5)?,
+            ..Default::default()
+        })
+    }
+
+    /// Return the user of an API token that has not expired and record its use
+    pub(crate) fn use_api_token(&self, token_hash: &str, now: i64) -> Result<Option<User>, rusqlite::Error> {
+        let user_id: Option<i64> = self.connection.query_row(
+            "SELECT user_id FROM api_tokens WHERE token_hash = ?1 AND (expires_on IS NULL OR expires_on > ?2)",
+            params![token_hash, now],
+            |row| row.get(0)
+        ).optional()?;
+        let Some(user_id) = user_id else { return Ok(None) };
+
+        self.connection.execute(
+            "UPDATE api_tokens SET last_used_on = ?1 WHERE token_hash = ?2",
+            params![now, token_hash]
+        )?;
+        self.get_user(user_id).optional()
+    }
+
+    /// Delete an API token from the database
+    pub(crate) fn delete_api_token(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "DELETE FROM api_tokens WHERE id=?1",
+            params![id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Insert a new enrollment token into the database
+    /// Adds id to EnrollmentToken struct
+    pub(crate) fn insert_enrollment_token(&self, token: &mut EnrollmentToken) -> Result<(), rusqlite::Error> {
+        let dns_names = serde_json::to_string(&token.dns_names).unwrap_or_default();
+        self.connection.execute(
+            "INSERT INTO enrollment_tokens (name, created_on, expires_on, token_hash, type, profile, validity_in_years, key_algorithm, dns_names, upn, ca_id, user_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![token.name, token.created_on, token.expires_on, token.token_hash, token.certificate_type as u8, token.profile as u8, token.validity_in_years, token.key_algorithm.map(|key_algorithm| key_algorithm as u8), dns_names, token.upn, token.ca_id, token.user_id],
+        )?;
+
+        token.id = self.connection.last_insert_rowid();
+
+        Ok(())
+    }
+
+    /// Retrieve all enrollment tokens from the database
+    pub(crate) fn get_all_enrollment_tokens(&self) -> Result<Vec<EnrollmentToken>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare("SELECT id, name, created_on, expires_on, type, profile, validity_in_years, key_algorithm, dns_names, upn, ca_id, used_on, certificate_id, user_id FROM enrollment_tokens")?;
+        let rows = stmt.query([])?;
+        rows.map(Self::enrollment_token_from_row).collect()
+    }
+
+    /// Retrieve an enrollment token by id from the database
+    pub(crate) fn get_enrollment_token(&self, id: i64) -> Result<EnrollmentToken, ApiError> {
+        self.connection.query_row(
+            "SELECT id, name, created_on, expires_on, type, profile, validity_in_years, key_algorithm, dns_names, upn, ca_id, used_on, certificate_id, user_id FROM enrollment_tokens WHERE id = ?1",
+            params![id],
+            Self::enrollment_token_from_row
+        ).optional()?.ok_or(ApiError::BadRequest(format!("Enrollment token {} does not exist", id)))
+    }
+
+    fn enrollment_token_from_row(row: &rusqlite::Row) -> Result<EnrollmentToken, rusqlite::Error> {
+        let dns_names: String = row.get(8)?;
+        Ok(EnrollmentToken {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_on: row.get(2)?,
+            expires_on: row.get(3)?,
+            certificate_type: row.get(4)?,
+            profile: row.get(5)?,
+            validity_in_years: row.get(6)?,
+            key_algorithm: row.get(7)?,
+            dns_names: serde_json::from_str(&dns_names).unwrap_or_default(),
+            upn: row.get(9)?,
+            ca_id: row.get(10)?,
+            used_on: row.get(11)?,
+            certificate_id: row.get(12)?,
+            user_id: row.get(13)?,
+            ..Default::default()
+        })
+    }
+
+    /// Retrieve an unused enrollment token that has not expired, without consuming it
+    pub(crate) fn find_enrollment_token(&self, token_hash: &str, now: i64) -> Result<Option<EnrollmentToken>, rusqlite::Error> {
+        self.connection.query_row(
+            "SELECT id, name, created_on, expires_on, type, profile, validity_in_years, key_algorithm, dns_names, upn, ca_id, used_on, certificate_id, user_id FROM enrollment_tokens WHERE token_hash = ?1 AND used_on IS NULL AND expires_on > ?2",
+            params![token_hash, now],
+            Self::enrollment_token_from_row
+        ).optional()
+    }
+
+    /// Mark an unused enrollment token that has not expired as used and return it
+    /// The check and the update are a single statement, so a token can only be consumed once
+    pub(crate) fn consume_enrollment_token(&self, token_hash: &str, now: i64) -> Result<EnrollmentToken, ApiError> {
+        let consumed = self.connection.execute(
+            "UPDATE enrollment_tokens SET used_on = ?1 WHERE token_hash = ?2 AND used_on IS NULL AND expires_on > ?1",
+            params![now, token_hash]
+        )?;
+        if consumed != 1 {
+            return Err(ApiError::Unauthorized(Some("Enrollment token is invalid, expired or already used".to_string())))
+        }
+
+        Ok(self.connection.query_row(
+            "SELECT id, name, created_on, expires_on, type, profile, validity_in_years, key_algorithm, dns_names, upn, ca_id, used_on, certificate_id, user_id FROM enrollment_tokens WHERE token_hash = ?1",
+            params![token_hash],
+            Self::enrollment_token_from_row
+        )?)
+    }
+
+    /// Set the certificate issued for an enrollment token
+    pub(crate) fn update_enrollment_token(&self, id: i64, certificate_id: i64) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "UPDATE enrollment_tokens SET certificate_id = ?1 WHERE id = ?2",
+            params![certificate_id, id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Delete an enrollment token from the database
+    pub(crate) fn delete_enrollment_token(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "DELETE FROM enrollment_tokens WHERE id=?1",
             params![id]
         )?;
 
@@ -220,13 +1195,20 @@ impl VaulTLSDB {
     }
 
     /// Delete a user from the database
-    pub(crate) fn delete_user(&self, id: i64) -> Result<(), rusqlite::Error> {
-        self.connection.execute(
-            "DELETE FROM users WHERE id=?1",
-            params![id]
-        )?;
-
-        Ok(())
+    pub(crate) fn delete_user(&self, id: i64, deleted_on: i64) -> Result<(), ApiError> {
+        self.transaction(|db| {
+            // The certificates of the user are deleted like single certificates and detached,
+            // as the user_id foreign key would otherwise delete them with the user
+            db.connection.execute(
+                &format!("{}, user_id = NULL WHERE user_id = ?3", DELETE_USER_CERTS),
+                params![deleted_on, RevocationReason::CessationOfOperation as u8, id]
+            )?;
+            db.connection.execute(
+                "DELETE FROM users WHERE id=?1",
+                params![id]
+            )?;
+            Ok(((), true))
+        })
     }
 
     /// Update a user in the database
@@ -363,6 +1345,66 @@ impl VaulTLSDB {
         }
     }
 
+    /// Retrieve all issuance policies from the database
+    pub(crate) fn get_all_policies(&self) -> Result<Vec<IssuancePolicy>, ApiError> {
+        let mut stmt = self.connection.prepare("SELECT id, name, enabled, user_id, role, type, rules FROM issuance_policies")?;
+        let rows = stmt.query([])?;
+        let policies: Vec<(IssuancePolicy, String)> = rows.map(|row| {
+                Ok((IssuancePolicy {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    enabled: row.get(2)?,
+                    user_id: row.get(3)?,
+                    role: row.get(4)?,
+                    certificate_type: row.get(5)?,
+                    ..Default::default()
+                }, row.get(6)?))
+            })
+            .collect()?;
+
+        policies.into_iter()
+            .map(|(mut policy, rules)| {
+                policy.rules = serde_json::from_str(&rules)
+                    .map_err(|e| ApiError::Other(format!("Failed to parse rules of policy {}: {}", policy.id, e)))?;
+                Ok(policy)
+            })
+            .collect()
+    }
+
+    /// Insert a new issuance policy into the database
+    /// Adds id to the IssuancePolicy struct
+    pub(crate) fn insert_policy(&self, policy: &mut IssuancePolicy) -> Result<(), ApiError> {
+        let rules = serde_json::to_string(&policy.rules).map_err(|e| ApiError::Other(e.to_string()))?;
+        self.connection.execute(
+            "INSERT INTO issuance_policies (name, enabled, user_id, role, type, rules) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![policy.name, policy.enabled, policy.user_id, policy.role.map(|role| role as u8), policy.certificate_type.map(|t| t as u8), rules],
+        )?;
+
+        policy.id = self.connection.last_insert_rowid();
+        Ok(())
+    }
+
+    /// Update an issuance policy in the database
+    pub(crate) fn update_policy(&self, policy: &IssuancePolicy) -> Result<(), ApiError> {
+        let rules = serde_json::to_string(&policy.rules).map_err(|e| ApiError::Other(e.to_string()))?;
+        self.connection.execute(
+            "UPDATE issuance_policies SET name = ?1, enabled = ?2, user_id = ?3, role = ?4, type = ?5, rules = ?6 WHERE id=?7",
+            params![policy.name, policy.enabled, policy.user_id, policy.role.map(|role| role as u8), policy.certificate_type.map(|t| t as u8), rules, policy.id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Delete an issuance policy from the database
+    pub(crate) fn delete_policy(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "DELETE FROM issuance_policies WHERE id=?1",
+            params![id]
+        )?;
+
+        Ok(())
+    }
+
     /// Check if the database is setup
     /// Returns true if the database contains at least one user
     /// Returns false if the database is empty
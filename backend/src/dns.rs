@@ -0,0 +1,157 @@
+use std::env;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use openssl::rand::rand_bytes;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+use crate::ApiError;
+
+const DNS_TIMEOUT: Duration = Duration::from_secs(5);
+const TYPE_TXT: u16 = 16;
+const CLASS_IN: u16 = 1;
+
+/// Looks up the TXT records of a name with the recursive resolver of the system.
+/// The resolver can be overridden with `VAULTLS_DNS_RESOLVER`, e.g. `1.1.1.1` or `10.0.0.53:5353`.
+/// Returns an empty list if the name does not exist.
+pub(crate) async fn lookup_txt(name: &str) -> Result<Vec<String>, ApiError> {
+    let resolver = get_resolver()?;
+    let mut id = [0u8; 2];
+    rand_bytes(&mut id)?;
+    let query = create_query(u16::from_be_bytes(id), name)?;
+
+    let mut response = query_udp(resolver, &query).await?;
+    // Truncated responses are repeated over TCP
+    if response.get(2).is_some_and(|flags| flags & 0x02 != 0) {
+        response = query_tcp(resolver, &query).await?;
+    }
+    parse_response(u16::from_be_bytes(id), &response)
+        .map_err(|e| ApiError::Other(format!("Invalid DNS response for {}: {}", name, e)))
+}
+
+/// Returns the configured resolver or the first name server of /etc/resolv.conf.
+fn get_resolver() -> Result<SocketAddr, ApiError> {
+    let resolver = match env::var("VAULTLS_DNS_RESOLVER") {
+        Ok(resolver) => resolver,
+        Err(_) => fs::read_to_string("/etc/resolv.conf").unwrap_or_default()
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("nameserver").map(|server| server.trim().to_string()))
+            .ok_or(ApiError::Other("No DNS resolver configured".to_string()))?
+    };
+    resolver.parse::<SocketAddr>()
+        .or_else(|_| resolver.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+        .map_err(|_| ApiError::Other(format!("Invalid DNS resolver {}", resolver)))
+}
+
+async fn query_udp(resolver: SocketAddr, query: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let bind = match resolver {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind).await.map_err(dns_error)?;
+    socket.connect(resolver).await.map_err(dns_error)?;
+    socket.send(query).await.map_err(dns_error)?;
+
+    let mut buffer = vec![0u8; 4096];
+    let length = timeout(DNS_TIMEOUT, socket.recv(&mut buffer)).await
+        .map_err(|_| ApiError::Other("DNS query timed out".to_string()))?
+        .map_err(dns_error)?;
+    buffer.truncate(length);
+    Ok(buffer)
+}
+
+async fn query_tcp(resolver: SocketAddr, query: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(resolver).await?;
+        stream.write_all(&(query.len() as u16).to_be_bytes()).await?;
+        stream.write_all(query).await?;
+        let length = stream.read_u16().await?;
+        let mut buffer = vec![0u8; length as usize];
+        stream.read_exact(&mut buffer).await?;
+        Ok::<Vec<u8>, std::io::Error>(buffer)
+    };
+    timeout(DNS_TIMEOUT, exchange).await
+        .map_err(|_| ApiError::Other("DNS query timed out".to_string()))?
+        .map_err(dns_error)
+}
+
+fn dns_error(e: std::io::Error) -> ApiError {
+    ApiError::Other(format!("DNS query failed: {}", e))
+}
+
+/// Creates a recursive TXT query for the name.
+fn create_query(id: u16, name: &str) -> Result<Vec<u8>, ApiError> {
+    let mut query = Vec::with_capacity(18 + name.len());
+    query.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question
+    query.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(ApiError::BadRequest(format!("{} is not a valid DNS name", name)))
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&TYPE_TXT.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// Extracts the TXT records of the answer section, the strings of a record are concatenated.
+fn parse_response(id: u16, response: &[u8]) -> Result<Vec<String>, &'static str> {
+    let read_u16 = |position: usize| response.get(position..position + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or("message is truncated");
+
+    if read_u16(0)? != id || response[2] & 0x80 == 0 {
+        return Err("response does not match the query")
+    }
+    match response[3] & 0x0f {
+        0 => {}
+        3 => return Ok(Vec::new()),
+        _ => return Err("resolver returned an error")
+    }
+
+    let questions = read_u16(4)?;
+    let answers = read_u16(6)?;
+    let mut position = 12;
+    for _ in 0..questions {
+        position = skip_name(response, position).ok_or("invalid question")? + 4;
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        position = skip_name(response, position).ok_or("invalid answer")?;
+        let record_type = read_u16(position)?;
+        let data_length = read_u16(position + 8)? as usize;
+        let data = response.get(position + 10..position + 10 + data_length).ok_or("message is truncated")?;
+        position += 10 + data_length;
+
+        if record_type != TYPE_TXT {
+            continue
+        }
+        let mut record = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let length = data[offset] as usize;
+            record.extend_from_slice(data.get(offset + 1..offset + 1 + length).ok_or("invalid TXT record")?);
+            offset += 1 + length;
+        }
+        records.push(String::from_utf8_lossy(&record).into_owned());
+    }
+    Ok(records)
+}
+
+/// Returns the position after a possibly compressed name.
+fn skip_name(message: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        let length = *message.get(position)?;
+        match length {
+            0 => return Some(position + 1),
+            length if length & 0xc0 == 0xc0 => return Some(position + 2),
+            length => position += 1 + length as usize
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{PKey, Private};
+use openssl::stack::Stack;
+use openssl::x509::X509;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::prelude::FromDer;
+use crate::{apply_password_rule, ApiError, User};
+use crate::cert::{asn1_time_to_millis, get_common_name, get_password, get_serial_hex, Certificate};
+use crate::data::api::{EasyRsaImportRequest, EasyRsaImportResponse, EasyRsaImportResult};
+use crate::data::enums::CertificateType;
+use crate::db::VaulTLSDB;
+use crate::lint;
+use crate::settings::Settings;
+
+/// Certificate issued by the easy-rsa CA with its key, if the key is in the PKI.
+struct IssuedCertificate {
+    file: String,
+    cert: X509,
+    key: Option<PKey<Private>>,
+}
+
+/// Imports an easy-rsa PKI directory: the CA, all issued certificates and their revocation status.
+/// Everything is imported in a single transaction, which is only committed if this is not a dry run.
+/// Returns the import results with the lint findings of the CA and every imported certificate.
+pub(crate) fn import(
+    db: &VaulTLSDB,
+    settings: &Settings,
+    request: &EasyRsaImportRequest
+) -> Result<EasyRsaImportResponse, ApiError> {
+    let pki = Path::new(request.path.trim());
+    if !pki.join("ca.crt").is_file() {
+        return Err(ApiError::BadRequest(format!("{} is not an easy-rsa PKI directory", pki.display())))
+    }
+    let passphrase = request.key_passphrase.as_deref().unwrap_or_default();
+    let system_generated_password = apply_password_rule(settings, request.system_generated_password, &request.pkcs12_password)?;
+
+    let ca_cert = read_certificate(&pki.join("ca.crt"))?;
+    let ca_key = read_private_key(&pki.join("private").join("ca.key"), passphrase)
+        .ok_or(ApiError::BadRequest("CA key could not be read, is the passphrase correct?".to_string()))?;
+    if !ca_cert.public_key()?.public_eq(&ca_key) {
+        return Err(ApiError::BadRequest("CA key does not match the CA certificate".to_string()))
+    }
+
+    let revocations = read_revocations(&pki.join("index.txt"))?;
+    let certificates = read_issued_certificates(pki, &ca_cert, passphrase)?;
+
+    db.transaction(|db| {
+        let mut ca = Certificate {
+            name: get_common_name(&ca_cert).unwrap_or_default(),
+            created_on: asn1_time_to_millis(ca_cert.not_before())?,
+            valid_until: asn1_time_to_millis(ca_cert.not_after())?,
+            certificate_type: CertificateType::CA,
+            cert: ca_cert.to_der()?,
+            key: ca_key.private_key_to_der()?,
+            ..Default::default()
+        };
+        if db.get_all_ca()?.iter().any(|existing| existing.cert == ca.cert) {
+            return Err(ApiError::BadRequest("CA is already present".to_string()))
+        }
+        db.insert_ca(&mut ca, request.purpose.unwrap_or_default(), request.active)?;
+        let ca_findings = lint::lint_stored_certificate(&ca)?.findings;
+
+        let fallback_user = match request.fallback_user_id {
+            Some(user_id) => Some(db.get_user(user_id)?),
+            None => None
+        };
+        let users = db.get_all_user()?;
+
+        let mut results = Vec::new();
+        for issued in &certificates {
+            let name = get_common_name(&issued.cert).unwrap_or_else(|| issued.file.clone());
+            let serial = get_serial_hex(&issued.cert)?;
+            let revoked_on = revocations.get(&serial).copied();
+            let user = match_user(&issued.cert, &users).or(fallback_user.as_ref());
+            let mut result = EasyRsaImportResult {
+                file: issued.file.clone(),
+                name: name.clone(),
+                serial,
+                user_id: user.map(|user| user.id),
+                has_key: issued.key.is_some(),
+                revoked: revoked_on.is_some(),
+                ..Default::default()
+            };
+
+            let Some(user) = user else {
+                result.error = Some(format!("No user matches the CN or e-mail of {}", name));
+                results.push(result);
+                continue
+            };
+            let password = get_password(system_generated_password, &request.pkcs12_password);
+            let mut cert = create_certificate(&ca, &ca_cert, issued, name, user.id, password)?;
+            db.insert_user_cert(&mut cert)?;
+            result.findings = lint::lint_stored_certificate(&cert)?.findings;
+            if let Some(revoked_on) = revoked_on {
+                db.revoke_user_cert(cert.id, revoked_on)?;
+            }
+            result.certificate_id = Some(cert.id);
+            results.push(result);
+        }
+
+        let committed = !request.dry_run;
+        Ok((EasyRsaImportResponse { dry_run: request.dry_run, committed, ca_id: ca.id, ca_findings, results }, committed))
+    })
+}
+
+/// Creates the VaulTLS certificate of an issued certificate, packed as PKCS#12 with its key if available.
+fn create_certificate(
+    ca: &Certificate,
+    ca_cert: &X509,
+    issued: &IssuedCertificate,
+    name: String,
+    user_id: i64,
+    password: String
+) -> Result<Certificate, ApiError> {
+    let mut ca_stack = Stack::new()?;
+    ca_stack.push(ca_cert.clone())?;
+    let mut builder = Pkcs12::builder();
+    builder.name(&name).cert(&issued.cert).ca(ca_stack);
+    if let Some(key) = &issued.key {
+        builder.pkey(key);
+    }
+
+    Ok(Certificate {
+        name,
+        created_on: asn1_time_to_millis(issued.cert.not_before())?,
+        valid_from: asn1_time_to_millis(issued.cert.not_before())?,
+        valid_until: asn1_time_to_millis(issued.cert.not_after())?,
+        certificate_type: get_certificate_type(&issued.cert)?,
+        user_id,
+        serial: get_serial_hex(&issued.cert)?,
+        pkcs12: builder.build2(&password)?.to_der()?,
+        pkcs12_password: password,
+        ca_id: ca.id,
+        ..Default::default()
+    })
+}
+
+/// Collects the certificates issued by the CA from issued/, certs_by_serial/ and revoked/certs_by_serial/,
+/// and the matching keys from private/ and revoked/private_by_serial/. Certificates are unique by serial.
+fn read_issued_certificates(pki: &Path, ca_cert: &X509, passphrase: &str) -> Result<Vec<IssuedCertificate>, ApiError> {
+    let ca_public_key = ca_cert.public_key()?;
+    let keys: Vec<PKey<Private>> = list_files(&pki.join("private"), "key").into_iter()
+        .chain(list_files(&pki.join("revoked").join("private_by_serial"), "key"))
+        .filter_map(|(_, path)| read_private_key(&path, passphrase))
+        .collect();
+
+    let mut serials = Vec::new();
+    let mut certificates = Vec::new();
+    let files = list_files(&pki.join("issued"), "crt").into_iter()
+        .chain(list_files(&pki.join("certs_by_serial"), "pem"))
+        .chain(list_files(&pki.join("revoked").join("certs_by_serial"), "crt"));
+    for (file, path) in files {
+        let Ok(cert) = read_certificate(&path) else { continue };
+        let serial = get_serial_hex(&cert)?;
+        if serials.contains(&serial) || !cert.verify(&ca_public_key).unwrap_or(false) {
+            continue
+        }
+        let public_key = cert.public_key()?;
+        let key = keys.iter().find(|key| public_key.public_eq(*key)).cloned();
+        serials.push(serial);
+        certificates.push(IssuedCertificate { file, cert, key });
+    }
+    Ok(certificates)
+}
+
+/// Reads the revocation times by serial from the easy-rsa (OpenSSL CA) index.
+/// Revoked entries have the form `R <expiry> <revocation time>[,reason] <serial> unknown <subject>`.
+fn read_revocations(index: &Path) -> Result<HashMap<String, i64>, ApiError> {
+    let Ok(index) = fs::read_to_string(index) else { return Ok(HashMap::new()) };
+
+    let mut revocations = HashMap::new();
+    for line in index.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let ["R", _, revoked, serial, ..] = fields.as_slice() else { continue };
+        let revoked_on = revoked.split(',').next().unwrap_or_default();
+        let revoked_on = Asn1Time::from_str(revoked_on)
+            .map_err(|_| ApiError::BadRequest(format!("Invalid revocation time {} in index.txt", revoked_on)))?;
+        let serial = BigNum::from_hex_str(serial)
+            .map_err(|_| ApiError::BadRequest(format!("Invalid serial {} in index.txt", serial)))?;
+        revocations.insert(serial.to_hex_str()?.to_string(), asn1_time_to_millis(&revoked_on)?);
+    }
+    Ok(revocations)
+}
+
+/// Finds the user by an e-mail address of the certificate, otherwise by the CN as name or e-mail.
+fn match_user<'a>(cert: &X509, users: &'a [User]) -> Option<&'a User> {
+    let mut emails: Vec<String> = cert.subject_alt_names().iter().flatten()
+        .filter_map(|name| name.email().map(str::to_string))
+        .collect();
+    emails.extend(cert.subject_name().entries_by_nid(Nid::PKCS9_EMAILADDRESS)
+        .filter_map(|entry| entry.data().to_string().ok()));
+    let common_name = get_common_name(cert);
+
+    emails.iter()
+        .find_map(|email| users.iter().find(|user| user.email.eq_ignore_ascii_case(email)))
+        .or_else(|| common_name.and_then(|common_name| users.iter()
+            .find(|user| user.email.eq_ignore_ascii_case(&common_name) || user.name.eq_ignore_ascii_case(&common_name))))
+}
+
+/// easy-rsa server and serverClient certificates have serverAuth, all others are treated as client certificates.
+fn get_certificate_type(cert: &X509) -> Result<CertificateType, ApiError> {
+    let der = cert.to_der()?;
+    let (_, parsed) = X509Certificate::from_der(&der)
+        .map_err(|e| ApiError::Other(format!("Failed to decode certificate: {}", e)))?;
+    let server_auth = parsed.extended_key_usage().ok().flatten().is_some_and(|eku| eku.value.server_auth);
+    Ok(if server_auth { CertificateType::Server } else { CertificateType::Client })
+}
+
+fn read_certificate(path: &Path) -> Result<X509, ApiError> {
+    let pem = fs::read(path).map_err(|e| ApiError::BadRequest(format!("Failed to read {}: {}", path.display(), e)))?;
+    X509::from_pem(&pem).map_err(|_| ApiError::BadRequest(format!("{} is not a PEM certificate", path.display())))
+}
+
+/// Reads a PEM private key, encrypted keys are decrypted with the passphrase.
+/// Returns None if the key cannot be read or decrypted.
+fn read_private_key(path: &Path, passphrase: &str) -> Option<PKey<Private>> {
+    let pem = fs::read(path).ok()?;
+    PKey::private_key_from_pem_passphrase(&pem, passphrase.as_bytes()).ok()
+}
+
+/// Lists the files with the extension in a directory sorted by name, missing directories are empty.
+fn list_files(directory: &Path, extension: &str) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(directory) else { return Vec::new() };
+    let mut files: Vec<(String, PathBuf)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|file_extension| file_extension == extension))
+        .filter_map(|path| Some((path.file_name()?.to_string_lossy().into_owned(), path)))
+        .collect();
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    files
+}
@@ -0,0 +1,108 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::rand::rand_bytes;
+use serde::Serialize;
+use crate::ApiError;
+use crate::auth::token_auth::hash_api_token;
+use crate::constants::{ENROLLMENT_TOKEN_DEFAULT_HOURS, ENROLLMENT_TOKEN_MAX_HOURS};
+use crate::data::api::{CreateEnrollmentTokenRequest, CreateUserCertificateRequest};
+use crate::data::enums::{CertificateProfile, CertificateType, KeyAlgorithm};
+
+/// Prefix of enrollment tokens, distinct from API tokens since they cannot authenticate.
+const TOKEN_PREFIX: &str = "vtenroll_";
+
+/// One-time token with which a device receives a certificate without user credentials.
+/// Only the hash of the token is stored, the token itself is returned once on creation.
+#[derive(Serialize, Clone, Default)]
+pub(crate) struct EnrollmentToken {
+    pub(crate) id: i64,
+    /// Name of the certificate issued for the token
+    pub(crate) name: String,
+    pub(crate) created_on: i64,
+    /// The token cannot be redeemed after this
+    pub(crate) expires_on: i64,
+    pub(crate) certificate_type: CertificateType,
+    pub(crate) profile: CertificateProfile,
+    pub(crate) validity_in_years: Option<u64>,
+    pub(crate) key_algorithm: Option<KeyAlgorithm>,
+    pub(crate) dns_names: Vec<String>,
+    pub(crate) upn: Option<String>,
+    pub(crate) ca_id: Option<i64>,
+    pub(crate) used_on: Option<i64>,
+    pub(crate) certificate_id: Option<i64>,
+    pub(crate) user_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) token: Option<String>,
+    #[serde(skip)]
+    pub(crate) token_hash: String,
+}
+
+impl EnrollmentToken {
+    /// The certificate request issued when the token is redeemed.
+    pub(crate) fn to_certificate_request(&self, csr: Option<String>) -> CreateUserCertificateRequest {
+        CreateUserCertificateRequest {
+            cert_name: self.name.clone(),
+            validity_in_years: self.validity_in_years,
+            user_id: self.user_id,
+            notify_user: None,
+            system_generated_password: true,
+            pkcs12_password: None,
+            cert_type: Some(self.certificate_type),
+            dns_names: Some(self.dns_names.clone()),
+            key_algorithm: self.key_algorithm,
+            spiffe_path: None,
+            profile: Some(self.profile),
+            upn: self.upn.clone(),
+            csr,
+            ca_id: self.ca_id,
+            not_before: None,
+        }
+    }
+}
+
+/// Creates a new random enrollment token for the certificate described by the request.
+pub(crate) fn create_enrollment_token(payload: &CreateEnrollmentTokenRequest) -> Result<EnrollmentToken, ApiError> {
+    if payload.cert_name.trim().is_empty() {
+        return Err(ApiError::BadRequest("Certificate name is required".to_string()))
+    }
+    let certificate_type = payload.cert_type.unwrap_or_default();
+    if !matches!(certificate_type, CertificateType::Client | CertificateType::Server) {
+        return Err(ApiError::BadRequest("Enrollment is only available for client and server certificates".to_string()))
+    }
+    let expires_in_hours = payload.expires_in_hours.unwrap_or(ENROLLMENT_TOKEN_DEFAULT_HOURS);
+    if expires_in_hours == 0 || expires_in_hours > ENROLLMENT_TOKEN_MAX_HOURS {
+        return Err(ApiError::BadRequest(format!("Expiry must be between 1 and {} hours", ENROLLMENT_TOKEN_MAX_HOURS)))
+    }
+
+    let mut secret = [0u8; 32];
+    rand_bytes(&mut secret)?;
+    let token = format!("{}{}", TOKEN_PREFIX, secret.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+
+    let created_on = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    Ok(EnrollmentToken {
+        name: payload.cert_name.trim().to_string(),
+        created_on,
+        expires_on: created_on + expires_in_hours as i64 * 60 * 60 * 1000,
+        certificate_type,
+        profile: payload.profile.unwrap_or_default(),
+        validity_in_years: payload.validity_in_years,
+        key_algorithm: payload.key_algorithm,
+        dns_names: match certificate_type {
+            CertificateType::Server => payload.dns_names.clone().unwrap_or_default(),
+            _ => Vec::new()
+        },
+        upn: payload.upn.clone(),
+        ca_id: payload.ca_id,
+        user_id: payload.user_id,
+        token_hash: hash_enrollment_token(&token)?,
+        token: Some(token),
+        ..Default::default()
+    })
+}
+
+/// Returns the hash under which a token is stored, or an error if it is no enrollment token.
+pub(crate) fn hash_enrollment_token(token: &str) -> Result<String, ApiError> {
+    if !token.trim().starts_with(TOKEN_PREFIX) {
+        return Err(ApiError::BadRequest("Not an enrollment token".to_string()))
+    }
+    hash_api_token(token)
+}
@@ -0,0 +1,165 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::pkcs12::Pkcs12;
+use openssl::x509::{X509Crl, X509};
+use crate::ApiError;
+use crate::cert::Certificate;
+
+/// Directory below the FreeRADIUS certdir holding the exported certificates.
+const CERT_DIRECTORY: &str = "certs/vaultls";
+const TAR_BLOCK_SIZE: usize = 512;
+/// Group of the FreeRADIUS daemon on Debian and Ubuntu, it is radiusd on Red Hat based distributions.
+pub(crate) const DEFAULT_GROUP: &str = "freerad";
+
+/// A CA trusted for client certificates with the CRL of its revoked serials.
+pub(crate) struct TrustedCa {
+    pub(crate) ca: Certificate,
+    pub(crate) crl: Vec<u8>,
+}
+
+/// Creates a tar archive to be extracted in the FreeRADIUS configuration directory (raddb).
+/// It contains an EAP module using EAP-TLS with the server certificate, the CA chain and the CRLs.
+/// If a PKI URL is configured, update-crl.sh refreshes the CRLs, which are only valid for a day.
+/// Files are owned by root and the group, so that the daemon can read the key and module, which are not world-readable.
+pub(crate) fn create_export(server: &Certificate, cas: &[TrustedCa], pki_url: Option<&str>, group: &str) -> Result<Vec<u8>, ApiError> {
+    if group.is_empty() || group.len() > 31 || !group.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(ApiError::BadRequest(format!("Invalid group {}", group)))
+    }
+
+    let parsed = Pkcs12::from_der(&server.pkcs12)?.parse2(&server.pkcs12_password)?;
+    let (Some(server_cert), Some(server_key)) = (parsed.cert, parsed.pkey) else {
+        return Err(ApiError::Other("PKCS#12 does not contain a certificate and key".to_string()))
+    };
+
+    let mut ca_pem = Vec::new();
+    let mut crl_pem = Vec::new();
+    for trusted in cas {
+        ca_pem.extend(X509::from_der(&trusted.ca.cert)?.to_pem()?);
+        crl_pem.extend(X509Crl::from_der(&trusted.crl)?.to_pem()?);
+    }
+    // OpenSSL loads the CRLs for check_crl from the CA file
+    let ca_crl_pem = [ca_pem.as_slice(), crl_pem.as_slice()].concat();
+
+    let mut files = vec![
+        (format!("{}/server.pem", CERT_DIRECTORY), 0o644, server_cert.to_pem()?),
+        (format!("{}/server.key", CERT_DIRECTORY), 0o640, server_key.private_key_to_pem_pkcs8()?),
+        (format!("{}/ca.pem", CERT_DIRECTORY), 0o644, ca_pem),
+        (format!("{}/crl.pem", CERT_DIRECTORY), 0o644, crl_pem),
+        (format!("{}/ca-crl.pem", CERT_DIRECTORY), 0o644, ca_crl_pem),
+        ("mods-available/eap".to_string(), 0o640, create_eap_module(&server.name).into_bytes()),
+    ];
+    if let Some(pki_url) = pki_url {
+        let ca_ids: Vec<i64> = cas.iter().map(|trusted| trusted.ca.id).collect();
+        files.push(("update-crl.sh".to_string(), 0o755, create_update_script(pki_url, &ca_ids).into_bytes()));
+    }
+
+    create_tar(&files, group)
+}
+
+/// Creates the FreeRADIUS 3 eap module. Clients must present a certificate issued by one of the CAs that is not revoked.
+fn create_eap_module(server_name: &str) -> String {
+    format!(r#"# EAP-TLS configuration generated by VaulTLS for {server_name}
+# Enable it with: ln -sf ../mods-available/eap mods-enabled/eap
+
+eap {{
+	default_eap_type = tls
+	timer_expire = 60
+	ignore_unknown_eap_types = no
+	cisco_accounting_username_bug = no
+	max_sessions = ${{max_requests}}
+
+	tls-config tls-common {{
+		private_key_file = ${{certdir}}/{dir}/server.key
+		certificate_file = ${{certdir}}/{dir}/server.pem
+
+		# CA certificates followed by their CRLs
+		ca_file = ${{certdir}}/{dir}/ca-crl.pem
+		check_crl = yes
+		check_all_crl = yes
+
+		# Only accept certificates whose CN is the EAP identity
+		# check_cert_cn = %{{User-Name}}
+
+		cipher_list = "DEFAULT"
+		cipher_server_preference = no
+		tls_min_version = "1.2"
+		tls_max_version = "1.3"
+
+		cache {{
+			enable = no
+		}}
+
+		verify {{
+		}}
+
+		ocsp {{
+			enable = no
+		}}
+	}}
+
+	tls {{
+		tls = tls-common
+	}}
+}}
+"#, server_name = server_name, dir = CERT_DIRECTORY)
+}
+
+/// Creates a script downloading the current CRLs of the CAs.
+fn create_update_script(pki_url: &str, ca_ids: &[i64]) -> String {
+    let ca_ids: Vec<String> = ca_ids.iter().map(i64::to_string).collect();
+    format!(r#"#!/bin/sh
+# Refreshes the VaulTLS CRLs, which are valid for one day. Run it from cron at least daily
+# and reload FreeRADIUS afterwards, e.g. systemctl reload freeradius
+set -e
+cd "$(dirname "$0")/{dir}"
+: > crl.pem.new
+for id in {ca_ids}; do
+	curl -fsS "{pki_url}/api/certificates/ca/$id/crl" | openssl crl -inform DER -outform PEM >> crl.pem.new
+done
+mv crl.pem.new crl.pem
+cat ca.pem crl.pem > ca-crl.pem.new
+mv ca-crl.pem.new ca-crl.pem
+"#, dir = CERT_DIRECTORY, ca_ids = ca_ids.join(" "), pki_url = pki_url)
+}
+
+/// Creates an uncompressed ustar archive of regular files with their path, mode and content.
+fn create_tar(files: &[(String, u32, Vec<u8>)], group: &str) -> Result<Vec<u8>, ApiError> {
+    let mtime = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let mut tar = Vec::new();
+    for (path, mode, content) in files {
+        if path.len() >= 100 {
+            return Err(ApiError::Other(format!("Path {} is too long for the archive", path)))
+        }
+        let mut header = [0u8; TAR_BLOCK_SIZE];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        write_octal(&mut header[100..108], *mode as u64);
+        write_octal(&mut header[108..116], 0);
+        write_octal(&mut header[116..124], 0);
+        write_octal(&mut header[124..136], content.len() as u64);
+        write_octal(&mut header[136..148], mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[265..269].copy_from_slice(b"root");
+        header[297..297 + group.len()].copy_from_slice(group.as_bytes());
+
+        // The checksum is calculated with the checksum field set to spaces
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|byte| *byte as u32).sum();
+        write_octal(&mut header[148..155], checksum as u64);
+
+        tar.extend_from_slice(&header);
+        tar.extend_from_slice(content);
+        tar.resize(tar.len().next_multiple_of(TAR_BLOCK_SIZE), 0);
+    }
+    // The archive ends with two empty blocks
+    tar.resize(tar.len() + 2 * TAR_BLOCK_SIZE, 0);
+    Ok(tar)
+}
+
+/// Writes a zero padded, NUL terminated octal number filling the field.
+fn write_octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{:0width$o}", value, width = width);
+    field[..width].copy_from_slice(digits.as_bytes());
+    field[width] = 0;
+}
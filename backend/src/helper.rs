@@ -3,7 +3,8 @@ use std::path::Path;
 use argon2::{Argon2, PasswordHasher};
 use argon2::password_hash::rand_core::OsRng;
 use argon2::password_hash::{PasswordHashString, SaltString};
-use serde::Serializer;
+use openssl::rand::rand_bytes;
+use serde::{Deserialize, Deserializer, Serializer};
 use crate::data::error::ApiError;
 
 /// Hashes a password using Argon2
@@ -22,7 +23,7 @@ pub fn hash_password_string(password: &Option<String>) -> Result<Option<Password
     Ok(match password {
         Some(password) => {
             let password_hash = hash_password(password)?;
-            Some(PasswordHashString::new(&*password_hash)?)
+            Some(PasswordHashString::new(&password_hash)?)
         },
         None => None,
     })
@@ -36,6 +37,35 @@ where
     s.serialize_bool(password_hash.is_some())
 }
 
+/// Deserializes a list given either as array or as comma-separated string
+pub fn deserialize_comma_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum CommaList {
+        List(Vec<String>),
+        Text(String),
+    }
+
+    let list = match CommaList::deserialize(deserializer)? {
+        CommaList::List(list) => list,
+        CommaList::Text(text) => text.split(',').map(str::to_string).collect(),
+    };
+    Ok(list.into_iter().map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect())
+}
+
+/// Returns a random (version 4) UUID
+pub fn random_uuid() -> Result<String, ApiError> {
+    let mut bytes = [0u8; 16];
+    rand_bytes(&mut bytes)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    Ok(format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+}
+
 /// Get secret
 pub fn get_secret(name: &str) -> anyhow::Result<String> {
     if let Ok(env_var) = env::var(name) {
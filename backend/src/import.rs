@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use openssl::pkey::{PKey, Private};
+use serde::Deserialize;
+use crate::{issue_certificate, ApiError, Certificate, User};
+use crate::data::api::{BulkImportRequest, BulkImportResponse, CreateUserCertificateRequest, ImportRow, ImportRowResult};
+use crate::data::enums::{CertificateType, KeyAlgorithm, UserRole};
+use crate::db::VaulTLSDB;
+use crate::helper::hash_password_string;
+use crate::settings::Settings;
+
+/// Row of a CSV import. DNS names are separated by `;` or whitespace.
+#[derive(Deserialize)]
+struct CsvImportRow {
+    user_name: String,
+    user_email: String,
+    role: Option<UserRole>,
+    password: Option<String>,
+    cert_name: Option<String>,
+    cert_type: Option<CertificateType>,
+    validity_in_years: Option<u64>,
+    dns_names: Option<String>,
+    key_algorithm: Option<KeyAlgorithm>,
+    spiffe_path: Option<String>,
+    system_generated_password: Option<bool>,
+    pkcs12_password: Option<String>,
+}
+
+impl From<CsvImportRow> for ImportRow {
+    fn from(row: CsvImportRow) -> Self {
+        ImportRow {
+            user_name: row.user_name,
+            user_email: row.user_email,
+            role: row.role,
+            password: row.password,
+            cert_name: row.cert_name,
+            cert_type: row.cert_type,
+            validity_in_years: row.validity_in_years,
+            dns_names: row.dns_names
+                .map(|dns_names| dns_names.split(|c: char| c == ';' || c.is_whitespace())
+                    .filter(|dns_name| !dns_name.is_empty())
+                    .map(str::to_string)
+                    .collect())
+                .unwrap_or_default(),
+            key_algorithm: row.key_algorithm,
+            spiffe_path: row.spiffe_path,
+            system_generated_password: row.system_generated_password,
+            pkcs12_password: row.pkcs12_password,
+        }
+    }
+}
+
+/// Returns the rows of an import, either from the CSV or the JSON rows.
+pub(crate) fn parse_rows(request: &BulkImportRequest) -> Result<Vec<ImportRow>, ApiError> {
+    let rows = match &request.csv {
+        Some(csv) => parse_csv(csv)?,
+        None => request.rows.clone()
+    };
+    if rows.is_empty() {
+        return Err(ApiError::BadRequest("Import does not contain any rows".to_string()))
+    }
+    Ok(rows)
+}
+
+/// Creates the users and certificates of an import in a single transaction.
+/// Rows with a certificate use the key at the same index, which was taken from the key pool before.
+/// The transaction is only committed if every row succeeded and this is not a dry run.
+/// Returns the result of every row and the users with their new certificates.
+pub(crate) fn import(
+    db: &VaulTLSDB,
+    settings: &Settings,
+    request: &BulkImportRequest,
+    rows: &[ImportRow],
+    private_keys: Vec<Option<PKey<Private>>>
+) -> Result<(BulkImportResponse, Vec<(User, Certificate)>), ApiError> {
+    db.transaction(|db| {
+        // Users are matched by e-mail, including users created by earlier rows
+        let mut known_users: HashMap<String, i64> = db.get_all_user()?
+            .into_iter()
+            .map(|user| (user.email.to_lowercase(), user.id))
+            .collect();
+
+        let mut results = Vec::new();
+        let mut issued = Vec::new();
+        for (index, (row, private_key)) in rows.iter().zip(private_keys).enumerate() {
+            let mut result = ImportRowResult { row: index + 1, ..Default::default() };
+            match import_row(db, settings, row, private_key, &mut known_users, &mut result) {
+                Ok(Some(issued_cert)) => issued.push(issued_cert),
+                Ok(None) => {}
+                Err(e) => result.error = Some(error_message(e))
+            }
+            results.push(result);
+        }
+
+        let committed = !request.dry_run && results.iter().all(|result| result.error.is_none());
+        if !committed {
+            issued.clear();
+        }
+        Ok(((BulkImportResponse { dry_run: request.dry_run, committed, results }, issued), committed))
+    })
+}
+
+/// Creates the user of a row if it does not exist yet and issues its certificate, if any.
+fn import_row(
+    db: &VaulTLSDB,
+    settings: &Settings,
+    row: &ImportRow,
+    private_key: Option<PKey<Private>>,
+    known_users: &mut HashMap<String, i64>,
+    result: &mut ImportRowResult
+) -> Result<Option<(User, Certificate)>, ApiError> {
+    let email = row.user_email.trim();
+    if row.user_name.trim().is_empty() || !email.contains('@') {
+        return Err(ApiError::BadRequest("User name and a valid e-mail address are required".to_string()))
+    }
+
+    let user = match known_users.get(&email.to_lowercase()) {
+        Some(id) => db.get_user(*id)?,
+        None => {
+            let mut user = User {
+                id: -1,
+                name: row.user_name.trim().to_string(),
+                email: email.to_string(),
+                password_hash: hash_password_string(&row.password)?,
+                oidc_id: None,
+                role: row.role.unwrap_or(UserRole::User)
+            };
+            db.add_user(&mut user)?;
+            known_users.insert(email.to_lowercase(), user.id);
+            result.user_created = true;
+            user
+        }
+    };
+    result.user_id = Some(user.id);
+
+    let Some(cert_name) = &row.cert_name else { return Ok(None) };
+    let payload = CreateUserCertificateRequest {
+        cert_name: cert_name.clone(),
+        validity_in_years: row.validity_in_years,
+        user_id: user.id,
+        notify_user: None,
+        system_generated_password: row.system_generated_password.unwrap_or(false),
+        pkcs12_password: row.pkcs12_password.clone(),
+        cert_type: row.cert_type,
+        dns_names: Some(row.dns_names.clone()),
+        key_algorithm: row.key_algorithm,
+        spiffe_path: row.spiffe_path.clone(),
+        profile: None,
+        upn: None,
+        csr: None,
+        ca_id: None,
+        not_before: None,
+    };
+    let cert = issue_certificate(db, settings, &payload, &user, private_key)?;
+    result.certificate_id = Some(cert.id);
+
+    Ok(Some((user, cert)))
+}
+
+/// Returns the plain message of an error for the row result.
+fn error_message(error: ApiError) -> String {
+    match error {
+        ApiError::BadRequest(message) | ApiError::Other(message) => message,
+        ApiError::Forbidden(Some(message)) | ApiError::Unauthorized(Some(message)) => message,
+        ApiError::Database(e) => e.to_string(),
+        ApiError::OpenSsl(e) => e.to_string(),
+        e => e.to_string()
+    }
+}
+
+/// Parses CSV with a header row naming the columns.
+fn parse_csv(csv: &str) -> Result<Vec<ImportRow>, ApiError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(csv.as_bytes());
+
+    reader.deserialize::<CsvImportRow>()
+        .enumerate()
+        .map(|(index, row)| row
+            .map(ImportRow::from)
+            .map_err(|e| ApiError::BadRequest(format!("CSV row {}: {}", index + 1, e))))
+        .collect()
+}
@@ -0,0 +1,159 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use openssl::pkey::{PKey, Private};
+use tokio::task;
+use crate::ApiError;
+use crate::cert::generate_key;
+use crate::data::api::KeyPoolStatus;
+use crate::data::enums::KeyAlgorithm;
+use crate::settings::KeyPoolLimit;
+
+/// Keys generated ahead of time, so issuance does not wait for slow RSA key generation.
+/// A background thread refills the pool up to the limit of each key algorithm.
+#[derive(Clone, Debug)]
+pub(crate) struct KeyPool {
+    inner: Arc<KeyPoolInner>,
+}
+
+#[derive(Debug)]
+struct KeyPoolInner {
+    state: Mutex<KeyPoolState>,
+    refill: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct KeyPoolState {
+    pools: Vec<AlgorithmPool>,
+}
+
+#[derive(Debug)]
+struct AlgorithmPool {
+    key_algorithm: KeyAlgorithm,
+    limit: usize,
+    keys: Vec<PKey<Private>>,
+    generated_on_demand: u64,
+}
+
+impl KeyPool {
+    /// Creates the pool and starts the background thread filling it.
+    pub(crate) fn start(limits: &[KeyPoolLimit]) -> Self {
+        let pool = KeyPool {
+            inner: Arc::new(KeyPoolInner {
+                state: Mutex::new(KeyPoolState::default()),
+                refill: Condvar::new(),
+            })
+        };
+        pool.set_limits(limits);
+
+        let worker = pool.clone();
+        thread::Builder::new()
+            .name("key-pool".to_string())
+            .spawn(move || worker.fill())
+            .expect("Failed starting key pool thread");
+        pool
+    }
+
+    /// Replaces the limits, keys above a reduced limit are dropped.
+    pub(crate) fn set_limits(&self, limits: &[KeyPoolLimit]) {
+        let mut state = self.lock();
+        for limit in limits {
+            match state.pools.iter_mut().find(|pool| pool.key_algorithm == limit.key_algorithm) {
+                Some(pool) => pool.limit = limit.size,
+                None => state.pools.push(AlgorithmPool {
+                    key_algorithm: limit.key_algorithm,
+                    limit: limit.size,
+                    keys: Vec::new(),
+                    generated_on_demand: 0,
+                })
+            }
+        }
+        for pool in state.pools.iter_mut() {
+            if !limits.iter().any(|limit| limit.key_algorithm == pool.key_algorithm) {
+                pool.limit = 0;
+            }
+            pool.keys.truncate(pool.limit);
+        }
+        self.inner.refill.notify_one();
+    }
+
+    /// Takes a ready key of the algorithm from the pool.
+    /// If the pool is empty, the key is generated on a blocking thread. Since that can take a while
+    /// for RSA keys, keys are taken before the database is locked.
+    pub(crate) async fn take(&self, key_algorithm: KeyAlgorithm) -> Result<PKey<Private>, ApiError> {
+        match self.pop(key_algorithm) {
+            Some(key) => Ok(key),
+            None => task::spawn_blocking(move || generate_key(key_algorithm)).await
+                .map_err(|e| ApiError::Other(format!("Key generation failed: {}", e)))?
+                .map_err(ApiError::from)
+        }
+    }
+
+    /// Removes a ready key of the algorithm from the pool and wakes the background thread to replace it.
+    fn pop(&self, key_algorithm: KeyAlgorithm) -> Option<PKey<Private>> {
+        let mut state = self.lock();
+        let key = match state.pools.iter_mut().find(|pool| pool.key_algorithm == key_algorithm) {
+            Some(pool) => {
+                let key = pool.keys.pop();
+                if key.is_none() && pool.limit > 0 {
+                    pool.generated_on_demand += 1;
+                }
+                key
+            }
+            None => None
+        };
+        drop(state);
+        self.inner.refill.notify_one();
+        key
+    }
+
+    /// Returns the number of ready keys per key algorithm.
+    pub(crate) fn status(&self) -> Vec<KeyPoolStatus> {
+        self.lock().pools.iter()
+            .map(|pool| KeyPoolStatus {
+                key_algorithm: pool.key_algorithm,
+                available: pool.keys.len(),
+                limit: pool.limit,
+                generated_on_demand: pool.generated_on_demand,
+            })
+            .collect()
+    }
+
+    /// Generates keys for the emptiest pool until all pools are full, then waits for keys to be taken.
+    fn fill(&self) {
+        let mut state = self.lock();
+        loop {
+            let next = state.pools.iter()
+                .filter(|pool| pool.keys.len() < pool.limit)
+                .min_by_key(|pool| pool.keys.len())
+                .map(|pool| pool.key_algorithm);
+
+            let Some(key_algorithm) = next else {
+                state = self.inner.refill.wait(state).unwrap_or_else(|e| e.into_inner());
+                continue
+            };
+
+            drop(state);
+            let key = generate_key(key_algorithm);
+            state = self.lock();
+
+            match key {
+                Ok(key) => {
+                    if let Some(pool) = state.pools.iter_mut().find(|pool| pool.key_algorithm == key_algorithm && pool.keys.len() < pool.limit) {
+                        pool.keys.push(key);
+                    }
+                }
+                Err(e) => {
+                    println!("Failed generating key for the key pool: {}", e);
+                    drop(state);
+                    thread::sleep(Duration::from_secs(10));
+                    state = self.lock();
+                }
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, KeyPoolState> {
+        self.inner.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,40 @@
+//! Types shared by the VaulTLS backend and the `vaultls-agent` binary.
+//! The library does not depend on Rocket, so that the agent does not link the web server.
+
+use serde::{Deserialize, Serialize};
+
+/// Renewal of a certificate with the identity of the current one. It is either authenticated by a session or
+/// API token, or by the current certificate together with a signature with its key.
+#[derive(Serialize, Deserialize, Default)]
+pub struct RenewCertificateRequest {
+    /// PEM encoded CSR for the new key, a key is generated if not provided
+    pub csr: Option<String>,
+    /// PEM encoded certificate that is renewed
+    pub certificate: Option<String>,
+    /// UNIX timestamp in milliseconds at which the signature was created
+    pub timestamp: Option<i64>,
+    /// Base64 encoded SHA-256 signature of signed_data with the key of the certificate
+    pub signature: Option<String>,
+}
+
+impl RenewCertificateRequest {
+    /// Data signed with the key of the current certificate, binding the signature to certificate id, time and CSR.
+    pub fn signed_data(&self, id: i64) -> Vec<u8> {
+        format!("vaultls-renew:{}:{}:{}", id, self.timestamp.unwrap_or_default(), self.csr.as_deref().unwrap_or_default()).into_bytes()
+    }
+}
+
+/// Certificate, chain and generated key in PEM, as returned to devices and hosts.
+#[derive(Serialize, Deserialize)]
+pub struct IssuedCertificateResponse {
+    pub id: i64,
+    pub name: String,
+    pub serial: String,
+    pub valid_until: i64,
+    /// PEM encoded certificate
+    pub certificate: String,
+    /// PEM encoded CA certificate
+    pub chain: String,
+    /// PEM encoded PKCS#8 private key, only set if the key was generated
+    pub private_key: Option<String>,
+}
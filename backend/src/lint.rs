@@ -0,0 +1,390 @@
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::nid::Nid;
+use openssl::pkey::Id;
+use openssl::x509::{X509, X509Ref};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::{GeneralName, ParsedExtension};
+use x509_parser::oid_registry::{OID_X509_EXT_AUTHORITY_KEY_IDENTIFIER, OID_X509_EXT_SUBJECT_KEY_IDENTIFIER};
+use x509_parser::prelude::FromDer;
+use x509_parser::x509::X509Version;
+use crate::ApiError;
+use crate::cert::{get_pkcs12_certificate, get_serial_hex, Certificate};
+use crate::data::api::{LintFinding, LintReport};
+use crate::data::enums::{CertificateType, LintSeverity};
+use crate::settings::Settings;
+
+/// Maximum validity of TLS server certificates accepted by browsers (CA/Browser Forum Baseline Requirements).
+const MAX_SERVER_VALIDITY_DAYS: i64 = 398;
+/// Serial numbers must contain at least 64 bits of randomness (CA/Browser Forum Baseline Requirements).
+const MIN_SERIAL_BYTES: usize = 8;
+/// Signature algorithms based on MD2, MD5 or SHA-1.
+const WEAK_SIGNATURE_ALGORITHMS: [&str; 5] = [
+    "1.2.840.113549.1.1.2",
+    "1.2.840.113549.1.1.4",
+    "1.2.840.113549.1.1.5",
+    "1.2.840.10045.4.1",
+    "1.2.840.10040.4.3",
+];
+
+/// Findings of a single lint run.
+#[derive(Default)]
+struct Findings(Vec<LintFinding>);
+
+impl Findings {
+    fn add(&mut self, severity: LintSeverity, code: &str, message: impl Into<String>) {
+        self.0.push(LintFinding { severity, code: code.to_string(), message: message.into() });
+    }
+
+    fn error(&mut self, code: &str, message: impl Into<String>) { self.add(LintSeverity::Error, code, message) }
+    fn warning(&mut self, code: &str, message: impl Into<String>) { self.add(LintSeverity::Warning, code, message) }
+    fn notice(&mut self, code: &str, message: impl Into<String>) { self.add(LintSeverity::Notice, code, message) }
+}
+
+/// Checks a certificate against RFC 5280 and common best practices.
+/// The certificate type selects the profile of leaf certificates. If unset, CAs are detected from
+/// the basic constraints and leaf certificates are only checked against the generic rules.
+pub(crate) fn lint_certificate(cert: &X509Ref, certificate_type: Option<CertificateType>) -> Result<LintReport, ApiError> {
+    let der = cert.to_der()?;
+    let (_, parsed) = X509Certificate::from_der(&der)
+        .map_err(|e| ApiError::BadRequest(format!("Failed to decode certificate: {}", e)))?;
+
+    let certificate_type = match certificate_type {
+        Some(certificate_type) => Some(certificate_type),
+        None => parsed.is_ca().then_some(CertificateType::CA)
+    };
+    let is_ca = certificate_type == Some(CertificateType::CA);
+
+    let mut findings = Findings::default();
+    lint_serial(&parsed, &mut findings);
+    lint_signature(&parsed, &mut findings);
+    lint_validity(&parsed, certificate_type, &mut findings);
+    lint_key(cert, &mut findings)?;
+    lint_extensions(&parsed, is_ca, &mut findings);
+    lint_basic_constraints(&parsed, is_ca, &mut findings);
+    lint_key_usage(&parsed, is_ca, cert.public_key()?.id(), &mut findings);
+    if !is_ca {
+        lint_extended_key_usage(&parsed, certificate_type, &mut findings);
+    }
+    lint_names(&parsed, is_ca, certificate_type, &mut findings);
+
+    let mut findings = findings.0;
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+
+    Ok(LintReport {
+        passed: findings.iter().all(|finding| finding.severity != LintSeverity::Error),
+        subject: parsed.subject().to_string(),
+        serial: get_serial_hex(cert)?,
+        certificate_id: None,
+        findings,
+    })
+}
+
+/// Lints a newly issued certificate if blocking is enabled and rejects it on errors.
+pub(crate) fn check_issuance(settings: &Settings, cert: &X509Ref, certificate_type: CertificateType) -> Result<(), ApiError> {
+    if !settings.lint_blocking() {
+        return Ok(())
+    }
+
+    let report = lint_certificate(cert, Some(certificate_type))?;
+    let errors: Vec<String> = report.findings.into_iter()
+        .filter(|finding| finding.severity == LintSeverity::Error)
+        .map(|finding| finding.message)
+        .collect();
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(ApiError::BadRequest(format!("Certificate failed linting: {}", errors.join("; "))))
+    }
+}
+
+/// Lints a stored CA or the leaf certificate of a stored user certificate.
+pub(crate) fn lint_stored_certificate(cert: &Certificate) -> Result<LintReport, ApiError> {
+    let x509 = match cert.certificate_type {
+        CertificateType::CA => X509::from_der(&cert.cert)?,
+        _ => get_pkcs12_certificate(&cert.pkcs12, &cert.pkcs12_password)?
+    };
+    let mut report = lint_certificate(&x509, Some(cert.certificate_type))?;
+    report.certificate_id = Some(cert.id);
+    Ok(report)
+}
+
+/// RFC 5280 4.1.2.2: the serial must be a positive integer of at most 20 octets.
+fn lint_serial(cert: &X509Certificate, findings: &mut Findings) {
+    let raw = cert.raw_serial();
+    if raw.first().is_some_and(|byte| byte & 0x80 != 0) {
+        findings.error("serial_number_negative", "Serial number is negative");
+    }
+    if raw.len() > 20 {
+        findings.error("serial_number_too_long", format!("Serial number is {} octets long, at most 20 are allowed", raw.len()));
+    }
+
+    let significant = raw.iter().skip_while(|byte| **byte == 0).count();
+    if significant == 0 {
+        findings.error("serial_number_zero", "Serial number is zero");
+    } else if significant < MIN_SERIAL_BYTES {
+        findings.warning("serial_number_low_entropy", format!("Serial number has fewer than {} bits", MIN_SERIAL_BYTES * 8));
+    }
+}
+
+/// RFC 5280 4.1.1.2: the outer and inner signature algorithm must match.
+fn lint_signature(cert: &X509Certificate, findings: &mut Findings) {
+    let algorithm = &cert.signature_algorithm.algorithm;
+    if *algorithm != cert.tbs_certificate.signature.algorithm {
+        findings.error("signature_algorithm_mismatch", "Signature algorithm differs from the one in the signed certificate body");
+    }
+    if WEAK_SIGNATURE_ALGORITHMS.contains(&algorithm.to_id_string().as_str()) {
+        findings.error("weak_signature_algorithm", format!("Signature algorithm {} uses a broken hash function", algorithm.to_id_string()));
+    }
+}
+
+fn lint_validity(cert: &X509Certificate, certificate_type: Option<CertificateType>, findings: &mut Findings) {
+    let not_before = cert.validity().not_before.timestamp();
+    let not_after = cert.validity().not_after.timestamp();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    if not_after <= not_before {
+        findings.error("validity_period_invalid", "Certificate expires before it becomes valid");
+    }
+    if not_after < now {
+        findings.warning("certificate_expired", "Certificate has expired");
+    }
+    let validity_days = (not_after - not_before) / (60 * 60 * 24);
+    if certificate_type == Some(CertificateType::Server) && validity_days > MAX_SERVER_VALIDITY_DAYS {
+        findings.warning("server_validity_too_long", format!("Validity of {} days exceeds the {} days accepted by browsers", validity_days, MAX_SERVER_VALIDITY_DAYS));
+    }
+}
+
+fn lint_key(cert: &X509Ref, findings: &mut Findings) -> Result<(), ApiError> {
+    let public_key = cert.public_key()?;
+    match public_key.id() {
+        Id::RSA => {
+            let rsa = public_key.rsa()?;
+            if public_key.bits() < 2048 {
+                findings.error("rsa_key_too_small", format!("RSA key of {} bits is smaller than 2048 bits", public_key.bits()));
+            }
+            if rsa.e().to_dec_str()?.parse::<u64>().is_ok_and(|e| e < 65537 || e % 2 == 0) {
+                findings.warning("rsa_exponent", "RSA public exponent should be odd and at least 65537");
+            }
+        }
+        Id::EC => {
+            let curve = public_key.ec_key()?.group().curve_name();
+            if !matches!(curve, Some(Nid::X9_62_PRIME256V1 | Nid::SECP384R1 | Nid::SECP521R1)) {
+                findings.warning("ec_curve_not_recommended", "Elliptic curve is not one of P-256, P-384 or P-521");
+            }
+        }
+        Id::ED25519 | Id::ED448 => {}
+        _ => findings.warning("key_algorithm_not_recommended", "Public key algorithm is not RSA, ECDSA or EdDSA")
+    }
+    Ok(())
+}
+
+/// RFC 5280 4.2: extensions require v3, must not repeat and must be understood if critical.
+fn lint_extensions(cert: &X509Certificate, is_ca: bool, findings: &mut Findings) {
+    if !cert.extensions().is_empty() && cert.version() != X509Version::V3 {
+        findings.error("version_not_v3", "Certificate has extensions but is not a version 3 certificate");
+    }
+
+    let mut seen = HashSet::new();
+    for extension in cert.extensions() {
+        if !seen.insert(extension.oid.to_id_string()) {
+            findings.error("duplicate_extension", format!("Extension {} is present more than once", extension.oid.to_id_string()));
+        }
+        match extension.parsed_extension() {
+            ParsedExtension::ParseError { error } => {
+                findings.error("malformed_extension", format!("Extension {} is malformed: {}", extension.oid.to_id_string(), error));
+            }
+            ParsedExtension::UnsupportedExtension { oid } if extension.critical => {
+                findings.warning("unknown_critical_extension", format!("Critical extension {} is not widely supported", oid.to_id_string()));
+            }
+            _ => {}
+        }
+    }
+
+    let self_issued = cert.subject().as_raw() == cert.issuer().as_raw();
+    match cert.get_extension_unique(&OID_X509_EXT_AUTHORITY_KEY_IDENTIFIER) {
+        Ok(Some(extension)) if extension.critical => {
+            findings.error("authority_key_identifier_critical", "Authority key identifier must not be critical");
+        }
+        Ok(None) if !self_issued => {
+            findings.error("authority_key_identifier_missing", "Authority key identifier is required for certificates issued by a CA");
+        }
+        _ => {}
+    }
+    match cert.get_extension_unique(&OID_X509_EXT_SUBJECT_KEY_IDENTIFIER) {
+        Ok(Some(extension)) if extension.critical => {
+            findings.error("subject_key_identifier_critical", "Subject key identifier must not be critical");
+        }
+        Ok(None) if is_ca => {
+            findings.error("subject_key_identifier_missing", "Subject key identifier is required for CA certificates");
+        }
+        Ok(None) => {
+            findings.notice("subject_key_identifier_missing", "Subject key identifier should be present");
+        }
+        _ => {}
+    }
+}
+
+/// RFC 5280 4.2.1.9: CAs must have critical basic constraints with the cA flag.
+fn lint_basic_constraints(cert: &X509Certificate, is_ca: bool, findings: &mut Findings) {
+    let Ok(basic_constraints) = cert.basic_constraints() else { return };
+    match (basic_constraints, is_ca) {
+        (None, true) => findings.error("ca_basic_constraints_missing", "Basic constraints are required for CA certificates"),
+        (Some(basic_constraints), true) => {
+            if !basic_constraints.critical {
+                findings.error("ca_basic_constraints_not_critical", "Basic constraints of CA certificates must be critical");
+            }
+            if !basic_constraints.value.ca {
+                findings.error("ca_flag_missing", "Basic constraints of a CA certificate do not set the cA flag");
+            }
+        }
+        (None, false) => findings.notice("basic_constraints_missing", "Basic constraints should be present"),
+        (Some(basic_constraints), false) => {
+            if basic_constraints.value.ca {
+                findings.error("leaf_is_ca", "Basic constraints of a leaf certificate set the cA flag");
+            }
+            if !basic_constraints.critical {
+                findings.warning("basic_constraints_not_critical", "Basic constraints should be critical");
+            }
+        }
+    }
+}
+
+/// RFC 5280 4.2.1.3 and RFC 5480 3: key usages must match the certificate role and key type.
+fn lint_key_usage(cert: &X509Certificate, is_ca: bool, key_type: Id, findings: &mut Findings) {
+    let key_usage = match cert.key_usage() {
+        Ok(Some(key_usage)) => key_usage,
+        Ok(None) if is_ca => return findings.error("ca_key_usage_missing", "Key usage is required for CA certificates"),
+        Ok(None) => return findings.warning("key_usage_missing", "Key usage should be present"),
+        Err(e) => return findings.error("malformed_extension", format!("Key usage is malformed: {}", e))
+    };
+
+    if !key_usage.critical {
+        findings.warning("key_usage_not_critical", "Key usage should be critical");
+    }
+    if is_ca && !key_usage.value.key_cert_sign() {
+        findings.error("ca_key_cert_sign_missing", "Key usage of a CA certificate does not include keyCertSign");
+    }
+    if !is_ca && (key_usage.value.key_cert_sign() || key_usage.value.crl_sign()) {
+        findings.error("leaf_key_cert_sign", "Key usage of a leaf certificate includes keyCertSign or cRLSign");
+    }
+    if key_type == Id::EC && (key_usage.value.key_encipherment() || key_usage.value.data_encipherment()) {
+        findings.warning("ec_key_encipherment", "Key usage allows encipherment, which ECDSA keys cannot perform");
+    }
+}
+
+fn lint_extended_key_usage(cert: &X509Certificate, certificate_type: Option<CertificateType>, findings: &mut Findings) {
+    let extended_key_usage = match cert.extended_key_usage() {
+        Ok(Some(extended_key_usage)) => extended_key_usage,
+        Ok(None) => return findings.warning("extended_key_usage_missing", "Extended key usage should be present"),
+        Err(e) => return findings.error("malformed_extension", format!("Extended key usage is malformed: {}", e))
+    };
+    let eku = extended_key_usage.value;
+
+    if eku.any {
+        findings.warning("any_extended_key_usage", "Extended key usage includes anyExtendedKeyUsage");
+    }
+    match certificate_type {
+        Some(CertificateType::Client) if !eku.client_auth => {
+            findings.error("purpose_mismatch", "Client certificate does not allow TLS client authentication");
+        }
+        Some(CertificateType::Server) if !eku.server_auth => {
+            findings.error("purpose_mismatch", "Server certificate does not allow TLS server authentication");
+        }
+        Some(CertificateType::Svid) if !eku.server_auth && !eku.client_auth => {
+            findings.error("purpose_mismatch", "SVID does not allow TLS authentication");
+        }
+        Some(CertificateType::Timestamping) => {
+            // RFC 3161 2.3
+            let only_time_stamping = eku.time_stamping && !eku.any && !eku.server_auth && !eku.client_auth
+                && !eku.code_signing && !eku.email_protection && !eku.ocsp_signing && eku.other.is_empty();
+            if !only_time_stamping || !extended_key_usage.critical {
+                findings.error("purpose_mismatch", "Timestamping certificate must have timeStamping as its only, critical extended key usage");
+            }
+        }
+        _ => {}
+    }
+}
+
+/// RFC 5280 4.1.2.6 and 4.2.1.6: subject and SANs must identify the certificate holder consistently.
+fn lint_names(cert: &X509Certificate, is_ca: bool, certificate_type: Option<CertificateType>, findings: &mut Findings) {
+    let common_names: Vec<&str> = cert.subject().iter_common_name()
+        .filter_map(|cn| cn.as_str().ok())
+        .collect();
+    if common_names.len() > 1 {
+        findings.warning("multiple_common_names", "Subject contains more than one common name");
+    }
+    if let Some(cn) = common_names.iter().find(|cn| cn.chars().count() > 64) {
+        findings.error("common_name_too_long", format!("Common name {} exceeds 64 characters", cn));
+    }
+
+    let subject_empty = cert.subject().iter().next().is_none();
+    if subject_empty && is_ca {
+        findings.error("ca_subject_empty", "Subject of a CA certificate must not be empty");
+    }
+
+    let san = match cert.subject_alternative_name() {
+        Ok(san) => san,
+        Err(e) => return findings.error("malformed_extension", format!("Subject alternative name is malformed: {}", e))
+    };
+    let names = san.as_ref().map(|san| san.value.general_names.as_slice()).unwrap_or_default();
+    match &san {
+        None if subject_empty => findings.error("subject_empty_without_san", "Subject is empty but there is no subject alternative name"),
+        Some(san) if subject_empty && !san.critical => {
+            findings.error("san_not_critical", "Subject alternative name must be critical if the subject is empty");
+        }
+        Some(_) if names.is_empty() => findings.error("san_empty", "Subject alternative name does not contain any names"),
+        _ => {}
+    }
+
+    let mut dns_names = Vec::new();
+    for name in names {
+        match name {
+            GeneralName::DNSName(dns_name) => {
+                if !is_valid_dns_name(dns_name) {
+                    findings.error("invalid_dns_name", format!("DNS name {} is not a valid host name", dns_name));
+                }
+                dns_names.push(dns_name.to_ascii_lowercase());
+            }
+            GeneralName::IPAddress(ip) if ip.len() != 4 && ip.len() != 16 => {
+                findings.error("invalid_ip_address", "IP address SAN is neither IPv4 nor IPv6");
+            }
+            _ => {}
+        }
+    }
+
+    match certificate_type {
+        Some(CertificateType::Server) => {
+            if !names.iter().any(|name| matches!(name, GeneralName::DNSName(_) | GeneralName::IPAddress(_))) {
+                findings.error("server_san_missing", "Server certificate has no DNS name or IP address SAN");
+            } else if let Some(cn) = common_names.iter().find(|cn| !dns_names.contains(&cn.to_ascii_lowercase())) {
+                findings.warning("common_name_not_in_san", format!("Common name {} is not one of the DNS names", cn));
+            }
+        }
+        Some(CertificateType::Client) if !names.iter().any(|name| matches!(name, GeneralName::RFC822Name(_))) => {
+            findings.warning("client_email_missing", "Client certificate has no e-mail address SAN");
+        }
+        Some(CertificateType::Svid) => {
+            let uris: Vec<&&str> = names.iter()
+                .filter_map(|name| match name { GeneralName::URI(uri) => Some(uri), _ => None })
+                .collect();
+            if uris.len() != 1 || !uris[0].starts_with("spiffe://") {
+                findings.error("svid_uri_san", "SVID must contain exactly one SPIFFE ID as URI SAN");
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Checks the syntax of a host name, allowing a wildcard as the leftmost label.
+pub(crate) fn is_valid_dns_name(dns_name: &str) -> bool {
+    let dns_name = dns_name.strip_prefix("*.").unwrap_or(dns_name);
+    !dns_name.is_empty()
+        && dns_name.len() <= 253
+        && dns_name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
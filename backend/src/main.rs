@@ -5,10 +5,13 @@ use std::{env, fs};
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use rocket::fairing::AdHoc;
-use rocket::http::{Cookie, CookieJar, Method, SameSite};
+use rocket::http::{ContentType, Cookie, CookieJar, Method, SameSite};
 use rocket::serde::json::Json;
 use rocket::State;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::pkey::{PKey, Private};
+use openssl::x509::{X509Req, X509};
 use argon2::password_hash::PasswordHashString;
 use rocket::response::Redirect;
 use rocket::tokio::sync::Mutex;
@@ -17,9 +20,9 @@ use serde::{Deserialize, Serialize};
 use cert::create_ca;
 use db::VaulTLSDB;
 use settings::Settings;
-use crate::cert::{get_pem, save_ca, Certificate};
-use crate::data::api::{CallbackQuery, ChangePasswordRequest, CreateUserCertificateRequest, CreateUserRequest, DownloadResponse, IsSetupResponse, LoginRequest, SetupRequest};
-use crate::data::enums::{CertificateType, UserRole};
+use crate::cert::{get_pem, save_ca, Certificate, CertificateAuthority, IssuanceOptions, ShortLivedCertificate, ShortLivedKey};
+use crate::data::api::{BulkImportRequest, CreateApiTokenRequest, BulkImportResponse, CreateEnrollmentTokenRequest, EnrollRequest, IssuedCertificateResponse, RenewCertificateRequest, CreateServerCertificateRequest, CreateShortLivedCertificateRequest, CreateSshCertificateRequest, CreateSshPublicKeyRequest, ShortLivedCertificateResponse, CallbackQuery, CertificatePasswordRequest, CertificatePreview, ChangePasswordRequest, CreateCaBackupRequest, CreateCaRequest, CreateTrustAnchorRequest, CreateUserCertificateRequest, CreateUserRequest, DownloadResponse, EasyRsaImportRequest, EasyRsaImportResponse, IsSetupResponse, KeyPoolStatus, LintCertificateRequest, LintReport, LoginRequest, RestoreCaBackupRequest, SetupRequest, UpdateCaRequest, UpdateTrustAnchorRequest, VaultCertificateData, VaultCertificateRequest, VaultResponse, VaultRevokeData, VaultRevokeRequest, VerificationReport, VerifyCertificateRequest};
+use crate::data::enums::{CaPurpose, CertificateProfile, CertificateType, SshCertificateType, UserRole, ValidationStatus};
 use crate::data::error::ApiError;
 use crate::data::enums::PasswordRule;
 use crate::helper::{get_secret, hash_password, hash_password_string};
@@ -27,8 +30,17 @@ use crate::notification::{MailMessage, Mailer};
 use auth::oidc_auth::OidcAuth;
 use crate::auth::password_auth::verify_password;
 use crate::auth::session_auth::{generate_token, Authenticated};
-use crate::constants::{API_PORT, DB_FILE_PATH, VAULTLS_VERSION};
+use crate::auth::token_auth;
+use crate::auth::token_auth::{ApiToken, TokenAuthenticated};
+use crate::constants::{API_PORT, DB_FILE_PATH, NOT_BEFORE_MAX_BACKDATE_SECS, NOT_BEFORE_MAX_FUTURE_SECS, SHORT_LIVED_MAX_HOURS, SSH_CERT_MAX_DAYS, VAULTLS_VERSION};
 use crate::settings::FrontendSettings;
+use crate::policy::{IssuanceContext, IssuancePolicy};
+use crate::ssh::{SshCertificate, SshCertificateOptions, SshPublicKey};
+use crate::backup::CaBackup;
+use crate::keypool::KeyPool;
+use crate::validation::ServerCertificateRequest;
+use crate::trust::TrustAnchor;
+use crate::enrollment::EnrollmentToken;
 
 mod db;
 mod cert;
@@ -38,11 +50,35 @@ mod data;
 mod helper;
 mod auth;
 mod constants;
+mod verify;
+mod policy;
+mod ocsp;
+mod import;
+mod ssh;
+mod spiffe;
+mod tsa;
+mod lint;
+mod preview;
+mod backup;
+mod keypool;
+mod easyrsa;
+mod dns;
+mod validation;
+mod freeradius;
+mod openvpn;
+mod mobileconfig;
+mod onc;
+mod vault;
+mod trust;
+mod ctlog;
+mod enrollment;
+mod renewal;
 
 #[derive(Clone)]
 struct AppState {
     db: Arc<Mutex<VaulTLSDB>>,
     settings: Arc<Mutex<Settings>>,
+    key_pool: KeyPool,
     oidc: Arc<Mutex<Option<OidcAuth>>>,
     mailer: Arc<Mutex<Option<Mailer>>>
 }
@@ -69,125 +105,1553 @@ fn version() -> &'static str {
     VAULTLS_VERSION
 }
 
+#[get("/api/server/key-pool")]
+async fn get_key_pool_status(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<KeyPoolStatus>>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    Ok(Json(state.key_pool.status()))
+}
+
 #[get("/api/certificates")]
 async fn get_certificates(
     state: &State<AppState>,
     authentication: Authenticated
-) -> Result<Json<Vec<Certificate>>, ApiError> {
+) -> Result<Json<Vec<Certificate>>, ApiError> {
+    let db = state.db.lock().await;
+    let user_id= if authentication.claims.role == UserRole::Admin {
+            None
+        } else {
+            Some(authentication.claims.id)
+        };
+    let certificates = db.get_all_user_cert(user_id)?;
+    Ok(Json(certificates))
+}
+
+#[post("/api/certificates", format = "json", data = "<payload>")]
+async fn create_user_certificate(
+    state: &State<AppState>,
+    payload: Json<CreateUserCertificateRequest>,
+    authentication: Authenticated
+) -> Result<Json<Certificate>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let private_key = take_key(&state.key_pool, &payload).await?;
+
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+
+    let user = db.get_user(payload.user_id)?;
+    let cert = issue_certificate(&db, &settings, &payload, &user, private_key)?;
+
+    if Some(true) == payload.notify_user {
+        notify_user(state.mailer.clone(), user, cert.clone());
+    }
+
+    Ok(Json(cert))
+}
+
+#[post("/api/certificates/preview", format = "json", data = "<payload>")]
+async fn preview_user_certificate(
+    state: &State<AppState>,
+    payload: Json<CreateUserCertificateRequest>,
+    authentication: Authenticated
+) -> Result<Json<CertificatePreview>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+
+    let (user, ca, mut prepared, policy_violations, lint_blocking) = {
+        let settings = state.settings.lock().await;
+        let db = state.db.lock().await;
+        let user = db.get_user(payload.user_id)?;
+        let prepared = PreparedIssuance::new(&settings, &payload, None)?;
+        let policy_violations = policy::evaluate_issuance(&db, &prepared.context(&payload, &user))?;
+        let ca = db.get_issuing_ca(payload.ca_id, prepared.certificate_type)?;
+        (user, ca, prepared, policy_violations, settings.lint_blocking())
+    };
+
+    let leaf_key_algorithm = prepared.options.public_key.is_none().then_some(prepared.options.key_algorithm);
+    let (leaf_key, signing_key) = preview::create_throwaway_keys(leaf_key_algorithm, X509::from_der(&ca.cert)?.public_key()?).await?;
+    prepared.options.private_key = leaf_key;
+    let signing_ca = Certificate { key: signing_key.private_key_to_der()?, ..ca.clone() };
+
+    let cert = prepared.sign(&signing_ca, &payload, &user)?;
+    let leaf = cert::get_pkcs12_certificate(&cert.pkcs12, &cert.pkcs12_password)?;
+    let lint_report = lint::lint_certificate(&leaf, Some(prepared.certificate_type))?;
+    let issuable = policy_violations.is_empty() && (lint_report.passed || !lint_blocking);
+
+    let preview = preview::create_preview(&leaf, &ca, prepared.options.key_algorithm, issuable, policy_violations, lint_report.findings)?;
+    Ok(Json(preview))
+}
+
+#[get("/api/certificates/server-requests")]
+async fn get_server_certificate_requests(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<ServerCertificateRequest>>, ApiError> {
+    let db = state.db.lock().await;
+    let user_id = if authentication.claims.role == UserRole::Admin {
+            None
+        } else {
+            Some(authentication.claims.id)
+        };
+    let requests = db.get_all_server_cert_requests(user_id)?;
+    Ok(Json(requests))
+}
+
+#[post("/api/certificates/server-requests", format = "json", data = "<payload>")]
+async fn create_server_certificate_request(
+    state: &State<AppState>,
+    payload: Json<CreateServerCertificateRequest>,
+    authentication: Authenticated
+) -> Result<Json<ServerCertificateRequest>, ApiError> {
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+
+    let user = db.get_user(authentication.claims.id)?;
+    let mut request = validation::create_request(user.id, &payload)?;
+
+    // Reject requests that could not be issued even after a successful validation
+    let certificate_request = request.to_certificate_request();
+    let prepared = PreparedIssuance::new(&settings, &certificate_request, None)?;
+    policy::check_issuance(&db, &prepared.context(&certificate_request, &user))?;
+
+    db.insert_server_cert_request(&mut request)?;
+    Ok(Json(request))
+}
+
+#[post("/api/certificates/server-requests/<id>/validate")]
+async fn validate_server_certificate_request(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<Json<ServerCertificateRequest>, ApiError> {
+    let request = state.db.lock().await.get_server_cert_request(id)?;
+    if request.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    if request.status != ValidationStatus::Pending {
+        return Err(ApiError::BadRequest("Server certificate request is not pending".to_string()))
+    }
+    if request.is_expired() {
+        state.db.lock().await.update_server_cert_request(id, ValidationStatus::Invalid, None)?;
+        return Err(ApiError::BadRequest("Server certificate request has expired".to_string()))
+    }
+
+    // DNS and HTTP checks run without holding the database lock
+    let mut results = Vec::new();
+    for validation in request.validations.iter().filter(|validation| validation.status != ValidationStatus::Valid) {
+        results.push((validation.id, validation::validate(validation).await));
+    }
+
+    // The key is only taken if the certificate can be issued after these checks
+    let private_key = match results.iter().all(|(_, result)| result.is_ok()) {
+        true => take_key(&state.key_pool, &request.to_certificate_request()).await?,
+        false => None
+    };
+
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    let validated_on = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    for (validation_id, result) in results {
+        match result {
+            Ok(()) => db.update_domain_validation(validation_id, ValidationStatus::Valid, Some(validated_on), None)?,
+            Err(error) => db.update_domain_validation(validation_id, ValidationStatus::Pending, None, Some(&error))?
+        }
+    }
+
+    // Re-read under the lock, so concurrent checks cannot issue twice
+    let request = db.get_server_cert_request(id)?;
+    let validated = request.validations.iter().all(|validation| validation.status == ValidationStatus::Valid);
+    if request.status != ValidationStatus::Pending || !validated {
+        return Ok(Json(request))
+    }
+
+    let user = db.get_user(request.user_id)?;
+    let cert = issue_certificate(&db, &settings, &request.to_certificate_request(), &user, private_key)?;
+    db.update_server_cert_request(id, ValidationStatus::Valid, Some(cert.id))?;
+    Ok(Json(db.get_server_cert_request(id)?))
+}
+
+#[delete("/api/certificates/server-requests/<id>")]
+async fn delete_server_certificate_request(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    let db = state.db.lock().await;
+    let request = db.get_server_cert_request(id)?;
+    if request.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    db.delete_server_cert_request(id)?;
+    Ok(())
+}
+
+#[get("/api/certificates/short-lived")]
+async fn get_short_lived_certificates(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<ShortLivedCertificate>>, ApiError> {
+    let db = state.db.lock().await;
+    let user_id = if authentication.claims.role == UserRole::Admin {
+            None
+        } else {
+            Some(authentication.claims.id)
+        };
+    let certificates = db.get_all_short_lived_cert(user_id)?;
+    Ok(Json(certificates))
+}
+
+#[post("/api/certificates/short-lived", format = "json", data = "<payload>")]
+async fn create_short_lived_certificate(
+    state: &State<AppState>,
+    payload: Json<CreateShortLivedCertificateRequest>,
+    authentication: Authenticated
+) -> Result<Json<ShortLivedCertificateResponse>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let user_id = payload.user_id.unwrap_or(authentication.claims.id);
+
+    let validity_in_hours = payload.validity_in_hours.unwrap_or(1);
+    if validity_in_hours == 0 || validity_in_hours > SHORT_LIVED_MAX_HOURS {
+        return Err(ApiError::BadRequest(format!("Validity must be between 1 and {} hours", SHORT_LIVED_MAX_HOURS)))
+    }
+
+    let (key, key_algorithm) = match &payload.csr {
+        Some(csr) => {
+            let csr = X509Req::from_pem(csr.as_bytes())
+                .map_err(|_| ApiError::BadRequest("CSR is not a valid PEM certificate request".to_string()))?;
+            let key_algorithm = cert::get_key_algorithm(&csr.public_key()?)?;
+            (ShortLivedKey::Csr(csr), key_algorithm)
+        }
+        None => {
+            // Taken before locking the database, a missing pool key is generated here
+            let key_algorithm = payload.key_algorithm.unwrap_or_default();
+            (ShortLivedKey::Generated(state.key_pool.take(key_algorithm).await?), key_algorithm)
+        }
+    };
+
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    let user = db.get_user(user_id)?;
+    policy::check_issuance(&db, &IssuanceContext {
+        user: &user,
+        certificate_type: CertificateType::Client,
+        common_name: &payload.cert_name,
+        dns_names: &[],
+        email: Some(&user.email),
+        validity_in_years: 0,
+        key_algorithm,
+        supersedes_active: false,
+    })?;
+
+    let ca = db.get_issuing_ca(payload.ca_id, CertificateType::Client)?;
+    let (mut metadata, cert, private_key) = cert::create_short_lived_cert(&ca, &payload.cert_name, user.id, &user.email, validity_in_hours, key)?;
+    lint::check_issuance(&settings, &cert, CertificateType::Client)?;
+    db.savepoint(|db| {
+        db.insert_short_lived_cert(&mut metadata)?;
+        ctlog::append(db, &cert, &ca)
+    })?;
+
+    let private_key = match private_key {
+        Some(private_key) => Some(String::from_utf8_lossy(&private_key.private_key_to_pem_pkcs8()?).into_owned()),
+        None => None
+    };
+    Ok(Json(ShortLivedCertificateResponse {
+        id: metadata.id,
+        name: metadata.name,
+        serial: metadata.serial,
+        valid_until: metadata.valid_until,
+        certificate: String::from_utf8_lossy(&cert.to_pem()?).into_owned(),
+        chain: String::from_utf8_lossy(&get_pem(&ca)?).into_owned(),
+        private_key,
+    }))
+}
+
+/// Checks a certificate request against the password rule and issuance policies, then signs and stores it.
+fn issue_certificate(
+    db: &VaulTLSDB,
+    settings: &Settings,
+    payload: &CreateUserCertificateRequest,
+    user: &User,
+    private_key: Option<PKey<Private>>
+) -> Result<Certificate, ApiError> {
+    let prepared = PreparedIssuance::new(settings, payload, private_key)?;
+    policy::check_issuance(db, &prepared.context(payload, user))?;
+
+    let ca = db.get_issuing_ca(payload.ca_id, prepared.certificate_type)?;
+    let mut cert = prepared.sign(&ca, payload, user)?;
+    let leaf = cert::get_pkcs12_certificate(&cert.pkcs12, &cert.pkcs12_password)?;
+    lint::check_issuance(settings, &leaf, prepared.certificate_type)?;
+
+    // The certificate is only stored if it is logged as well
+    db.savepoint(|db| {
+        db.insert_user_cert(&mut cert)?;
+        ctlog::append(db, &leaf, &ca)
+    })?;
+
+    Ok(cert)
+}
+
+/// Issues the successor of a certificate with the same checks as a new certificate and the lifetime of the certificate.
+/// The successor keeps the id, the superseded certificate is revoked and kept.
+fn supersede_certificate(
+    db: &VaulTLSDB,
+    settings: &Settings,
+    cert: &Certificate,
+    payload: &CreateUserCertificateRequest,
+    ca: &Certificate,
+    keep_password: Option<&str>,
+    private_key: Option<PKey<Private>>
+) -> Result<Certificate, ApiError> {
+    let user = db.get_user(cert.user_id)?;
+    let mut prepared = PreparedIssuance::new(settings, payload, private_key)?;
+    prepared.options.lifetime = Some(cert::lifetime(cert)?);
+    if let Some(password) = keep_password {
+        prepared.options.system_generated_password = false;
+        prepared.options.pkcs12_password = Some(password.to_string());
+    }
+    let mut context = prepared.context(payload, &user);
+    context.supersedes_active = true;
+    policy::check_issuance(db, &context)?;
+
+    let mut successor = prepared.sign(ca, payload, &user)?;
+    let leaf = cert::get_pkcs12_certificate(&successor.pkcs12, &successor.pkcs12_password)?;
+    lint::check_issuance(settings, &leaf, prepared.certificate_type)?;
+
+    successor.id = cert.id;
+    db.savepoint(|db| {
+        db.supersede_user_cert(cert, &successor, successor.created_on)?;
+        ctlog::append(db, &leaf, ca)
+    })?;
+
+    Ok(successor)
+}
+
+/// Takes the key for a certificate request from the key pool, None if the request brings a CSR.
+/// A missing pool key is generated on demand, so this is called before the database is locked.
+async fn take_key(key_pool: &KeyPool, payload: &CreateUserCertificateRequest) -> Result<Option<PKey<Private>>, ApiError> {
+    match payload.csr {
+        Some(_) => Ok(None),
+        None => Ok(Some(key_pool.take(payload.key_algorithm.unwrap_or_default()).await?))
+    }
+}
+
+/// A certificate request with the password rule and defaults applied.
+struct PreparedIssuance {
+    options: IssuanceOptions,
+    certificate_type: CertificateType,
+    dns_names: Vec<String>,
+    spiffe_id: Option<String>,
+}
+
+impl PreparedIssuance {
+    fn new(settings: &Settings, payload: &CreateUserCertificateRequest, private_key: Option<PKey<Private>>) -> Result<Self, ApiError> {
+        let user_password = apply_password_rule(settings, payload.system_generated_password, &payload.pkcs12_password)?;
+
+        let certificate_type = payload.cert_type.unwrap_or_default();
+        let profile = payload.profile.unwrap_or_default();
+        if profile == CertificateProfile::EapTls && !matches!(certificate_type, CertificateType::Client | CertificateType::Server) {
+            return Err(ApiError::BadRequest("The EAP-TLS profile is only available for client and server certificates".to_string()))
+        }
+        let upn = payload.upn.as_deref().map(str::trim).filter(|upn| !upn.is_empty());
+        if let Some(upn) = upn {
+            if profile != CertificateProfile::EapTls || certificate_type != CertificateType::Client {
+                return Err(ApiError::BadRequest("A UPN is only available for EAP-TLS client certificates".to_string()))
+            }
+            if !upn.contains('@') {
+                return Err(ApiError::BadRequest(format!("UPN {} must have the form user@domain", upn)))
+            }
+        }
+
+        let public_key = match &payload.csr {
+            Some(csr) => {
+                if !matches!(certificate_type, CertificateType::Client | CertificateType::Server) {
+                    return Err(ApiError::BadRequest("A CSR is only accepted for client and server certificates".to_string()))
+                }
+                Some(cert::get_csr_public_key(csr)?)
+            }
+            None => None
+        };
+        let key_algorithm = match &public_key {
+            Some(public_key) => cert::get_key_algorithm(public_key)?,
+            None => payload.key_algorithm.unwrap_or_default()
+        };
+
+        if let Some(not_before) = payload.not_before {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+            if not_before < now - NOT_BEFORE_MAX_BACKDATE_SECS * 1000 {
+                return Err(ApiError::BadRequest(format!("notBefore can be backdated by at most {} minutes", NOT_BEFORE_MAX_BACKDATE_SECS / 60)))
+            }
+            if not_before > now + NOT_BEFORE_MAX_FUTURE_SECS * 1000 {
+                return Err(ApiError::BadRequest(format!("notBefore can be scheduled at most {} days ahead", NOT_BEFORE_MAX_FUTURE_SECS / 60 / 60 / 24)))
+            }
+        }
+
+        let options = IssuanceOptions {
+            validity_in_years: payload.validity_in_years.unwrap_or(1),
+            key_algorithm,
+            system_generated_password: user_password,
+            pkcs12_password: payload.pkcs12_password.clone(),
+            pki_url: settings.get_pki_url(),
+            private_key,
+            profile,
+            upn: upn.map(str::to_string),
+            public_key,
+            not_before: payload.not_before,
+            lifetime: None,
+        };
+        let dns_names = match certificate_type {
+            CertificateType::Server => payload.dns_names.clone().unwrap_or_default(),
+            _ => Vec::new()
+        };
+        let spiffe_id = match certificate_type {
+            CertificateType::Svid => {
+                let trust_domain = settings.get_spiffe_trust_domain()
+                    .ok_or(ApiError::BadRequest("SPIFFE trust domain is not configured".to_string()))?;
+                let path = payload.spiffe_path.as_deref()
+                    .ok_or(ApiError::BadRequest("SPIFFE path is required for SVIDs".to_string()))?;
+                Some(spiffe::create_spiffe_id(trust_domain, path)?)
+            }
+            _ => None
+        };
+
+        Ok(Self { options, certificate_type, dns_names, spiffe_id })
+    }
+
+    /// The request as seen by the policy engine.
+    fn context<'a>(&'a self, payload: &'a CreateUserCertificateRequest, user: &'a User) -> IssuanceContext<'a> {
+        IssuanceContext {
+            user,
+            certificate_type: self.certificate_type,
+            common_name: self.spiffe_id.as_deref().unwrap_or(&payload.cert_name),
+            dns_names: &self.dns_names,
+            email: (self.certificate_type == CertificateType::Client).then_some(user.email.as_str()),
+            validity_in_years: self.options.validity_in_years,
+            key_algorithm: self.options.key_algorithm,
+            supersedes_active: false,
+        }
+    }
+
+    /// Generates the key and signs the certificate with the CA, nothing is stored.
+    fn sign(&self, ca: &Certificate, payload: &CreateUserCertificateRequest, user: &User) -> Result<Certificate, ApiError> {
+        match self.certificate_type {
+            CertificateType::Client => {
+                cert::create_user_cert(ca, &payload.cert_name, user.id, &user.email, &self.options)
+            }
+            CertificateType::Server => {
+                cert::create_server_cert(ca, &payload.cert_name, &self.dns_names, user.id, &self.options)
+            }
+            CertificateType::Svid => {
+                let spiffe_id = self.spiffe_id.as_deref().unwrap_or_default();
+                cert::create_svid(ca, &payload.cert_name, spiffe_id, user.id, &self.options)
+            }
+            CertificateType::Timestamping => {
+                cert::create_timestamping_cert(ca, &payload.cert_name, user.id, &self.options)
+            }
+            CertificateType::CA => {
+                Err(ApiError::BadRequest("Cannot create CA certificate".to_string()))
+            }
+        }
+    }
+}
+
+/// Sends a mail about a new certificate to the user in the background.
+fn notify_user(mailer: Arc<Mutex<Option<Mailer>>>, user: User, certificate: Certificate) {
+    let mail = MailMessage{
+        to: format!("{} <{}>", user.name, user.email),
+        subject: "VaulTLS: A new certificate is available".to_string(),
+        username: user.name,
+        certificate
+    };
+
+    tokio::spawn(async move {
+        if let Some(mailer) = &mut *mailer.lock().await {
+            let _ = mailer.send_email(mail).await;
+        }
+    });
+}
+
+/// Applies the PKCS#12 password rule and returns whether the password has to be system generated.
+fn apply_password_rule(
+    settings: &Settings,
+    system_generated_password: bool,
+    pkcs12_password: &Option<String>
+) -> Result<bool, ApiError> {
+    match settings.password_rule() {
+        PasswordRule::System => Ok(true),
+        PasswordRule::Required => {
+            if !system_generated_password
+                && pkcs12_password.as_deref().unwrap_or("").trim().is_empty() {
+                return Err(ApiError::BadRequest("Password is not provided, but is required.".to_string()))
+            }
+            Ok(system_generated_password)
+        }
+        PasswordRule::Optional => Ok(system_generated_password)
+    }
+}
+
+#[get("/api/certificates/ca")]
+async fn get_certificate_authorities(
+    state: &State<AppState>,
+    _authentication: Authenticated
+) -> Result<Json<Vec<CertificateAuthority>>, ApiError> {
+    let db = state.db.lock().await;
+    let authorities = db.get_all_certificate_authorities()?;
+    Ok(Json(authorities))
+}
+
+#[post("/api/certificates/ca", format = "json", data = "<payload>")]
+async fn create_certificate_authority(
+    state: &State<AppState>,
+    payload: Json<CreateCaRequest>,
+    authentication: Authenticated
+) -> Result<Json<CertificateAuthority>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    if payload.name.trim().is_empty() {
+        return Err(ApiError::BadRequest("CA name is required".to_string()))
+    }
+
+    let mut ca = create_ca(payload.name.trim(), payload.validity_in_years)?;
+    let db = state.db.lock().await;
+    db.insert_ca(&mut ca, payload.purpose.unwrap_or_default(), true)?;
+    save_current_ca(&db)?;
+
+    Ok(Json(db.get_certificate_authority(ca.id)?))
+}
+
+#[put("/api/certificates/ca/<id>", format = "json", data = "<payload>", rank = 1)]
+async fn update_certificate_authority(
+    state: &State<AppState>,
+    id: i64,
+    payload: Json<UpdateCaRequest>,
+    authentication: Authenticated
+) -> Result<Json<CertificateAuthority>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let mut authority = db.get_certificate_authority(id)?;
+    if !payload.name.trim().is_empty() {
+        authority.name = payload.name.trim().to_string();
+    }
+    authority.purpose = payload.purpose;
+    authority.active = payload.active;
+    db.update_certificate_authority(&authority)?;
+    save_current_ca(&db)?;
+
+    Ok(Json(authority))
+}
+
+/// Saves the CA that is downloaded without id to the filesystem, after it may have changed.
+fn save_current_ca(db: &VaulTLSDB) -> Result<(), ApiError> {
+    match db.get_current_ca() {
+        Ok(ca) => save_ca(&ca),
+        Err(_) => Ok(())
+    }
+}
+
+/// Downloads the default CA, which is the most recent active general purpose CA.
+#[get("/api/certificates/ca/download")]
+async fn download_ca(
+    state: &State<AppState>
+) -> Result<DownloadResponse, ApiError> {
+    let db = state.db.lock().await;
+    let ca = db.get_current_ca()?;
+    let pem = get_pem(&ca)?;
+    Ok(DownloadResponse::new(pem, "ca_certificate.pem"))
+}
+
+#[get("/api/certificates/ca/<id>/download")]
+async fn download_ca_by_id(
+    state: &State<AppState>,
+    id: i64
+) -> Result<DownloadResponse, ApiError> {
+    let db = state.db.lock().await;
+    let ca = db.get_ca(id)?;
+    let pem = get_pem(&ca)?;
+    Ok(DownloadResponse::new(pem, &format!("ca_certificate_{}.pem", id)))
+}
+
+#[get("/api/trust-anchors")]
+async fn get_trust_anchors(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<TrustAnchor>>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let anchors = db.get_all_trust_anchors()?;
+    Ok(Json(anchors))
+}
+
+#[post("/api/trust-anchors", format = "json", data = "<payload>")]
+async fn create_trust_anchor(
+    state: &State<AppState>,
+    payload: Json<CreateTrustAnchorRequest>,
+    authentication: Authenticated
+) -> Result<Json<TrustAnchor>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let mut anchor = trust::create_trust_anchor(&payload.name, &payload.certificate, payload.purpose.unwrap_or_default())?;
+    let db = state.db.lock().await;
+    db.insert_trust_anchor(&mut anchor)?;
+    Ok(Json(anchor))
+}
+
+#[put("/api/trust-anchors/<id>", format = "json", data = "<payload>")]
+async fn update_trust_anchor(
+    state: &State<AppState>,
+    id: i64,
+    payload: Json<UpdateTrustAnchorRequest>,
+    authentication: Authenticated
+) -> Result<Json<TrustAnchor>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let mut anchor = db.get_trust_anchor(id)?;
+    if !payload.name.trim().is_empty() {
+        anchor.name = payload.name.trim().to_string();
+    }
+    anchor.purpose = payload.purpose;
+    anchor.enabled = payload.enabled;
+    db.update_trust_anchor(&anchor)?;
+    Ok(Json(anchor))
+}
+
+#[delete("/api/trust-anchors/<id>")]
+async fn delete_trust_anchor(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    db.delete_trust_anchor(id)?;
+    Ok(())
+}
+
+/// Downloads the VaulTLS CAs and enabled trust anchors as one PEM bundle, e.g. for Caddy's `trust_pool http`.
+/// The optional purpose restricts the bundle to CAs of that purpose and general purpose CAs.
+#[get("/api/trust-bundle?<purpose>")]
+async fn download_trust_bundle(
+    state: &State<AppState>,
+    purpose: Option<u8>
+) -> Result<DownloadResponse, ApiError> {
+    let purpose = match purpose {
+        Some(purpose) => Some(CaPurpose::try_from(purpose).map_err(|_| ApiError::BadRequest(format!("Invalid purpose {}", purpose)))?),
+        None => None
+    };
+    let db = state.db.lock().await;
+    let authorities = db.get_all_certificate_authorities()?;
+    let cas = db.get_all_ca()?;
+    let anchors = db.get_all_trust_anchors()?;
+    let bundle = trust::create_trust_bundle(&authorities, &cas, &anchors, purpose)?;
+    Ok(DownloadResponse::new(bundle.into_bytes(), "trust_bundle.pem"))
+}
+
+#[get("/api/certificates/ca/<id>/der")]
+async fn download_ca_der(
+    state: &State<AppState>,
+    id: i64
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let db = state.db.lock().await;
+    let ca = db.get_ca(id)?;
+    Ok((ContentType::new("application", "pkix-cert"), ca.cert))
+}
+
+#[get("/api/certificates/ca/<id>/crl")]
+async fn download_crl(
+    state: &State<AppState>,
+    id: i64
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let db = state.db.lock().await;
+    let ca = db.get_ca(id)?;
+    let revoked = db.get_revoked_serials(id)?;
+    let crl = cert::create_crl(&ca, &revoked)?;
+    Ok((ContentType::new("application", "pkix-crl"), crl))
+}
+
+#[post("/api/ocsp", data = "<request>")]
+async fn ocsp_responder(
+    state: &State<AppState>,
+    request: Vec<u8>
+) -> (ContentType, Vec<u8>) {
+    let db = state.db.lock().await;
+    let response = ocsp::respond(&db, &request);
+    (ContentType::new("application", "ocsp-response"), response)
+}
+
+#[post("/api/tsa", data = "<request>")]
+async fn timestamp_authority(
+    state: &State<AppState>,
+    request: Vec<u8>
+) -> (ContentType, Vec<u8>) {
+    let db = state.db.lock().await;
+    let response = tsa::respond(&db, &request);
+    (ContentType::new("application", "timestamp-reply"), response)
+}
+
+#[get("/api/ct/v1/get-sth")]
+async fn ct_get_sth(
+    state: &State<AppState>
+) -> Result<Json<ctlog::SignedTreeHead>, ApiError> {
+    let db = state.db.lock().await;
+    Ok(Json(ctlog::get_sth(&db)?))
+}
+
+#[get("/api/ct/v1/get-sth-consistency?<first>&<second>")]
+async fn ct_get_sth_consistency(
+    state: &State<AppState>,
+    first: u64,
+    second: u64
+) -> Result<Json<ctlog::ConsistencyProof>, ApiError> {
+    let db = state.db.lock().await;
+    Ok(Json(ctlog::get_consistency_proof(&db, first, second)?))
+}
+
+#[get("/api/ct/v1/get-proof-by-hash?<hash>&<tree_size>")]
+async fn ct_get_proof_by_hash(
+    state: &State<AppState>,
+    hash: &str,
+    tree_size: u64
+) -> Result<Json<ctlog::InclusionProof>, ApiError> {
+    let db = state.db.lock().await;
+    Ok(Json(ctlog::get_proof_by_hash(&db, hash, tree_size)?))
+}
+
+/// Entries contain the certificates with names and e-mail addresses, so they require authentication.
+#[get("/api/ct/v1/get-entries?<start>&<end>")]
+async fn ct_get_entries(
+    state: &State<AppState>,
+    start: u64,
+    end: u64,
+    _authentication: TokenAuthenticated
+) -> Result<Json<ctlog::LogEntries>, ApiError> {
+    let db = state.db.lock().await;
+    Ok(Json(ctlog::get_entries(&db, start, end)?))
+}
+
+#[get("/api/ct/log-key")]
+async fn ct_get_log_key(
+    state: &State<AppState>
+) -> Result<Json<ctlog::LogPublicKey>, ApiError> {
+    let db = state.db.lock().await;
+    Ok(Json(ctlog::get_public_key(&db)?))
+}
+
+#[get("/api/certificates/ca/backups")]
+async fn get_ca_backups(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<CaBackup>>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let backups = db.get_all_ca_backups()?;
+    Ok(Json(backups))
+}
+
+#[post("/api/certificates/ca/backups", format = "json", data = "<payload>")]
+async fn create_ca_backup(
+    state: &State<AppState>,
+    payload: Json<CreateCaBackupRequest>,
+    authentication: Authenticated
+) -> Result<Json<CaBackup>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let ca = match payload.ca_id {
+        Some(ca_id) => db.get_ca(ca_id)?,
+        None => db.get_current_ca()?
+    };
+    let mut ca_backup = backup::create_backup(&ca, payload.threshold, payload.share_count, &backup::share_key()?)?;
+    ca_backup.user_id = authentication.claims.id;
+    db.insert_ca_backup(&mut ca_backup)?;
+    Ok(Json(ca_backup))
+}
+
+#[get("/api/certificates/ca/backups/<id>/download")]
+async fn download_ca_backup(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<DownloadResponse, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let ca_backup = db.get_ca_backup(id)?;
+    Ok(DownloadResponse::new(ca_backup.backup.into_bytes(), &format!("ca_backup_{}.json", id)))
+}
+
+#[get("/api/certificates/ca/backups/<id>/shares/<index>")]
+async fn download_ca_backup_share(
+    state: &State<AppState>,
+    id: i64,
+    index: u8,
+    authentication: Authenticated
+) -> Result<DownloadResponse, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let downloaded_on = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let share_key = backup::share_key()?;
+    // The share stays stored if it cannot be opened
+    let share = db.savepoint(|db| backup::open_share(&db.take_ca_backup_share(id, index, authentication.claims.id, downloaded_on)?, &share_key))?;
+    Ok(DownloadResponse::new(share.into_bytes(), &format!("ca_backup_{}_share_{}.txt", id, index)))
+}
+
+#[delete("/api/certificates/ca/backups/<id>")]
+async fn delete_ca_backup(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    db.delete_ca_backup(id)?;
+    Ok(())
+}
+
+#[post("/api/certificates/ca/restore", format = "json", data = "<payload>")]
+async fn restore_ca_backup(
+    state: &State<AppState>,
+    payload: Json<RestoreCaBackupRequest>,
+    authentication: Authenticated
+) -> Result<Json<LintReport>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let mut ca = backup::restore_backup(&payload.backup, &payload.shares)?;
+
+    let db = state.db.lock().await;
+    if db.get_all_ca()?.iter().any(|existing| existing.cert == ca.cert) {
+        return Err(ApiError::BadRequest("CA is already present".to_string()))
+    }
+    db.insert_ca(&mut ca, payload.purpose.unwrap_or_default(), payload.active)?;
+    save_current_ca(&db)?;
+
+    let report = lint::lint_stored_certificate(&ca)?;
+    Ok(Json(report))
+}
+
+#[get("/api/spiffe/bundle")]
+async fn download_spiffe_bundle(
+    state: &State<AppState>
+) -> Result<Json<spiffe::SpiffeBundle>, ApiError> {
+    let db = state.db.lock().await;
+    let authorities = db.get_all_certificate_authorities()?;
+    let cas = db.get_all_ca()?;
+    Ok(Json(spiffe::create_bundle(&authorities, &cas)?))
+}
+
+#[get("/api/certificates/<id>/download")]
+async fn download_certificate(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<DownloadResponse, ApiError> {
+    let db = state.db.lock().await;
+    let (user_id, name, pkcs12) = db.get_user_cert_pkcs12(id)?;
+    if user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    Ok(DownloadResponse::new(pkcs12, &format!("{}.p12", name)))
+}
+
+/// Exports a FreeRADIUS EAP-TLS configuration for a RADIUS server certificate.
+/// All CAs that are still valid are trusted for client certificates.
+#[get("/api/certificates/<id>/freeradius?<group>")]
+async fn download_freeradius_config(
+    state: &State<AppState>,
+    id: i64,
+    group: Option<&str>,
+    authentication: Authenticated
+) -> Result<DownloadResponse, ApiError> {
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    let cert = db.get_user_cert(id)?;
+    if cert.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    if cert.certificate_type != CertificateType::Server {
+        return Err(ApiError::BadRequest("FreeRADIUS configurations can only be exported for server certificates".to_string()))
+    }
+    if cert.revoked_on.is_some() {
+        return Err(ApiError::BadRequest("Cannot export a revoked certificate".to_string()))
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let mut cas = Vec::new();
+    for ca in db.get_all_ca()? {
+        if ca.valid_until < now { continue }
+        let revoked = db.get_revoked_serials(ca.id)?;
+        let crl = cert::create_crl(&ca, &revoked)?;
+        cas.push(freeradius::TrustedCa { ca, crl });
+    }
+    let group = group.unwrap_or(freeradius::DEFAULT_GROUP);
+    let archive = freeradius::create_export(&cert, &cas, settings.get_pki_url().as_deref(), group)?;
+    Ok(DownloadResponse::new(archive, &format!("{}-freeradius.tar", cert.name)))
+}
+
+/// Renders an OpenVPN client profile for a client certificate with the server template of the settings.
+#[get("/api/certificates/<id>/openvpn")]
+async fn download_openvpn_profile(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<DownloadResponse, ApiError> {
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    let cert = get_exportable_client_cert(&db, id, &authentication)?;
+    let ca = db.get_ca(cert.ca_id)?;
+    let profile = openvpn::create_profile(settings.get_openvpn(), &cert, &ca)?;
+    Ok(DownloadResponse::new(profile.into_bytes(), &format!("{}.ovpn", cert.name)))
+}
+
+/// Exports a client certificate with the CA as Apple configuration profile.
+/// The profile is signed if a profile signing certificate is configured.
+#[get("/api/certificates/<id>/mobileconfig?<include_password>")]
+async fn download_mobileconfig(
+    state: &State<AppState>,
+    id: i64,
+    include_password: Option<bool>,
+    authentication: Authenticated
+) -> Result<DownloadResponse, ApiError> {
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    let cert = get_exportable_client_cert(&db, id, &authentication)?;
+    let ca = db.get_ca(cert.ca_id)?;
+
+    let signer = match settings.get_profile_signing_certificate_id() {
+        Some(signer_id) => {
+            let signer = db.get_user_cert(signer_id)?;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+            if signer.revoked_on.is_some() || signer.valid_from > now || signer.valid_until < now {
+                return Err(ApiError::Other("Profile signing certificate is revoked, not yet valid or expired".to_string()))
+            }
+            Some(signer)
+        }
+        None => None
+    };
+    let profile = mobileconfig::create_mobileconfig(&cert, &ca, include_password.unwrap_or(false), signer.as_ref())?;
+    Ok(DownloadResponse::new(profile, &format!("{}.mobileconfig", cert.name))
+        .with_content_type(ContentType::new("application", "x-apple-aspen-config")))
+}
+
+/// Exports a client certificate with the CA as ChromeOS Open Network Configuration.
+#[get("/api/certificates/<id>/onc")]
+async fn download_onc(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<DownloadResponse, ApiError> {
+    let db = state.db.lock().await;
+    let cert = get_exportable_client_cert(&db, id, &authentication)?;
+    let ca = db.get_ca(cert.ca_id)?;
+    let onc = onc::create_onc(&cert, &ca)?;
+    Ok(DownloadResponse::new(onc, &format!("{}.onc", cert.name)))
+}
+
+/// Returns a client certificate of the user, or of any user for admins, which is not revoked.
+fn get_exportable_client_cert(db: &VaulTLSDB, id: i64, authentication: &Authenticated) -> Result<Certificate, ApiError> {
+    let cert = db.get_user_cert(id)?;
+    if cert.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    if cert.certificate_type != CertificateType::Client {
+        return Err(ApiError::BadRequest("Only client certificates can be exported for devices".to_string()))
+    }
+    if cert.revoked_on.is_some() {
+        return Err(ApiError::BadRequest("Cannot export a revoked certificate".to_string()))
+    }
+    Ok(cert)
+}
+
+#[get("/api/certificates/<id>/password")]
+async fn fetch_certificate_password(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<Json<String>, ApiError> {
+    let db = state.db.lock().await;
+    let (user_id, pkcs12_password) = db.get_user_cert_pkcs12_password(id)?;
+    if user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    Ok(Json(pkcs12_password))
+}
+
+#[put("/api/certificates/<id>/password", format = "json", data = "<payload>")]
+async fn update_certificate_password(
+    state: &State<AppState>,
+    id: i64,
+    payload: Json<CertificatePasswordRequest>,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    let cert = db.get_user_cert(id)?;
+    if cert.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+
+    let system_generated_password = apply_password_rule(&settings, payload.system_generated_password, &payload.pkcs12_password)?;
+    let password = cert::get_password(system_generated_password, &payload.pkcs12_password);
+    let pkcs12 = cert::repackage_pkcs12(&cert, &password)?;
+    db.update_user_cert_pkcs12(id, &pkcs12, &password)?;
+    Ok(())
+}
+
+#[post("/api/certificates/<id>/rekey", format = "json", data = "<payload>")]
+async fn rekey_certificate(
+    state: &State<AppState>,
+    id: i64,
+    payload: Json<CertificatePasswordRequest>,
+    authentication: Authenticated
+) -> Result<Json<Certificate>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+
+    let cert = state.db.lock().await.get_user_cert(id)?;
+    if cert.revoked_on.is_some() {
+        return Err(ApiError::BadRequest("Cannot re-key a revoked certificate".to_string()))
+    }
+
+    let mut request = cert::rekey_request(&cert)?;
+    request.system_generated_password = payload.system_generated_password;
+    request.pkcs12_password = payload.pkcs12_password.clone();
+    let private_key = take_key(&state.key_pool, &request).await?;
+
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    let ca = db.get_ca(cert.ca_id)?;
+    let rekeyed = db.transaction(|db| {
+        let rekeyed = supersede_certificate(db, &settings, &cert, &request, &ca, None, private_key)?;
+        Ok((rekeyed, true))
+    })?;
+
+    Ok(Json(rekeyed))
+}
+
+/// Renews a certificate with a new key and validity, keeping its identity and PKCS#12 password.
+/// Besides a session or API token of the owner or an admin, the current certificate can authenticate the request.
+#[post("/api/certificates/<id>/renew", format = "json", data = "<payload>")]
+async fn renew_certificate(
+    state: &State<AppState>,
+    id: i64,
+    payload: Json<RenewCertificateRequest>,
+    authentication: Option<TokenAuthenticated>
+) -> Result<Json<IssuedCertificateResponse>, ApiError> {
+    let (cert, request) = {
+        let db = state.db.lock().await;
+        let cert = db.get_user_cert(id)?;
+        match authentication {
+            Some(authentication) => {
+                if cert.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+            }
+            None => renewal::verify_proof(&payload, id, &cert, &db.get_ca(cert.ca_id)?)?
+        }
+        if cert.revoked_on.is_some() {
+            return Err(ApiError::BadRequest("Cannot renew a revoked certificate".to_string()))
+        }
+
+        let mut request = cert::rekey_request(&cert)?;
+        request.csr = payload.csr.clone();
+        (cert, request)
+    };
+    let private_key = take_key(&state.key_pool, &request).await?;
+
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    // Certificates of a CA that no longer issues move to the current CA for their type
+    let ca_id = db.get_certificate_authority(cert.ca_id)?.active.then_some(cert.ca_id);
+    let ca = db.get_issuing_ca(ca_id, cert.certificate_type)?;
+    let renewed = db.transaction(|db| {
+        let renewed = supersede_certificate(db, &settings, &cert, &request, &ca, Some(&cert.pkcs12_password), private_key)?;
+        Ok((renewed, true))
+    })?;
+
+    Ok(Json(cert::get_pem_bundle(&renewed, &ca)?))
+}
+
+#[post("/api/certificates/<id>/revoke")]
+async fn revoke_user_cert(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let revoked_on = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    db.revoke_user_cert(id, revoked_on)?;
+    Ok(())
+}
+
+#[delete("/api/certificates/<id>")]
+async fn delete_user_cert(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let deleted_on = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    db.delete_user_cert(id, deleted_on)?;
+    Ok(())
+}
+
+#[post("/api/certificates/verify", format = "json", data = "<payload>")]
+async fn verify_certificate(
+    state: &State<AppState>,
+    payload: Json<VerifyCertificateRequest>,
+    _authentication: Authenticated
+) -> Result<Json<VerificationReport>, ApiError> {
+    let db = state.db.lock().await;
+    let report = verify::verify_certificate(&db, &payload)?;
+    Ok(Json(report))
+}
+
+#[post("/api/certificates/lint", format = "json", data = "<payload>")]
+async fn lint_certificate(
+    payload: Json<LintCertificateRequest>,
+    _authentication: Authenticated
+) -> Result<Json<LintReport>, ApiError> {
+    let submitted = verify::parse_submitted_certificate(&payload.certificate, &payload.password)?;
+    let report = lint::lint_certificate(&submitted.leaf, payload.certificate_type)?;
+    Ok(Json(report))
+}
+
+#[get("/api/certificates/ca/lint")]
+async fn lint_ca_certificates(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<LintReport>>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
     let db = state.db.lock().await;
-    let user_id= if authentication.claims.role == UserRole::Admin {
+    let reports = db.get_all_ca()?
+        .iter()
+        .map(lint::lint_stored_certificate)
+        .collect::<Result<Vec<LintReport>, ApiError>>()?;
+    Ok(Json(reports))
+}
+
+#[get("/api/certificates/<id>/lint")]
+async fn lint_user_certificate(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<Json<LintReport>, ApiError> {
+    let db = state.db.lock().await;
+    let cert = db.get_user_cert(id)?;
+    if cert.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let report = lint::lint_stored_certificate(&cert)?;
+    Ok(Json(report))
+}
+
+#[get("/api/policies")]
+async fn get_policies(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<IssuancePolicy>>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let policies = db.get_all_policies()?;
+    Ok(Json(policies))
+}
+
+#[post("/api/policies", format = "json", data = "<payload>")]
+async fn create_policy(
+    state: &State<AppState>,
+    payload: Json<IssuancePolicy>,
+    authentication: Authenticated
+) -> Result<Json<i64>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let mut policy = payload.into_inner();
+    db.insert_policy(&mut policy)?;
+    Ok(Json(policy.id))
+}
+
+#[put("/api/policies", format = "json", data = "<payload>")]
+async fn update_policy(
+    state: &State<AppState>,
+    payload: Json<IssuancePolicy>,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    db.update_policy(&payload)
+}
+
+#[delete("/api/policies/<id>")]
+async fn delete_policy(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    db.delete_policy(id)?;
+    Ok(())
+}
+
+#[get("/api/ssh/ca")]
+async fn download_ssh_ca(
+    state: &State<AppState>
+) -> Result<DownloadResponse, ApiError> {
+    let db = state.db.lock().await;
+    let ssh_ca = db.get_current_ssh_ca()?;
+    Ok(DownloadResponse::new(format!("{}\n", ssh_ca.public_key).into_bytes(), "ssh_ca.pub"))
+}
+
+#[get("/api/tokens")]
+async fn get_api_tokens(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<ApiToken>>, ApiError> {
+    let db = state.db.lock().await;
+    let user_id = if authentication.claims.role == UserRole::Admin {
             None
         } else {
             Some(authentication.claims.id)
         };
-    let certificates = db.get_all_user_cert(user_id)?;
-    Ok(Json(certificates))
+    let tokens = db.get_all_api_tokens(user_id)?;
+    Ok(Json(tokens))
 }
 
-#[post("/api/certificates", format = "json", data = "<payload>")]
-async fn create_user_certificate(
+/// Creates an API token. The token is only part of this response and cannot be retrieved later.
+#[post("/api/tokens", format = "json", data = "<payload>")]
+async fn create_api_token(
     state: &State<AppState>,
-    payload: Json<CreateUserCertificateRequest>,
+    payload: Json<CreateApiTokenRequest>,
     authentication: Authenticated
-) -> Result<Json<Certificate>, ApiError> {
+) -> Result<Json<ApiToken>, ApiError> {
+    let user_id = payload.user_id.unwrap_or(authentication.claims.id);
+    if user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+
+    let db = state.db.lock().await;
+    db.get_user(user_id)?;
+    let mut token = token_auth::create_api_token(&payload.name, user_id, payload.expires_in_days)?;
+    db.insert_api_token(&mut token)?;
+
+    Ok(Json(token))
+}
+
+#[delete("/api/tokens/<id>")]
+async fn delete_api_token(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    let db = state.db.lock().await;
+    let token = db.get_api_token(id)?;
+    if token.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    db.delete_api_token(id)?;
+    Ok(())
+}
+
+#[get("/api/enrollment-tokens")]
+async fn get_enrollment_tokens(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<EnrollmentToken>>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    let tokens = db.get_all_enrollment_tokens()?;
+    Ok(Json(tokens))
+}
+
+/// Creates a one-time enrollment token. The token is only part of this response and cannot be retrieved later.
+#[post("/api/enrollment-tokens", format = "json", data = "<payload>")]
+async fn create_enrollment_token(
+    state: &State<AppState>,
+    payload: Json<CreateEnrollmentTokenRequest>,
+    authentication: Authenticated
+) -> Result<Json<EnrollmentToken>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+
+    let user = db.get_user(payload.user_id)?;
+    let mut token = enrollment::create_enrollment_token(&payload)?;
+
+    // Reject tokens that could not be redeemed
+    let certificate_request = token.to_certificate_request(None);
+    let prepared = PreparedIssuance::new(&settings, &certificate_request, None)?;
+    policy::check_issuance(&db, &prepared.context(&certificate_request, &user))?;
+    db.get_issuing_ca(token.ca_id, token.certificate_type)?;
+
+    db.insert_enrollment_token(&mut token)?;
+    Ok(Json(token))
+}
+
+#[delete("/api/enrollment-tokens/<id>")]
+async fn delete_enrollment_token(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let db = state.db.lock().await;
+    db.get_enrollment_token(id)?;
+    db.delete_enrollment_token(id)?;
+    Ok(())
+}
+
+/// Redeems an enrollment token. The device is authenticated by the token alone.
+#[post("/api/enroll", format = "json", data = "<payload>")]
+async fn enroll_device(
+    state: &State<AppState>,
+    payload: Json<EnrollRequest>
+) -> Result<Json<IssuedCertificateResponse>, ApiError> {
+    let token_hash = enrollment::hash_enrollment_token(&payload.token)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    // Only valid tokens take a key from the pool, the token is redeemed below
+    let token = state.db.lock().await.find_enrollment_token(&token_hash, now)?
+        .ok_or(ApiError::Unauthorized(Some("Enrollment token is invalid, expired or already used".to_string())))?;
+    let private_key = take_key(&state.key_pool, &token.to_certificate_request(payload.csr.clone())).await?;
+
     let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+
+    // The token stays unused if issuance fails, since the transaction is rolled back
+    let (cert, ca) = db.transaction(|db| {
+        let token = db.consume_enrollment_token(&token_hash, now)?;
+        let user = db.get_user(token.user_id)?;
+        let cert = issue_certificate(db, &settings, &token.to_certificate_request(payload.csr.clone()), &user, private_key)?;
+        db.update_enrollment_token(token.id, cert.id)?;
+        let ca = db.get_ca(cert.ca_id)?;
+        Ok(((cert, ca), true))
+    })?;
+
+    Ok(Json(cert::get_pem_bundle(&cert, &ca)?))
+}
+
+#[post("/v1/pki/issue/<role>", format = "json", data = "<payload>")]
+async fn vault_issue_certificate(
+    state: &State<AppState>,
+    role: &str,
+    payload: Json<VaultCertificateRequest>,
+    authentication: TokenAuthenticated
+) -> Result<Json<VaultResponse<VaultCertificateData>>, ApiError> {
+    if payload.csr.is_some() { return Err(ApiError::BadRequest("Use the sign endpoint for CSRs".to_string())) }
+    vault_issue(state, role, &payload, authentication).await
+}
+
+#[post("/v1/pki/sign/<role>", format = "json", data = "<payload>")]
+async fn vault_sign_certificate(
+    state: &State<AppState>,
+    role: &str,
+    payload: Json<VaultCertificateRequest>,
+    authentication: TokenAuthenticated
+) -> Result<Json<VaultResponse<VaultCertificateData>>, ApiError> {
+    if payload.csr.is_none() { return Err(ApiError::BadRequest("csr is required".to_string())) }
+    vault_issue(state, role, &payload, authentication).await
+}
+
+/// Issues a certificate for the user of the API token through the regular issuance checks.
+async fn vault_issue(
+    state: &State<AppState>,
+    role: &str,
+    payload: &VaultCertificateRequest,
+    authentication: TokenAuthenticated
+) -> Result<Json<VaultResponse<VaultCertificateData>>, ApiError> {
     if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
 
+    let request = vault::create_certificate_request(role, payload, authentication.claims.id, payload.csr.as_deref())?;
+    let private_key = take_key(&state.key_pool, &request).await?;
+    let settings = state.settings.lock().await;
     let db = state.db.lock().await;
+    let user = db.get_user(authentication.claims.id)?;
+    let cert = issue_certificate(&db, &settings, &request, &user, private_key)?;
+    let ca = db.get_ca(cert.ca_id)?;
 
-    let mut user_password: bool = payload.system_generated_password;
-    match settings.password_rule() {
-        PasswordRule::System => {
-            user_password = true;
-        }
-        PasswordRule::Required => {
-            if !payload.system_generated_password
-                && payload.pkcs12_password.as_deref().unwrap_or("").trim().is_empty() {
-                return Err(ApiError::BadRequest("Password is not provided, but is required.".to_string()))
-            }
-        }
-        PasswordRule::Optional => {}
-    }
+    let data = vault::create_certificate_data(&cert, &ca)?;
+    Ok(Json(vault::create_response(data, Vec::new())?))
+}
 
-    let ca = db.get_current_ca()?;
-    let mut cert = match payload.cert_type.unwrap_or_default() {
-        CertificateType::Client => {
-            let user = db.get_user(payload.user_id)?;
-            cert::create_user_cert(&ca, &payload.cert_name, payload.validity_in_years.unwrap_or(1), payload.user_id, &user.email, user_password, &payload.pkcs12_password)?
-        }
-        CertificateType::Server => {
-            cert::create_server_cert(&ca, &payload.cert_name, &payload.dns_names.clone().unwrap_or_default(), payload.validity_in_years.unwrap_or(1), user_password, &payload.pkcs12_password, payload.user_id)?
-        }
-        CertificateType::CA => {
-            return Err(ApiError::BadRequest("Cannot create CA certificate".to_string()))
+#[get("/v1/pki/ca/pem?<role>")]
+async fn vault_download_ca(
+    state: &State<AppState>,
+    role: Option<&str>
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let db = state.db.lock().await;
+    let ca = vault::get_issuer(&db, None, role)?;
+    Ok((ContentType::new("application", "pem-certificate-chain"), get_pem(&ca)?))
+}
+
+#[get("/v1/pki/issuer/<issuer_ref>/pem")]
+async fn vault_download_issuer(
+    state: &State<AppState>,
+    issuer_ref: &str
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let db = state.db.lock().await;
+    let ca = vault::get_issuer(&db, Some(issuer_ref), None)?;
+    Ok((ContentType::new("application", "pem-certificate-chain"), get_pem(&ca)?))
+}
+
+#[get("/v1/pki/crl?<role>")]
+async fn vault_download_crl(
+    state: &State<AppState>,
+    role: Option<&str>
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let db = state.db.lock().await;
+    let ca = vault::get_issuer(&db, None, role)?;
+    let revoked = db.get_revoked_serials(ca.id)?;
+    let crl = cert::create_crl(&ca, &revoked)?;
+    Ok((ContentType::new("application", "pkix-crl"), crl))
+}
+
+#[get("/v1/pki/issuer/<issuer_ref>/crl/der")]
+async fn vault_download_issuer_crl(
+    state: &State<AppState>,
+    issuer_ref: &str
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let db = state.db.lock().await;
+    let ca = vault::get_issuer(&db, Some(issuer_ref), None)?;
+    let revoked = db.get_revoked_serials(ca.id)?;
+    let crl = cert::create_crl(&ca, &revoked)?;
+    Ok((ContentType::new("application", "pkix-crl"), crl))
+}
+
+#[post("/v1/pki/revoke", format = "json", data = "<payload>")]
+async fn vault_revoke_certificate(
+    state: &State<AppState>,
+    payload: Json<VaultRevokeRequest>,
+    authentication: TokenAuthenticated
+) -> Result<Json<VaultResponse<VaultRevokeData>>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    let serial = vault::parse_serial(&payload.serial_number)?;
+
+    let db = state.db.lock().await;
+    let cert = db.get_user_cert_by_serial(&serial)?
+        .ok_or(ApiError::BadRequest(format!("No certificate with serial number {}", payload.serial_number)))?;
+    // Like Vault, revoking a revoked certificate returns the original revocation time
+    let revoked_on = match cert.revoked_on {
+        Some(revoked_on) => revoked_on,
+        None => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+            db.revoke_user_cert(cert.id, now)?;
+            now
         }
     };
+    let data = VaultRevokeData {
+        revocation_time: revoked_on / 1000,
+        revocation_time_rfc3339: chrono::DateTime::from_timestamp_millis(revoked_on).unwrap_or_default().to_rfc3339(),
+    };
+    Ok(Json(vault::create_response(data, Vec::new())?))
+}
 
+#[get("/api/ssh/keys")]
+async fn get_ssh_public_keys(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<SshPublicKey>>, ApiError> {
+    let db = state.db.lock().await;
+    let user_id = if authentication.claims.role == UserRole::Admin {
+            None
+        } else {
+            Some(authentication.claims.id)
+        };
+    let keys = db.get_all_ssh_public_keys(user_id)?;
+    Ok(Json(keys))
+}
 
-    db.insert_user_cert(&mut cert)?;
+#[post("/api/ssh/keys", format = "json", data = "<payload>")]
+async fn create_ssh_public_key(
+    state: &State<AppState>,
+    payload: Json<CreateSshPublicKeyRequest>,
+    authentication: Authenticated
+) -> Result<Json<SshPublicKey>, ApiError> {
+    let user_id = payload.user_id.unwrap_or(authentication.claims.id);
+    if user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
 
-    if Some(true) == payload.notify_user {
-        let user = db.get_user(payload.user_id)?;
-        let mail = MailMessage{
-            to: format!("{} <{}>", user.name, user.email),
-            subject: "VaulTLS: A new certificate is available".to_string(),
-            username: user.name,
-            certificate: cert.clone()
-        };
+    let db = state.db.lock().await;
+    let (public_key, fingerprint) = ssh::parse_public_key(&payload.public_key)?;
+    let mut key = SshPublicKey {
+        id: -1,
+        name: payload.name.clone(),
+        created_on: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64,
+        user_id,
+        public_key,
+        fingerprint,
+    };
+    db.insert_ssh_public_key(&mut key)?;
 
-        let mailer = state.mailer.clone();
-        tokio::spawn(async move {
-            if let Some(mailer) = &mut *mailer.lock().await {
-                let _ = mailer.send_email(mail).await;
-            }
-        });
-    }
+    Ok(Json(key))
+}
 
-    Ok(Json(cert))
+#[delete("/api/ssh/keys/<id>")]
+async fn delete_ssh_public_key(
+    state: &State<AppState>,
+    id: i64,
+    authentication: Authenticated
+) -> Result<(), ApiError> {
+    let db = state.db.lock().await;
+    let key = db.get_ssh_public_key(id)?;
+    if key.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    db.delete_ssh_public_key(id)?;
+    Ok(())
 }
 
-#[get("/api/certificates/ca/download")]
-async fn download_ca(
-    state: &State<AppState>
-) -> Result<DownloadResponse, ApiError> {
+#[get("/api/ssh/certificates")]
+async fn get_ssh_certificates(
+    state: &State<AppState>,
+    authentication: Authenticated
+) -> Result<Json<Vec<SshCertificate>>, ApiError> {
     let db = state.db.lock().await;
-    let ca = db.get_current_ca()?;
-    let pem = get_pem(&ca)?;
-    Ok(DownloadResponse::new(pem, "ca_certificate.pem"))
+    let user_id = if authentication.claims.role == UserRole::Admin {
+            None
+        } else {
+            Some(authentication.claims.id)
+        };
+    let certificates = db.get_all_ssh_cert(user_id)?;
+    Ok(Json(certificates))
 }
-#[get("/api/certificates/<id>/download")]
-async fn download_certificate(
+
+#[post("/api/ssh/certificates", format = "json", data = "<payload>")]
+async fn create_ssh_certificate(
     state: &State<AppState>,
-    id: i64,
+    payload: Json<CreateSshCertificateRequest>,
     authentication: Authenticated
-) -> Result<DownloadResponse, ApiError> {
+) -> Result<Json<SshCertificate>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+
     let db = state.db.lock().await;
-    let (user_id, name, pkcs12) = db.get_user_cert_pkcs12(id)?;
-    if user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
-    Ok(DownloadResponse::new(pkcs12, &format!("{}.p12", name)))
+    let user = db.get_user(payload.user_id)?;
+
+    let public_key = match (payload.public_key_id, &payload.public_key) {
+        (Some(public_key_id), _) => {
+            let key = db.get_ssh_public_key(public_key_id)?;
+            if key.user_id != user.id {
+                return Err(ApiError::BadRequest("SSH public key does not belong to the user".to_string()))
+            }
+            key.public_key
+        }
+        (None, Some(public_key)) => public_key.clone(),
+        (None, None) => return Err(ApiError::BadRequest("SSH public key is not provided".to_string()))
+    };
+
+    let validity_in_days = payload.validity_in_days.unwrap_or(30);
+    if validity_in_days == 0 || validity_in_days > SSH_CERT_MAX_DAYS {
+        return Err(ApiError::BadRequest(format!("Validity must be between 1 and {} days", SSH_CERT_MAX_DAYS)))
+    }
+
+    let certificate_type = payload.cert_type.unwrap_or_default();
+    let (key_id, principals) = match certificate_type {
+        SshCertificateType::User => (user.email.clone(), ssh::get_user_principals(&user.email)),
+        SshCertificateType::Host => (payload.cert_name.clone(), payload.principals.clone().unwrap_or_else(|| vec![payload.cert_name.clone()]))
+    };
+    let options = SshCertificateOptions {
+        certificate_type,
+        principals,
+        validity_in_days,
+        force_command: payload.force_command.clone(),
+        source_address: payload.source_address.clone(),
+    };
+
+    let ssh_ca = db.get_current_ssh_ca()?;
+    let mut cert = ssh::create_ssh_cert(&ssh_ca, &payload.cert_name, user.id, &key_id, &public_key, &options)?;
+    db.insert_ssh_cert(&mut cert)?;
+
+    Ok(Json(cert))
 }
 
-#[get("/api/certificates/<id>/password")]
-async fn fetch_certificate_password(
+#[get("/api/ssh/certificates/<id>/download")]
+async fn download_ssh_certificate(
     state: &State<AppState>,
     id: i64,
     authentication: Authenticated
-) -> Result<Json<String>, ApiError> {
+) -> Result<DownloadResponse, ApiError> {
     let db = state.db.lock().await;
-    let (user_id, pkcs12_password) = db.get_user_cert_pkcs12_password(id)?;
-    if user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
-    Ok(Json(pkcs12_password))
+    let cert = db.get_ssh_cert(id)?;
+    if cert.user_id != authentication.claims.id && authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+    Ok(DownloadResponse::new(format!("{}\n", cert.certificate).into_bytes(), &format!("{}-cert.pub", cert.name)))
 }
 
-#[delete("/api/certificates/<id>")]
-async fn delete_user_cert(
+#[delete("/api/ssh/certificates/<id>")]
+async fn delete_ssh_certificate(
     state: &State<AppState>,
     id: i64,
     authentication: Authenticated
 ) -> Result<(), ApiError> {
     if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
     let db = state.db.lock().await;
-    db.delete_user_cert(id)?;
+    db.delete_ssh_cert(id)?;
     Ok(())
 }
 
@@ -213,9 +1677,10 @@ async fn update_settings(
     let mut oidc = state.oidc.lock().await;
 
     settings.set_settings(&payload).await?;
+    state.key_pool.set_limits(&settings.get_key_pool());
 
     if let Some(oidc) = &mut *oidc {
-        oidc.update_config(&settings.get_oidc()).await?;
+        oidc.update_config(settings.get_oidc()).await?;
     }
 
     let mut mailer = state.mailer.lock().await;
@@ -278,7 +1743,13 @@ async fn setup(
 
     let mut ca = create_ca(&setup_req.ca_name, setup_req.ca_validity_in_years)?;
     save_ca(&ca)?;
-    db.insert_ca(&mut ca)?;
+    db.insert_ca(&mut ca, CaPurpose::General, true)?;
+
+    let mut ssh_ca = ssh::create_ssh_ca()?;
+    db.insert_ssh_ca(&mut ssh_ca)?;
+
+    let mut ct_log_key = ctlog::create_log_key()?;
+    db.insert_ct_log_key(&mut ct_log_key)?;
 
     Ok(())
 }
@@ -291,9 +1762,9 @@ async fn login(
 ) -> Result<(), ApiError> {
     let settings = state.settings.lock().await;
     let db = state.db.lock().await;
-    let user: User = db.get_user_by_email(&*login_req_opt.email).map_err(|_| ApiError::Unauthorized(Some("Invalid credentials".to_string())))?;
+    let user: User = db.get_user_by_email(&login_req_opt.email).map_err(|_| ApiError::Unauthorized(Some("Invalid credentials".to_string())))?;
     if let Some(password_hash) = user.password_hash {
-        verify_password(&password_hash, &*login_req_opt.password)?;
+        verify_password(&password_hash, &login_req_opt.password)?;
         let jwt_key = settings.get_jwt_key()?;
         let token = generate_token(&jwt_key, user.id, user.role)?;
 
@@ -430,6 +1901,54 @@ async fn create_user(
     Ok(Json(user.id))
 }
 
+#[post("/api/users/import", format = "json", data = "<payload>")]
+async fn import_users(
+    state: &State<AppState>,
+    payload: Json<BulkImportRequest>,
+    authentication: Authenticated
+) -> Result<Json<BulkImportResponse>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+
+    let rows = import::parse_rows(&payload)?;
+    let mut private_keys = Vec::new();
+    for row in &rows {
+        private_keys.push(match row.cert_name {
+            Some(_) => Some(state.key_pool.take(row.key_algorithm.unwrap_or_default()).await?),
+            None => None
+        });
+    }
+
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    let (response, issued) = import::import(&db, &settings, &payload, &rows, private_keys)?;
+
+    if payload.notify_users {
+        for (user, cert) in issued {
+            notify_user(state.mailer.clone(), user, cert);
+        }
+    }
+
+    Ok(Json(response))
+}
+
+#[post("/api/certificates/import/easy-rsa", format = "json", data = "<payload>")]
+async fn import_easy_rsa(
+    state: &State<AppState>,
+    payload: Json<EasyRsaImportRequest>,
+    authentication: Authenticated
+) -> Result<Json<EasyRsaImportResponse>, ApiError> {
+    if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
+
+    let settings = state.settings.lock().await;
+    let db = state.db.lock().await;
+    let response = easyrsa::import(&db, &settings, &payload)?;
+    if response.committed {
+        save_current_ca(&db)?;
+    }
+
+    Ok(Json(response))
+}
+
 #[put("/api/users", format = "json", data = "<payload>")]
 async fn update_user(
     state: &State<AppState>,
@@ -449,7 +1968,8 @@ async fn delete_user(
 ) -> Result<(), ApiError> {
     if authentication.claims.role != UserRole::Admin { return Err(ApiError::Forbidden(None)) }
     let db = state.db.lock().await;
-    db.delete_user(id)?;
+    let deleted_on = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    db.delete_user(id, deleted_on)?;
     Ok(())
 }
 
@@ -477,12 +1997,24 @@ async fn rocket() -> _ {
         fs::set_permissions(db_path, perms).unwrap();
     }
 
+    if db.is_setup() && db.get_current_ssh_ca().is_err() {
+        println!("Creating SSH CA for existing installation");
+        let mut ssh_ca = ssh::create_ssh_ca().expect("Failed creating SSH CA");
+        db.insert_ssh_ca(&mut ssh_ca).expect("Failed saving SSH CA");
+    }
+
+    if db.is_setup() && db.get_ct_log_key().is_err() {
+        println!("Creating certificate transparency log key for existing installation");
+        let mut ct_log_key = ctlog::create_log_key().expect("Failed creating certificate transparency log key");
+        db.insert_ct_log_key(&mut ct_log_key).expect("Failed saving certificate transparency log key");
+    }
+
     let oidc_settings = settings.get_oidc();
     let oidc = match oidc_settings.auth_url.is_empty() {
         true => None,
         false => {
             println!("OIDC enabled. Trying to connect to {}.", oidc_settings.auth_url);
-            OidcAuth::new(&settings.get_oidc()).await.ok()
+            OidcAuth::new(settings.get_oidc()).await.ok()
         }
     };
 
@@ -497,9 +2029,13 @@ async fn rocket() -> _ {
     let rocket_secret = get_secret("VAULTLS_API_SECRET").expect("Failed to get VAULTLS_API_SECRET");
     unsafe { env::set_var("ROCKET_SECRET_KEY", rocket_secret) }
 
+    println!("Starting key pool");
+    let key_pool = KeyPool::start(&settings.get_key_pool());
+
     let app_state = AppState {
         db: Arc::new(Mutex::new(db)),
         settings: Arc::new(Mutex::new(settings)),
+        key_pool,
         oidc: Arc::new(Mutex::new(oidc)),
         mailer: Arc::new(Mutex::new(mailer))
     };
@@ -525,12 +2061,83 @@ async fn rocket() -> _ {
             routes![
                 index,
                 version,
+                get_key_pool_status,
                 get_certificates,
                 create_user_certificate,
+                preview_user_certificate,
+                get_server_certificate_requests,
+                create_server_certificate_request,
+                validate_server_certificate_request,
+                delete_server_certificate_request,
+                get_short_lived_certificates,
+                create_short_lived_certificate,
+                get_certificate_authorities,
+                create_certificate_authority,
+                update_certificate_authority,
                 download_ca,
+                download_ca_by_id,
+                download_ca_der,
+                get_trust_anchors,
+                create_trust_anchor,
+                update_trust_anchor,
+                delete_trust_anchor,
+                download_trust_bundle,
+                download_crl,
+                ocsp_responder,
+                get_ca_backups,
+                create_ca_backup,
+                download_ca_backup,
+                download_ca_backup_share,
+                delete_ca_backup,
+                restore_ca_backup,
+                download_spiffe_bundle,
+                timestamp_authority,
+                ct_get_sth,
+                ct_get_sth_consistency,
+                ct_get_proof_by_hash,
+                ct_get_entries,
+                ct_get_log_key,
                 download_certificate,
+                download_freeradius_config,
+                download_openvpn_profile,
+                download_mobileconfig,
+                download_onc,
+                revoke_user_cert,
                 delete_user_cert,
                 fetch_certificate_password,
+                update_certificate_password,
+                rekey_certificate,
+                renew_certificate,
+                verify_certificate,
+                lint_certificate,
+                lint_ca_certificates,
+                lint_user_certificate,
+                download_ssh_ca,
+                get_api_tokens,
+                create_api_token,
+                delete_api_token,
+                get_enrollment_tokens,
+                create_enrollment_token,
+                delete_enrollment_token,
+                enroll_device,
+                vault_issue_certificate,
+                vault_sign_certificate,
+                vault_download_ca,
+                vault_download_issuer,
+                vault_download_crl,
+                vault_download_issuer_crl,
+                vault_revoke_certificate,
+                get_ssh_public_keys,
+                create_ssh_public_key,
+                delete_ssh_public_key,
+                get_ssh_certificates,
+                create_ssh_certificate,
+                download_ssh_certificate,
+                delete_ssh_certificate,
+                get_policies,
+                create_policy,
+                update_policy,
+                delete_policy,
                 fetch_settings,
                 update_settings,
                 is_setup,
@@ -543,10 +2150,18 @@ async fn rocket() -> _ {
                 get_current_user,
                 get_users,
                 create_user,
+                import_users,
+                import_easy_rsa,
                 delete_user,
                 update_user
             ],
         )
         .attach(cors.to_cors().unwrap())
         .attach(AdHoc::config::<Settings>())
+        .attach(AdHoc::on_request("Vault write requests", |request, _| Box::pin(async move {
+            // Vault clients write with PUT, which Vault treats like POST
+            if request.method() == Method::Put && request.uri().path().starts_with("/v1/pki/") {
+                request.set_method(Method::Post);
+            }
+        })))
 }
@@ -0,0 +1,133 @@
+use openssl::base64;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkcs7::{Pkcs7, Pkcs7Flags};
+use openssl::stack::Stack;
+use openssl::x509::X509;
+use crate::ApiError;
+use crate::cert::Certificate;
+use crate::helper::random_uuid;
+
+/// Creates an Apple configuration profile installing the CA as trusted root and the PKCS#12 of the certificate.
+/// The PKCS#12 password is only included on request, otherwise the device asks for it during installation.
+/// If a signer is given, the profile is signed with its certificate and key (CMS with attached content).
+pub(crate) fn create_mobileconfig(
+    cert: &Certificate,
+    ca: &Certificate,
+    include_password: bool,
+    signer: Option<&Certificate>
+) -> Result<Vec<u8>, ApiError> {
+    let identifier = format!("vaultls.certificate.{}", cert.serial);
+    let ca_cert = X509::from_der(&ca.cert)?;
+    let ca_name = ca_cert.subject_name().entries().next()
+        .and_then(|entry| entry.data().to_string().ok())
+        .unwrap_or_else(|| "VaulTLS CA".to_string());
+
+    let ca_payload = format!("\
+        <dict>
+            <key>PayloadType</key>
+            <string>com.apple.security.root</string>
+            <key>PayloadIdentifier</key>
+            <string>{identifier}.ca</string>
+            <key>PayloadUUID</key>
+            <string>{uuid}</string>
+            <key>PayloadVersion</key>
+            <integer>1</integer>
+            <key>PayloadDisplayName</key>
+            <string>{ca_name}</string>
+            <key>PayloadCertificateFileName</key>
+            <string>ca.cer</string>
+            <key>PayloadContent</key>
+            <data>{content}</data>
+        </dict>",
+        identifier = identifier,
+        uuid = random_uuid()?,
+        ca_name = escape_xml(&ca_name),
+        content = base64::encode_block(&ca.cert),
+    );
+    let password = match include_password && !cert.pkcs12_password.is_empty() {
+        true => format!("
+            <key>Password</key>
+            <string>{}</string>", escape_xml(&cert.pkcs12_password)),
+        false => String::new()
+    };
+    let pkcs12_payload = format!("\
+        <dict>
+            <key>PayloadType</key>
+            <string>com.apple.security.pkcs12</string>
+            <key>PayloadIdentifier</key>
+            <string>{identifier}.pkcs12</string>
+            <key>PayloadUUID</key>
+            <string>{uuid}</string>
+            <key>PayloadVersion</key>
+            <integer>1</integer>
+            <key>PayloadDisplayName</key>
+            <string>{name}</string>
+            <key>PayloadCertificateFileName</key>
+            <string>{name}.p12</string>{password}
+            <key>PayloadContent</key>
+            <data>{content}</data>
+        </dict>",
+        identifier = identifier,
+        uuid = random_uuid()?,
+        name = escape_xml(&cert.name),
+        password = password,
+        content = base64::encode_block(&cert.pkcs12),
+    );
+
+    let profile = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>PayloadType</key>
+    <string>Configuration</string>
+    <key>PayloadIdentifier</key>
+    <string>{identifier}</string>
+    <key>PayloadUUID</key>
+    <string>{uuid}</string>
+    <key>PayloadVersion</key>
+    <integer>1</integer>
+    <key>PayloadDisplayName</key>
+    <string>{name}</string>
+    <key>PayloadDescription</key>
+    <string>Installs the certificate {name} and the CA {ca_name}.</string>
+    <key>PayloadOrganization</key>
+    <string>VaulTLS</string>
+    <key>PayloadContent</key>
+    <array>
+        {ca_payload}
+        {pkcs12_payload}
+    </array>
+</dict>
+</plist>
+"#,
+        identifier = identifier,
+        uuid = random_uuid()?,
+        name = escape_xml(&cert.name),
+        ca_name = escape_xml(&ca_name),
+        ca_payload = ca_payload,
+        pkcs12_payload = pkcs12_payload,
+    );
+
+    match signer {
+        Some(signer) => sign_profile(profile.as_bytes(), signer),
+        None => Ok(profile.into_bytes())
+    }
+}
+
+/// Signs the profile with the certificate and key of the signer, including its chain.
+fn sign_profile(profile: &[u8], signer: &Certificate) -> Result<Vec<u8>, ApiError> {
+    let parsed = Pkcs12::from_der(&signer.pkcs12)?.parse2(&signer.pkcs12_password)?;
+    let (Some(signer_cert), Some(signer_key)) = (parsed.cert, parsed.pkey) else {
+        return Err(ApiError::Other("Profile signing certificate has no private key".to_string()))
+    };
+    let chain = match parsed.ca {
+        Some(chain) => chain,
+        None => Stack::new()?
+    };
+    let signed = Pkcs7::sign(&signer_cert, &signer_key, &chain, profile, Pkcs7Flags::BINARY)?;
+    Ok(signed.to_der()?)
+}
+
+fn escape_xml(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,262 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use der::asn1::{BitString, GeneralizedTime, Null, ObjectIdentifier, OctetString};
+use der::oid::db::rfc6960::{ID_PKIX_OCSP_BASIC, ID_PKIX_OCSP_NONCE};
+use der::oid::db::rfc5912::{ECDSA_WITH_SHA_256, ID_SHA_1, ID_SHA_256, SHA_256_WITH_RSA_ENCRYPTION};
+use der::{Choice, Decode, Encode, Enumerated, Sequence};
+use openssl::bn::BigNum;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{Id, PKey, Private};
+use openssl::sign::Signer;
+use x509_cert::ext::pkix::crl::CrlReason;
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::Extensions;
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::AlgorithmIdentifierOwned;
+use crate::ApiError;
+use crate::cert::Certificate;
+use crate::constants::CRL_VALIDITY_SECS;
+use crate::data::enums::RevocationReason;
+use crate::db::VaulTLSDB;
+
+/// OCSP request and response structures as defined in RFC 6960.
+#[derive(Sequence)]
+struct OcspRequest {
+    tbs_request: TbsRequest,
+    #[asn1(context_specific = "0", tag_mode = "EXPLICIT", optional = "true")]
+    optional_signature: Option<der::Any>,
+}
+
+#[derive(Sequence)]
+struct TbsRequest {
+    #[asn1(context_specific = "0", tag_mode = "EXPLICIT", optional = "true")]
+    version: Option<u8>,
+    #[asn1(context_specific = "1", tag_mode = "EXPLICIT", optional = "true")]
+    requestor_name: Option<GeneralName>,
+    request_list: Vec<Request>,
+    #[asn1(context_specific = "2", tag_mode = "EXPLICIT", optional = "true")]
+    request_extensions: Option<Extensions>,
+}
+
+#[derive(Sequence)]
+struct Request {
+    req_cert: CertId,
+    #[asn1(context_specific = "0", tag_mode = "EXPLICIT", optional = "true")]
+    single_request_extensions: Option<Extensions>,
+}
+
+#[derive(Sequence, Clone)]
+struct CertId {
+    hash_algorithm: AlgorithmIdentifierOwned,
+    issuer_name_hash: OctetString,
+    issuer_key_hash: OctetString,
+    serial_number: SerialNumber,
+}
+
+#[derive(Sequence)]
+struct OcspResponse {
+    response_status: OcspResponseStatus,
+    #[asn1(context_specific = "0", tag_mode = "EXPLICIT", optional = "true")]
+    response_bytes: Option<ResponseBytes>,
+}
+
+#[derive(Enumerated, Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u32)]
+enum OcspResponseStatus {
+    Successful = 0,
+    MalformedRequest = 1,
+    InternalError = 2,
+    Unauthorized = 6,
+}
+
+#[derive(Sequence)]
+struct ResponseBytes {
+    response_type: ObjectIdentifier,
+    response: OctetString,
+}
+
+#[derive(Sequence)]
+struct BasicOcspResponse {
+    tbs_response_data: ResponseData,
+    signature_algorithm: AlgorithmIdentifierOwned,
+    signature: BitString,
+}
+
+#[derive(Sequence)]
+struct ResponseData {
+    #[asn1(context_specific = "0", tag_mode = "EXPLICIT", optional = "true")]
+    version: Option<u8>,
+    responder_id: ResponderId,
+    produced_at: GeneralizedTime,
+    responses: Vec<SingleResponse>,
+    #[asn1(context_specific = "1", tag_mode = "EXPLICIT", optional = "true")]
+    response_extensions: Option<Extensions>,
+}
+
+#[derive(Choice)]
+#[allow(dead_code)]
+enum ResponderId {
+    #[asn1(context_specific = "1", tag_mode = "EXPLICIT", constructed = "true")]
+    ByName(Name),
+    #[asn1(context_specific = "2", tag_mode = "EXPLICIT", constructed = "true")]
+    ByKey(OctetString),
+}
+
+#[derive(Sequence)]
+struct SingleResponse {
+    cert_id: CertId,
+    cert_status: CertStatus,
+    this_update: GeneralizedTime,
+    #[asn1(context_specific = "0", tag_mode = "EXPLICIT", optional = "true")]
+    next_update: Option<GeneralizedTime>,
+}
+
+#[derive(Choice)]
+enum CertStatus {
+    #[asn1(context_specific = "0", tag_mode = "IMPLICIT")]
+    Good(Null),
+    #[asn1(context_specific = "1", tag_mode = "IMPLICIT", constructed = "true")]
+    Revoked(RevokedInfo),
+    #[asn1(context_specific = "2", tag_mode = "IMPLICIT")]
+    Unknown(Null),
+}
+
+#[derive(Sequence)]
+struct RevokedInfo {
+    revocation_time: GeneralizedTime,
+    #[asn1(context_specific = "0", tag_mode = "EXPLICIT", optional = "true")]
+    revocation_reason: Option<CrlReason>,
+}
+
+/// Answers a DER encoded OCSP request.
+/// Errors are reported through the OCSP response status, so this always returns a response.
+pub(crate) fn respond(db: &VaulTLSDB, request: &[u8]) -> Vec<u8> {
+    let response = match create_response(db, request) {
+        Ok(response) => response,
+        Err(status) => OcspResponse { response_status: status, response_bytes: None }
+    };
+    response.to_der().unwrap_or_default()
+}
+
+fn create_response(db: &VaulTLSDB, request: &[u8]) -> Result<OcspResponse, OcspResponseStatus> {
+    let request = OcspRequest::from_der(request).map_err(|_| OcspResponseStatus::MalformedRequest)?;
+    let request_list = &request.tbs_request.request_list;
+    if request_list.is_empty() {
+        return Err(OcspResponseStatus::MalformedRequest)
+    }
+
+    let cas = db.get_all_ca().map_err(|_| OcspResponseStatus::InternalError)?;
+    // All certificates of one request must be issued by the same CA, which signs the response
+    let ca = find_issuer(&cas, &request_list[0].req_cert)?;
+    for request in request_list {
+        if find_issuer(&cas, &request.req_cert)?.id != ca.id {
+            return Err(OcspResponseStatus::Unauthorized)
+        }
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let this_update = to_generalized_time(now)?;
+    let next_update = to_generalized_time(now + Duration::from_secs(CRL_VALIDITY_SECS as u64))?;
+
+    let mut responses = Vec::new();
+    for request in request_list {
+        let cert_id = request.req_cert.clone();
+        let serial = BigNum::from_slice(cert_id.serial_number.as_bytes())
+            .and_then(|serial| serial.to_hex_str().map(|hex| hex.to_string()))
+            .map_err(|_| OcspResponseStatus::MalformedRequest)?;
+        let stored = db.get_user_cert_by_serial(&serial).map_err(|_| OcspResponseStatus::InternalError)?;
+        let cert_status = match stored {
+            Some(cert) if cert.ca_id == ca.id => match cert.revoked_on {
+                Some(revoked_on) => CertStatus::Revoked(RevokedInfo {
+                    revocation_time: to_generalized_time(Duration::from_millis(revoked_on as u64))?,
+                    revocation_reason: match cert.revocation_reason {
+                        Some(RevocationReason::Superseded) => Some(CrlReason::Superseded),
+                        Some(RevocationReason::CessationOfOperation) => Some(CrlReason::CessationOfOperation),
+                        _ => None
+                    }
+                }),
+                None => CertStatus::Good(Null)
+            },
+            _ => CertStatus::Unknown(Null)
+        };
+        responses.push(SingleResponse { cert_id, cert_status, this_update, next_update: Some(next_update) });
+    }
+
+    // Echo the nonce to protect against replay
+    let nonce = request.tbs_request.request_extensions
+        .and_then(|extensions| extensions.into_iter().find(|extension| extension.extn_id == ID_PKIX_OCSP_NONCE));
+
+    let (ca_cert, ca_key) = decode_ca(ca)?;
+    let key_hash = hash(MessageDigest::sha1(), ca_cert.tbs_certificate.subject_public_key_info.subject_public_key.raw_bytes())
+        .map_err(|_| OcspResponseStatus::InternalError)?;
+
+    let tbs_response_data = ResponseData {
+        version: None,
+        responder_id: ResponderId::ByKey(OctetString::new(key_hash.to_vec()).map_err(|_| OcspResponseStatus::InternalError)?),
+        produced_at: this_update,
+        responses,
+        response_extensions: nonce.map(|nonce| vec![nonce]),
+    };
+    let tbs = tbs_response_data.to_der().map_err(|_| OcspResponseStatus::InternalError)?;
+    let (signature_algorithm, signature) = sign(&ca_key, &tbs).map_err(|_| OcspResponseStatus::InternalError)?;
+
+    let basic_response = BasicOcspResponse {
+        tbs_response_data,
+        signature_algorithm,
+        signature: BitString::from_bytes(&signature).map_err(|_| OcspResponseStatus::InternalError)?,
+    };
+    let response = basic_response.to_der()
+        .and_then(OctetString::new)
+        .map_err(|_| OcspResponseStatus::InternalError)?;
+
+    Ok(OcspResponse {
+        response_status: OcspResponseStatus::Successful,
+        response_bytes: Some(ResponseBytes { response_type: ID_PKIX_OCSP_BASIC, response }),
+    })
+}
+
+/// Finds the CA whose name and key hash match the certificate id.
+fn find_issuer<'a>(cas: &'a [Certificate], cert_id: &CertId) -> Result<&'a Certificate, OcspResponseStatus> {
+    let digest = match cert_id.hash_algorithm.oid {
+        ID_SHA_1 => MessageDigest::sha1(),
+        ID_SHA_256 => MessageDigest::sha256(),
+        _ => return Err(OcspResponseStatus::MalformedRequest)
+    };
+
+    for ca in cas {
+        let Ok(ca_cert) = x509_cert::Certificate::from_der(&ca.cert) else { continue };
+        let Ok(name) = ca_cert.tbs_certificate.subject.to_der() else { continue };
+        let public_key = ca_cert.tbs_certificate.subject_public_key_info.subject_public_key.raw_bytes();
+        let (Ok(name_hash), Ok(key_hash)) = (hash(digest, &name), hash(digest, public_key)) else { continue };
+        if *name_hash == *cert_id.issuer_name_hash.as_bytes() && *key_hash == *cert_id.issuer_key_hash.as_bytes() {
+            return Ok(ca)
+        }
+    }
+
+    Err(OcspResponseStatus::Unauthorized)
+}
+
+fn decode_ca(ca: &Certificate) -> Result<(x509_cert::Certificate, PKey<Private>), OcspResponseStatus> {
+    let ca_cert = x509_cert::Certificate::from_der(&ca.cert).map_err(|_| OcspResponseStatus::InternalError)?;
+    let ca_key = PKey::private_key_from_der(&ca.key).map_err(|_| OcspResponseStatus::InternalError)?;
+    Ok((ca_cert, ca_key))
+}
+
+/// Signs DER encoded data with SHA-256 and returns the matching signature algorithm identifier.
+pub(crate) fn sign(key: &PKey<Private>, data: &[u8]) -> Result<(AlgorithmIdentifierOwned, Vec<u8>), ApiError> {
+    let algorithm = match key.id() {
+        Id::EC => AlgorithmIdentifierOwned { oid: ECDSA_WITH_SHA_256, parameters: None },
+        Id::RSA => AlgorithmIdentifierOwned { oid: SHA_256_WITH_RSA_ENCRYPTION, parameters: Some(Null.into()) },
+        _ => return Err(ApiError::Other("Unsupported CA key type".to_string()))
+    };
+
+    let mut signer = Signer::new(MessageDigest::sha256(), key)?;
+    signer.update(data)?;
+    Ok((algorithm, signer.sign_to_vec()?))
+}
+
+fn to_generalized_time(since_epoch: Duration) -> Result<GeneralizedTime, OcspResponseStatus> {
+    // DER GeneralizedTime must not contain fractional seconds
+    GeneralizedTime::from_unix_duration(Duration::from_secs(since_epoch.as_secs()))
+        .map_err(|_| OcspResponseStatus::InternalError)
+}
@@ -0,0 +1,114 @@
+use openssl::base64;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+use openssl::symm::{encrypt, Cipher};
+use serde::Serialize;
+use crate::ApiError;
+use crate::cert::{repackage_pkcs12, Certificate};
+
+/// PBKDF2 iterations deriving the key of encrypted configurations.
+const PBKDF2_ITERATIONS: usize = 20000;
+
+/// Open Network Configuration as imported by ChromeOS.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct UnencryptedConfiguration {
+    r#type: &'static str,
+    certificates: Vec<OncCertificate>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct OncCertificate {
+    #[serde(rename = "GUID")]
+    guid: String,
+    r#type: &'static str,
+    #[serde(rename = "X509", skip_serializing_if = "Option::is_none")]
+    x509: Option<String>,
+    #[serde(rename = "PKCS12", skip_serializing_if = "Option::is_none")]
+    pkcs12: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    trust_bits: Vec<&'static str>,
+}
+
+/// Configuration encrypted with a passphrase, ChromeOS asks for it during the import.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EncryptedConfiguration {
+    r#type: &'static str,
+    cipher: &'static str,
+    ciphertext: String,
+    #[serde(rename = "HMAC")]
+    hmac: String,
+    #[serde(rename = "HMACMethod")]
+    hmac_method: &'static str,
+    #[serde(rename = "IV")]
+    iv: String,
+    iterations: usize,
+    salt: String,
+    stretch: &'static str,
+}
+
+/// Creates an ONC file installing the CA as trusted web authority and the client certificate.
+/// ChromeOS only imports unprotected PKCS#12 data, so the configuration itself is encrypted
+/// with the PKCS#12 password instead, unless the certificate has no password.
+pub(crate) fn create_onc(cert: &Certificate, ca: &Certificate) -> Result<Vec<u8>, ApiError> {
+    let configuration = UnencryptedConfiguration {
+        r#type: "UnencryptedConfiguration",
+        certificates: vec![
+            OncCertificate {
+                guid: format!("vaultls-ca-{}", ca.id),
+                r#type: "Authority",
+                x509: Some(base64::encode_block(&ca.cert)),
+                pkcs12: None,
+                trust_bits: vec!["Web"],
+            },
+            OncCertificate {
+                guid: format!("vaultls-certificate-{}", cert.serial),
+                r#type: "Client",
+                x509: None,
+                pkcs12: Some(base64::encode_block(&repackage_pkcs12(cert, "")?)),
+                trust_bits: Vec::new(),
+            },
+        ],
+    };
+    let configuration = serde_json::to_vec_pretty(&configuration).map_err(|e| ApiError::Other(e.to_string()))?;
+    if cert.pkcs12_password.is_empty() {
+        return Ok(configuration)
+    }
+
+    let encrypted = encrypt_configuration(&configuration, &cert.pkcs12_password)?;
+    serde_json::to_vec_pretty(&encrypted).map_err(|e| ApiError::Other(e.to_string()))
+}
+
+/// Encrypts the configuration with AES-256-CBC and authenticates it with HMAC-SHA1,
+/// both keyed with PBKDF2-SHA1 of the passphrase as specified by ONC.
+fn encrypt_configuration(configuration: &[u8], passphrase: &str) -> Result<EncryptedConfiguration, ApiError> {
+    let mut salt = [0u8; 8];
+    rand_bytes(&mut salt)?;
+    let mut iv = [0u8; 16];
+    rand_bytes(&mut iv)?;
+    let mut key = [0u8; 32];
+    pbkdf2_hmac(passphrase.as_bytes(), &salt, PBKDF2_ITERATIONS, MessageDigest::sha1(), &mut key)?;
+
+    let ciphertext = encrypt(Cipher::aes_256_cbc(), &key, Some(&iv), configuration)?;
+    let hmac_key = PKey::hmac(&key)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &hmac_key)?;
+    signer.update(&ciphertext)?;
+    let hmac = signer.sign_to_vec()?;
+
+    Ok(EncryptedConfiguration {
+        r#type: "EncryptedConfiguration",
+        cipher: "AES256",
+        ciphertext: base64::encode_block(&ciphertext),
+        hmac: base64::encode_block(&hmac),
+        hmac_method: "SHA1",
+        iv: base64::encode_block(&iv),
+        iterations: PBKDF2_ITERATIONS,
+        salt: base64::encode_block(&salt),
+        stretch: "PBKDF2",
+    })
+}
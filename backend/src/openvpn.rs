@@ -0,0 +1,71 @@
+use std::fmt::Write;
+use openssl::pkcs12::Pkcs12;
+use openssl::x509::X509;
+use crate::ApiError;
+use crate::cert::Certificate;
+use crate::data::enums::OpenVpnProtocol;
+use crate::settings::OpenVpn;
+
+const TLS_CRYPT_KEY_HEADER: &str = "-----BEGIN OpenVPN Static key V1-----";
+
+/// Renders an OpenVPN client profile for a client certificate.
+/// The CA, the client certificate, its private key and the tls-crypt key are inlined.
+pub(crate) fn create_profile(template: &OpenVpn, cert: &Certificate, ca: &Certificate) -> Result<String, ApiError> {
+    let remote = template.remote.trim();
+    if remote.is_empty() {
+        return Err(ApiError::BadRequest("OpenVPN server is not configured".to_string()))
+    }
+    if remote.contains(char::is_whitespace) {
+        return Err(ApiError::BadRequest(format!("OpenVPN server {} is not a valid host name", remote)))
+    }
+    let tls_crypt_key = template.tls_crypt_key.as_deref().map(str::trim).filter(|key| !key.is_empty());
+    if tls_crypt_key.is_some_and(|key| !key.starts_with(TLS_CRYPT_KEY_HEADER)) {
+        return Err(ApiError::BadRequest("OpenVPN tls-crypt key is not a static key".to_string()))
+    }
+
+    let parsed = Pkcs12::from_der(&cert.pkcs12)?.parse2(&cert.pkcs12_password)?;
+    let (Some(client_cert), Some(client_key)) = (parsed.cert, parsed.pkey) else {
+        return Err(ApiError::BadRequest("Certificate has no private key".to_string()))
+    };
+    let ca_pem = X509::from_der(&ca.cert)?.to_pem()?;
+
+    let protocol = match template.protocol {
+        OpenVpnProtocol::Udp => "udp",
+        OpenVpnProtocol::Tcp => "tcp-client"
+    };
+    let mut profile = String::new();
+    let _ = write!(profile, "\
+# OpenVPN client profile generated by VaulTLS for {name}
+client
+dev tun
+proto {protocol}
+remote {remote} {port}
+resolv-retry infinite
+nobind
+persist-key
+persist-tun
+remote-cert-tls server
+verb 3
+",
+        name = cert.name,
+        protocol = protocol,
+        remote = remote,
+        port = template.port,
+    );
+    let cipher = template.cipher.trim();
+    if !cipher.is_empty() {
+        let _ = writeln!(profile, "data-ciphers {}", cipher);
+    }
+    append_inline(&mut profile, "ca", &String::from_utf8_lossy(&ca_pem));
+    append_inline(&mut profile, "cert", &String::from_utf8_lossy(&client_cert.to_pem()?));
+    append_inline(&mut profile, "key", &String::from_utf8_lossy(&client_key.private_key_to_pem_pkcs8()?));
+    if let Some(tls_crypt_key) = tls_crypt_key {
+        append_inline(&mut profile, "tls-crypt", tls_crypt_key);
+    }
+    Ok(profile)
+}
+
+/// Appends an inline file in the form `<tag>content</tag>`.
+fn append_inline(profile: &mut String, tag: &str, content: &str) {
+    let _ = write!(profile, "<{tag}>\n{}\n</{tag}>\n", content.trim_end(), tag = tag);
+}
@@ -0,0 +1,171 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::{ApiError, User};
+use crate::data::enums::{CertificateType, KeyAlgorithm, UserRole};
+use crate::db::VaulTLSDB;
+
+/// Admin-configured rule set evaluated before a certificate is signed.
+/// A policy applies to a user if its scope (user, role and certificate type) matches;
+/// unset scope fields match everything.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub(crate) struct IssuancePolicy {
+    #[serde(default)]
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    #[serde(default = "default_enabled")]
+    pub(crate) enabled: bool,
+    pub(crate) user_id: Option<i64>,
+    pub(crate) role: Option<UserRole>,
+    pub(crate) certificate_type: Option<CertificateType>,
+    #[serde(default)]
+    pub(crate) rules: PolicyRules,
+}
+
+/// Constraints of a policy. Empty lists and unset values are not enforced.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub(crate) struct PolicyRules {
+    /// Every DNS name must equal or be a subdomain of one of these suffixes
+    #[serde(default)]
+    pub(crate) allowed_dns_suffixes: Vec<String>,
+    /// Every SAN (DNS name or e-mail) must match one of these patterns, `*` matches any sequence
+    #[serde(default)]
+    pub(crate) allowed_san_patterns: Vec<String>,
+    pub(crate) max_validity_in_years: Option<u64>,
+    pub(crate) max_active_certificates: Option<u32>,
+    #[serde(default)]
+    pub(crate) allowed_key_algorithms: Vec<KeyAlgorithm>,
+    /// Common names matching one of these patterns are rejected, `*` matches any sequence
+    #[serde(default)]
+    pub(crate) forbidden_subjects: Vec<String>,
+}
+
+fn default_enabled() -> bool { true }
+
+/// A certificate about to be issued, as seen by the policy engine.
+pub(crate) struct IssuanceContext<'a> {
+    pub(crate) user: &'a User,
+    pub(crate) certificate_type: CertificateType,
+    pub(crate) common_name: &'a str,
+    pub(crate) dns_names: &'a [String],
+    pub(crate) email: Option<&'a str>,
+    pub(crate) validity_in_years: u64,
+    pub(crate) key_algorithm: KeyAlgorithm,
+    /// The certificate replaces an active certificate, which then does not count towards the limit
+    pub(crate) supersedes_active: bool,
+}
+
+impl IssuancePolicy {
+    /// Check if the policy scope covers the certificate to be issued.
+    fn applies_to(&self, context: &IssuanceContext) -> bool {
+        self.enabled
+            && self.user_id.is_none_or(|user_id| user_id == context.user.id)
+            && self.role.is_none_or(|role| role == context.user.role)
+            && self.certificate_type.is_none_or(|certificate_type| certificate_type == context.certificate_type)
+    }
+
+    /// Returns a rejection reason for every rule the certificate violates.
+    fn evaluate(&self, context: &IssuanceContext, active_certificates: usize) -> Vec<String> {
+        let rules = &self.rules;
+        let mut violations = Vec::new();
+
+        if !rules.allowed_dns_suffixes.is_empty() {
+            for dns_name in context.dns_names {
+                if !rules.allowed_dns_suffixes.iter().any(|suffix| matches_dns_suffix(suffix, dns_name)) {
+                    violations.push(format!("DNS name {} is not within the allowed suffixes {}", dns_name, rules.allowed_dns_suffixes.join(", ")));
+                }
+            }
+        }
+
+        if !rules.allowed_san_patterns.is_empty() {
+            for san in context.dns_names.iter().map(String::as_str).chain(context.email) {
+                if !rules.allowed_san_patterns.iter().any(|pattern| matches_pattern(pattern, san)) {
+                    violations.push(format!("Subject alternative name {} does not match the allowed patterns {}", san, rules.allowed_san_patterns.join(", ")));
+                }
+            }
+        }
+
+        if let Some(max_validity) = rules.max_validity_in_years {
+            if context.validity_in_years > max_validity {
+                violations.push(format!("Validity of {} years exceeds the maximum of {} years", context.validity_in_years, max_validity));
+            }
+        }
+
+        if let Some(max_active) = rules.max_active_certificates {
+            if active_certificates >= max_active as usize {
+                violations.push(format!("User already has {} active certificates, the maximum is {}", active_certificates, max_active));
+            }
+        }
+
+        if !rules.allowed_key_algorithms.is_empty() && !rules.allowed_key_algorithms.contains(&context.key_algorithm) {
+            violations.push(format!("Key algorithm {:?} is not allowed", context.key_algorithm));
+        }
+
+        if let Some(pattern) = rules.forbidden_subjects.iter().find(|pattern| matches_pattern(pattern, context.common_name)) {
+            violations.push(format!("Subject {} matches the forbidden pattern {}", context.common_name, pattern));
+        }
+
+        violations.into_iter()
+            .map(|violation| format!("Policy '{}': {}", self.name, violation))
+            .collect()
+    }
+}
+
+/// Evaluates all applicable policies and returns the rejection reasons.
+pub(crate) fn evaluate_issuance(db: &VaulTLSDB, context: &IssuanceContext) -> Result<Vec<String>, ApiError> {
+    let policies: Vec<IssuancePolicy> = db.get_all_policies()?
+        .into_iter()
+        .filter(|policy| policy.applies_to(context))
+        .collect();
+    if policies.is_empty() {
+        return Ok(Vec::new())
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let active_certificates = db.count_active_user_certs(context.user.id, now)?
+        .saturating_sub(context.supersedes_active as usize);
+
+    Ok(policies.iter()
+        .flat_map(|policy| policy.evaluate(context, active_certificates))
+        .collect())
+}
+
+/// Rejects the issuance if any applicable policy is violated.
+pub(crate) fn check_issuance(db: &VaulTLSDB, context: &IssuanceContext) -> Result<(), ApiError> {
+    let violations = evaluate_issuance(db, context)?;
+    match violations.is_empty() {
+        true => Ok(()),
+        false => Err(ApiError::Forbidden(Some(violations.join("; "))))
+    }
+}
+
+/// Checks if a DNS name (including wildcards) equals the suffix or is a subdomain of it.
+fn matches_dns_suffix(suffix: &str, dns_name: &str) -> bool {
+    let suffix = suffix.trim_start_matches('.').to_ascii_lowercase();
+    let dns_name = dns_name.trim_end_matches('.').to_ascii_lowercase();
+    dns_name == suffix || dns_name.ends_with(&format!(".{}", suffix))
+}
+
+/// Case-insensitive glob match where `*` matches any sequence of characters.
+pub(crate) fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let value: Vec<char> = value.to_lowercase().chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == value[v] {
+            p += 1;
+            v += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            v = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
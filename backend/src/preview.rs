@@ -0,0 +1,111 @@
+use openssl::pkey::{Id, PKey, Private, Public};
+use openssl::x509::X509Ref;
+use tokio::task;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::{DistributionPointName, ParsedExtension, X509Extension};
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::prelude::FromDer;
+use crate::ApiError;
+use crate::cert::{generate_key, get_key_algorithm, Certificate};
+use crate::data::api::{CertificateExtension, CertificatePreview, LintFinding};
+use crate::data::enums::KeyAlgorithm;
+use crate::verify::{format_general_name, get_extended_key_usage, get_subject_alt_names};
+
+/// Generates throwaway keys for a preview: a leaf key of the requested algorithm, unless the certificate
+/// is issued for a CSR, and a key of the CA's type to sign with. The preview thus shows the signature
+/// algorithm of the CA, but neither takes a key from the pool nor carries a signature of the CA.
+pub(crate) async fn create_throwaway_keys(
+    leaf_key_algorithm: Option<KeyAlgorithm>,
+    ca_public_key: PKey<Public>
+) -> Result<(Option<PKey<Private>>, PKey<Private>), ApiError> {
+    task::spawn_blocking(move || {
+        let leaf_key = leaf_key_algorithm.map(generate_key).transpose()?;
+        // The signature algorithm does not depend on the RSA key size, so the smaller key is faster
+        let signing_key_algorithm = match ca_public_key.id() {
+            Id::RSA => KeyAlgorithm::Rsa2048,
+            _ => get_key_algorithm(&ca_public_key)?
+        };
+        Ok((leaf_key, generate_key(signing_key_algorithm)?))
+    }).await.map_err(|e| ApiError::Other(format!("Key generation failed: {}", e)))?
+}
+
+/// Decodes a certificate signed with a throwaway key into a preview of what would be issued.
+pub(crate) fn create_preview(
+    leaf: &X509Ref,
+    ca: &Certificate,
+    key_algorithm: KeyAlgorithm,
+    issuable: bool,
+    policy_violations: Vec<String>,
+    lint_findings: Vec<LintFinding>
+) -> Result<CertificatePreview, ApiError> {
+    let der = leaf.to_der()?;
+    let (_, parsed) = X509Certificate::from_der(&der)
+        .map_err(|e| ApiError::Other(format!("Failed to decode certificate: {}", e)))?;
+
+    let extensions = parsed.extensions().iter()
+        .map(|extension| CertificateExtension {
+            oid: extension.oid.to_id_string(),
+            name: oid2sn(&extension.oid, oid_registry()).unwrap_or("unknown").to_string(),
+            critical: extension.critical,
+            value: describe_extension(&parsed, extension),
+        })
+        .collect();
+
+    Ok(CertificatePreview {
+        issuable,
+        ca_id: ca.id,
+        subject: parsed.subject().to_string(),
+        issuer: parsed.issuer().to_string(),
+        not_before: parsed.validity().not_before.timestamp() * 1000,
+        not_after: parsed.validity().not_after.timestamp() * 1000,
+        key_algorithm,
+        signature_algorithm: oid2sn(&parsed.signature_algorithm.algorithm, oid_registry())
+            .map(str::to_string)
+            .unwrap_or_else(|_| parsed.signature_algorithm.algorithm.to_id_string()),
+        subject_alt_names: get_subject_alt_names(&parsed),
+        extensions,
+        policy_violations,
+        lint_findings,
+    })
+}
+
+/// Returns a human-readable value of an extension, unknown extensions are shown as hex.
+fn describe_extension(cert: &X509Certificate, extension: &X509Extension) -> String {
+    match extension.parsed_extension() {
+        ParsedExtension::BasicConstraints(basic_constraints) => match (basic_constraints.ca, basic_constraints.path_len_constraint) {
+            (true, Some(path_len)) => format!("CA:TRUE, pathlen:{}", path_len),
+            (true, None) => "CA:TRUE".to_string(),
+            (false, _) => "CA:FALSE".to_string()
+        },
+        ParsedExtension::KeyUsage(key_usage) => key_usage.to_string(),
+        ParsedExtension::ExtendedKeyUsage(_) => get_extended_key_usage(cert).join(", "),
+        ParsedExtension::SubjectAlternativeName(_) => get_subject_alt_names(cert).join(", "),
+        ParsedExtension::SubjectKeyIdentifier(key_id) => to_hex(key_id.0),
+        ParsedExtension::AuthorityKeyIdentifier(aki) => aki.key_identifier.as_ref()
+            .map(|key_id| to_hex(key_id.0))
+            .unwrap_or_default(),
+        ParsedExtension::CRLDistributionPoints(points) => points.iter()
+            .filter_map(|point| match &point.distribution_point {
+                Some(DistributionPointName::FullName(names)) => Some(names),
+                _ => None
+            })
+            .flatten()
+            .map(format_general_name)
+            .collect::<Vec<String>>()
+            .join(", "),
+        ParsedExtension::AuthorityInfoAccess(aia) => aia.accessdescs.iter()
+            .map(|access| format!("{} - {}",
+                oid2sn(&access.access_method, oid_registry()).unwrap_or("unknown"),
+                format_general_name(&access.access_location)))
+            .collect::<Vec<String>>()
+            .join(", "),
+        _ => to_hex(extension.value)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(":")
+}
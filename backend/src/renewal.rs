@@ -0,0 +1,47 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::base64;
+use openssl::hash::MessageDigest;
+use openssl::sign::Verifier;
+use openssl::x509::X509;
+use crate::ApiError;
+use crate::cert::{get_serial_hex, Certificate};
+use crate::constants::RENEWAL_PROOF_MAX_AGE_SECS;
+use crate::data::api::RenewCertificateRequest;
+
+/// Checks that a renewal request carries the current certificate, issued by the CA,
+/// and a recent signature of the request with its key.
+pub(crate) fn verify_proof(payload: &RenewCertificateRequest, id: i64, cert: &Certificate, ca: &Certificate) -> Result<(), ApiError> {
+    let unauthorized = |reason: &str| ApiError::Unauthorized(Some(reason.to_string()));
+    let (Some(certificate), Some(timestamp), Some(signature)) = (&payload.certificate, payload.timestamp, &payload.signature) else {
+        return Err(unauthorized("Renewal requires a session, an API token or the current certificate with a signature"))
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    if (now - timestamp).abs() > RENEWAL_PROOF_MAX_AGE_SECS * 1000 {
+        return Err(unauthorized("Renewal signature is too old or the clock of the host is off"))
+    }
+    if cert.valid_until < now {
+        return Err(unauthorized("Expired certificates cannot authenticate their renewal"))
+    }
+
+    let leaf = X509::from_pem(certificate.as_bytes())
+        .map_err(|_| ApiError::BadRequest("Certificate is not a valid PEM certificate".to_string()))?;
+    // Only the current certificate is accepted, so a renewed certificate cannot renew again
+    if get_serial_hex(&leaf)? != cert.serial {
+        return Err(unauthorized("Certificate is not the current certificate"))
+    }
+    let ca_key = X509::from_der(&ca.cert)?.public_key()?;
+    if !leaf.verify(&ca_key)? {
+        return Err(unauthorized("Certificate was not issued by VaulTLS"))
+    }
+
+    let signature = base64::decode_block(signature.trim())
+        .map_err(|_| ApiError::BadRequest("Signature is not valid base64".to_string()))?;
+    let public_key = leaf.public_key()?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key)?;
+    verifier.update(&payload.signed_data(id))?;
+    if !verifier.verify(&signature).unwrap_or(false) {
+        return Err(unauthorized("Renewal signature is invalid"))
+    }
+    Ok(())
+}
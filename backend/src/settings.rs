@@ -6,7 +6,7 @@ use rocket::serde::json::serde_json;
 use rocket::serde::{Deserialize, Serialize};
 use rocket::serde::ser::SerializeStruct;
 use crate::ApiError;
-use crate::data::enums::{MailEncryption, PasswordRule};
+use crate::data::enums::{KeyAlgorithm, MailEncryption, OpenVpnProtocol, PasswordRule};
 use crate::constants::SETTINGS_FILE_PATH;
 use tokio::fs::OpenOptions;
 use tokio::io::AsyncWriteExt;
@@ -24,6 +24,8 @@ pub(crate) struct Settings {
     #[serde(default)]
     oidc: OIDC,
     #[serde(default)]
+    openvpn: OpenVpn,
+    #[serde(default)]
     logic: Logic
 }
 
@@ -40,6 +42,7 @@ impl Serialize for FrontendSettings {
         state.serialize_field("common", &self.0.common)?;
         state.serialize_field("mail", &self.0.mail)?;
         state.serialize_field("oidc", &self.0.oidc)?;
+        state.serialize_field("openvpn", &self.0.openvpn)?;
         state.end()
     }
 }
@@ -51,6 +54,28 @@ pub(crate) struct Common {
     vaultls_url: String,
     #[serde(default)]
     password_rule: PasswordRule,
+    /// Base URL embedded in certificates for CRL, CA issuer and OCSP access, defaults to vaultls_url
+    #[serde(default)]
+    pki_url: String,
+    /// SPIFFE trust domain of X.509-SVIDs, SVIDs cannot be issued if empty
+    #[serde(default)]
+    spiffe_trust_domain: String,
+    /// Reject newly issued certificates if the linter reports errors
+    #[serde(default)]
+    lint_blocking: bool,
+    /// Number of keys generated ahead of time per key algorithm, defaults to RSA keys only
+    #[serde(default)]
+    key_pool: Option<Vec<KeyPoolLimit>>,
+    /// Certificate signing Apple configuration profiles, profiles are unsigned if None
+    #[serde(default)]
+    profile_signing_certificate_id: Option<i64>,
+}
+
+/// Number of pre-generated keys kept ready for a key algorithm.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub(crate) struct KeyPoolLimit {
+    pub(crate) key_algorithm: KeyAlgorithm,
+    pub(crate) size: usize,
 }
 
 impl Common {
@@ -62,9 +87,43 @@ impl Common {
         if let Ok(vaultls_url) = env::var("VAULTLS_URL") {
             self.vaultls_url = vaultls_url;
         }
+        if let Ok(pki_url) = env::var("VAULTLS_PKI_URL") {
+            self.pki_url = pki_url;
+        }
+        if let Ok(spiffe_trust_domain) = env::var("VAULTLS_SPIFFE_TRUST_DOMAIN") {
+            self.spiffe_trust_domain = spiffe_trust_domain;
+        }
+        if let Ok(lint_blocking) = env::var("VAULTLS_LINT_BLOCKING") {
+            self.lint_blocking = lint_blocking == "true";
+        }
+        if let Ok(key_pool) = env::var("VAULTLS_KEY_POOL") {
+            match parse_key_pool(&key_pool) {
+                Some(key_pool) => self.key_pool = Some(key_pool),
+                None => println!("Ignoring invalid VAULTLS_KEY_POOL, expected e.g. rsa2048=4,rsa4096=8")
+            }
+        }
     }
 }
 
+/// Parses key pool limits in the form `rsa2048=4,rsa4096=8`.
+fn parse_key_pool(key_pool: &str) -> Option<Vec<KeyPoolLimit>> {
+    key_pool.split(',')
+        .map(str::trim)
+        .filter(|limit| !limit.is_empty())
+        .map(|limit| {
+            let (key_algorithm, size) = limit.split_once('=')?;
+            let key_algorithm = match key_algorithm.trim().to_lowercase().as_str() {
+                "p256" => KeyAlgorithm::EcdsaP256,
+                "p384" => KeyAlgorithm::EcdsaP384,
+                "rsa2048" => KeyAlgorithm::Rsa2048,
+                "rsa4096" => KeyAlgorithm::Rsa4096,
+                _ => return None
+            };
+            Some(KeyPoolLimit { key_algorithm, size: size.trim().parse().ok()? })
+        })
+        .collect()
+}
+
 /// Mail settings for the backend.
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub(crate) struct Mail {
@@ -79,7 +138,7 @@ pub(crate) struct Mail {
 impl Mail {
     /// Check if the mail settings are valid.
     pub(crate) fn is_valid(&self) -> bool {
-        self.smtp_host.len() > 0 && self.smtp_port > 0 && self.from.len() > 0
+        !self.smtp_host.is_empty() && self.smtp_port > 0 && !self.from.is_empty()
     }
 }
 
@@ -97,6 +156,7 @@ impl Default for Auth {
 
 /// OpenID Connect settings for the backend.
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) struct OIDC {
     pub(crate) id: String,
     pub(crate) secret: String,
@@ -121,6 +181,32 @@ impl OIDC {
     }
 }
 
+/// OpenVPN server template for client profiles.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct OpenVpn {
+    /// Host name or IP address of the server, profiles cannot be generated if empty
+    pub(crate) remote: String,
+    pub(crate) port: u16,
+    pub(crate) protocol: OpenVpnProtocol,
+    /// Data channel ciphers, separated by colons
+    pub(crate) cipher: String,
+    /// Static key (`openvpn --genkey tls-crypt`) shared with the server
+    pub(crate) tls_crypt_key: Option<String>,
+}
+
+impl Default for OpenVpn {
+    fn default() -> Self {
+        Self {
+            remote: String::new(),
+            port: 1194,
+            protocol: OpenVpnProtocol::Udp,
+            cipher: "AES-256-GCM:AES-128-GCM:CHACHA20-POLY1305".to_string(),
+            tls_crypt_key: None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub(crate) struct Logic {
     pub(crate) db_encrypted: bool,
@@ -177,6 +263,7 @@ impl Settings {
         self.common = settings.common.clone();
         self.mail = settings.mail.clone();
         self.oidc = settings.oidc.clone();
+        self.openvpn = settings.openvpn.clone();
 
         self.save_to_file(None).await
     }
@@ -189,8 +276,37 @@ impl Settings {
     
     pub(crate) fn get_mail(&self) -> &Mail { &self.mail }
     pub(crate) fn get_oidc(&self) -> &OIDC { &self.oidc }
+    pub(crate) fn get_openvpn(&self) -> &OpenVpn { &self.openvpn }
     pub(crate) fn get_vaultls_url(&self) -> &str { &self.common.vaultls_url }
+
+    /// Get the base URL for PKI endpoints embedded in certificates.
+    /// Returns None if neither the PKI URL nor the VaulTLS URL is configured.
+    pub(crate) fn get_pki_url(&self) -> Option<String> {
+        let url = match self.common.pki_url.trim().is_empty() {
+            true => self.common.vaultls_url.trim(),
+            false => self.common.pki_url.trim()
+        };
+        (!url.is_empty()).then(|| url.trim_end_matches('/').to_string())
+    }
+
+    /// Get the SPIFFE trust domain, None if not configured.
+    pub(crate) fn get_spiffe_trust_domain(&self) -> Option<&str> {
+        let trust_domain = self.common.spiffe_trust_domain.trim();
+        (!trust_domain.is_empty()).then_some(trust_domain)
+    }
+
+    /// Check if certificates with lint errors must not be issued.
+    pub(crate) fn lint_blocking(&self) -> bool { self.common.lint_blocking }
     pub(crate) fn get_db_encrypted(&self) -> bool { self.logic.db_encrypted }
+    pub(crate) fn get_profile_signing_certificate_id(&self) -> Option<i64> { self.common.profile_signing_certificate_id }
+
+    /// Get the number of keys to pre-generate per key algorithm.
+    pub(crate) fn get_key_pool(&self) -> Vec<KeyPoolLimit> {
+        self.common.key_pool.clone().unwrap_or_else(|| vec![
+            KeyPoolLimit { key_algorithm: KeyAlgorithm::Rsa2048, size: 4 },
+            KeyPoolLimit { key_algorithm: KeyAlgorithm::Rsa4096, size: 4 },
+        ])
+    }
     
     pub(crate) async fn set_password_enabled(&mut self, password_enabled: bool) -> Result<(), ApiError>{
         self.common.password_enabled = password_enabled;
@@ -0,0 +1,143 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::base64::encode_block;
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use openssl::nid::Nid;
+use openssl::pkey::Id;
+use openssl::x509::X509;
+use serde::Serialize;
+use crate::ApiError;
+use crate::cert::{Certificate, CertificateAuthority};
+use crate::constants::SPIFFE_REFRESH_HINT_SECS;
+use crate::data::enums::CertificateType;
+
+/// SPIFFE trust bundle in JWKS format as defined by the SPIFFE Trust Domain and Bundle specification.
+#[derive(Serialize)]
+pub(crate) struct SpiffeBundle {
+    keys: Vec<Jwk>,
+    spiffe_sequence: u64,
+    spiffe_refresh_hint: i64,
+}
+
+#[derive(Serialize)]
+struct Jwk {
+    kty: &'static str,
+    #[serde(rename = "use")]
+    key_use: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crv: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    e: Option<String>,
+    x5c: Vec<String>,
+}
+
+/// Checks that a trust domain only contains the characters allowed by the SPIFFE ID specification.
+fn validate_trust_domain(trust_domain: &str) -> Result<(), ApiError> {
+    let valid = !trust_domain.is_empty()
+        && trust_domain.len() <= 255
+        && trust_domain.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '-' | '_'));
+    match valid {
+        true => Ok(()),
+        false => Err(ApiError::BadRequest(format!("Invalid SPIFFE trust domain {}", trust_domain)))
+    }
+}
+
+/// Builds a SPIFFE ID from the trust domain and a workload path such as `/ns/prod/sa/web`.
+pub(crate) fn create_spiffe_id(trust_domain: &str, path: &str) -> Result<String, ApiError> {
+    validate_trust_domain(trust_domain)?;
+
+    let segments: Vec<&str> = match path.strip_prefix('/') {
+        Some(path) => path.split('/').collect(),
+        None => return Err(ApiError::BadRequest("SPIFFE path must start with /".to_string()))
+    };
+    let valid = segments.iter().all(|segment| {
+        !segment.is_empty()
+            && *segment != "."
+            && *segment != ".."
+            && segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    });
+    if !valid {
+        return Err(ApiError::BadRequest(format!("Invalid SPIFFE path {}", path)))
+    }
+
+    Ok(format!("spiffe://{}{}", trust_domain, path))
+}
+
+/// Creates the trust bundle containing the unexpired VaulTLS CAs that may issue X.509-SVIDs.
+/// CAs with a key type that cannot be expressed as JWK are left out.
+pub(crate) fn create_bundle(authorities: &[CertificateAuthority], cas: &[Certificate]) -> Result<SpiffeBundle, ApiError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+
+    let mut keys = Vec::new();
+    for authority in authorities {
+        if authority.valid_until < now || !authority.purpose.allows(CertificateType::Svid) { continue }
+        let Some(ca) = cas.iter().find(|ca| ca.id == authority.id) else { continue };
+        if let Some(jwk) = create_jwk(ca)? {
+            keys.push(jwk);
+        }
+    }
+    let spiffe_sequence = cas.iter().map(|ca| ca.id as u64).max().unwrap_or_default();
+
+    Ok(SpiffeBundle { keys, spiffe_sequence, spiffe_refresh_hint: SPIFFE_REFRESH_HINT_SECS })
+}
+
+/// Converts the CA public key into a JWK, or returns `None` for unsupported key types and curves.
+fn create_jwk(ca: &Certificate) -> Result<Option<Jwk>, ApiError> {
+    let ca_cert = X509::from_der(&ca.cert)?;
+    let public_key = ca_cert.public_key()?;
+    let mut jwk = Jwk {
+        kty: "EC",
+        key_use: "x509-svid",
+        crv: None,
+        x: None,
+        y: None,
+        n: None,
+        e: None,
+        x5c: vec![encode_block(&ca.cert)],
+    };
+
+    match public_key.id() {
+        Id::EC => {
+            let ec_key = public_key.ec_key()?;
+            let group = ec_key.group();
+            let (crv, size) = match group.curve_name() {
+                Some(Nid::X9_62_PRIME256V1) => ("P-256", 32),
+                Some(Nid::SECP384R1) => ("P-384", 48),
+                _ => return Ok(None)
+            };
+            let mut x = BigNum::new()?;
+            let mut y = BigNum::new()?;
+            let mut context = BigNumContext::new()?;
+            ec_key.public_key().affine_coordinates(group, &mut x, &mut y, &mut context)?;
+            jwk.crv = Some(crv);
+            jwk.x = Some(encode_base64url(&x.to_vec_padded(size)?));
+            jwk.y = Some(encode_base64url(&y.to_vec_padded(size)?));
+        }
+        Id::RSA => {
+            let rsa = public_key.rsa()?;
+            jwk.kty = "RSA";
+            jwk.n = Some(encode_bignum(rsa.n()));
+            jwk.e = Some(encode_bignum(rsa.e()));
+        }
+        _ => return Ok(None)
+    }
+
+    Ok(Some(jwk))
+}
+
+fn encode_bignum(value: &BigNumRef) -> String {
+    encode_base64url(&value.to_vec())
+}
+
+/// Encodes data as unpadded base64url as required by JWK.
+fn encode_base64url(data: &[u8]) -> String {
+    encode_block(data)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
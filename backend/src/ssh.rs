@@ -0,0 +1,161 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use ssh_key::certificate::{Builder, CertType};
+use ssh_key::rand_core::{OsRng, RngCore};
+use ssh_key::{Algorithm, HashAlg, LineEnding, PrivateKey, PublicKey};
+use crate::ApiError;
+use crate::data::enums::SshCertificateType;
+
+/// Extensions granted to user certificates, matching the defaults of ssh-keygen.
+const USER_CERT_EXTENSIONS: [&str; 5] = [
+    "permit-X11-forwarding",
+    "permit-agent-forwarding",
+    "permit-port-forwarding",
+    "permit-pty",
+    "permit-user-rc",
+];
+
+/// Key pair of the SSH certificate authority in OpenSSH format.
+#[derive(Default, Clone)]
+pub(crate) struct SshCa {
+    pub(crate) id: i64,
+    pub(crate) created_on: i64,
+    pub(crate) private_key: String,
+    pub(crate) public_key: String,
+}
+
+/// SSH public key uploaded by a user.
+#[derive(Default, Clone, Serialize)]
+pub(crate) struct SshPublicKey {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    pub(crate) created_on: i64,
+    pub(crate) user_id: i64,
+    pub(crate) public_key: String,
+    pub(crate) fingerprint: String,
+}
+
+/// OpenSSH user or host certificate signed by the SSH CA.
+#[derive(Default, Clone, Serialize)]
+pub(crate) struct SshCertificate {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    pub(crate) created_on: i64,
+    pub(crate) valid_until: i64,
+    pub(crate) certificate_type: SshCertificateType,
+    pub(crate) user_id: i64,
+    pub(crate) serial: String,
+    pub(crate) principals: Vec<String>,
+    #[serde(skip)]
+    pub(crate) certificate: String,
+    #[serde(skip)]
+    pub(crate) ssh_ca_id: i64,
+}
+
+/// Options for a new SSH certificate.
+pub(crate) struct SshCertificateOptions {
+    pub(crate) certificate_type: SshCertificateType,
+    pub(crate) principals: Vec<String>,
+    pub(crate) validity_in_days: u64,
+    /// Critical option restricting user certificates to a single command
+    pub(crate) force_command: Option<String>,
+    /// Critical option restricting user certificates to comma-separated source addresses
+    pub(crate) source_address: Option<String>,
+}
+
+/// Creates a new Ed25519 SSH CA key pair.
+pub(crate) fn create_ssh_ca() -> Result<SshCa, ApiError> {
+    let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?;
+
+    Ok(SshCa {
+        created_on: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64,
+        private_key: private_key.to_openssh(LineEnding::LF)?.to_string(),
+        public_key: private_key.public_key().to_openssh()?,
+        ..Default::default()
+    })
+}
+
+/// Parses a public key in OpenSSH format and returns it normalized together with its SHA-256 fingerprint.
+pub(crate) fn parse_public_key(public_key: &str) -> Result<(String, String), ApiError> {
+    let public_key = PublicKey::from_openssh(public_key.trim())
+        .map_err(|e| ApiError::BadRequest(format!("Invalid SSH public key: {}", e)))?;
+    let fingerprint = public_key.fingerprint(HashAlg::Sha256).to_string();
+    Ok((public_key.to_openssh()?, fingerprint))
+}
+
+/// Returns the principals of a user certificate: the local part of the e-mail address and the address itself.
+pub(crate) fn get_user_principals(email: &str) -> Vec<String> {
+    match email.split_once('@') {
+        Some((local_part, _)) if !local_part.is_empty() => vec![local_part.to_string(), email.to_string()],
+        _ => vec![email.to_string()]
+    }
+}
+
+/// Signs an OpenSSH certificate for the public key.
+pub(crate) fn create_ssh_cert(
+    ca: &SshCa,
+    name: &str,
+    user_id: i64,
+    key_id: &str,
+    public_key: &str,
+    options: &SshCertificateOptions
+) -> Result<SshCertificate, ApiError> {
+    let ca_key = PrivateKey::from_openssh(&ca.private_key)?;
+    let public_key = PublicKey::from_openssh(public_key.trim())
+        .map_err(|e| ApiError::BadRequest(format!("Invalid SSH public key: {}", e)))?;
+
+    if options.principals.is_empty() {
+        return Err(ApiError::BadRequest("SSH certificate requires at least one principal".to_string()))
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let valid_after = now.as_secs();
+    let valid_before = options.validity_in_days.checked_mul(60 * 60 * 24)
+        .and_then(|validity| validity.checked_add(valid_after))
+        .ok_or(ApiError::BadRequest("SSH certificate validity is too long".to_string()))?;
+    let serial = OsRng.next_u64();
+
+    let mut builder = Builder::new_with_random_nonce(&mut OsRng, public_key.key_data().clone(), valid_after, valid_before)?;
+    builder.serial(serial)?;
+    builder.key_id(key_id)?;
+    for principal in &options.principals {
+        builder.valid_principal(principal)?;
+    }
+
+    match options.certificate_type {
+        SshCertificateType::User => {
+            builder.cert_type(CertType::User)?;
+            if let Some(force_command) = &options.force_command {
+                builder.critical_option("force-command", force_command)?;
+            }
+            if let Some(source_address) = &options.source_address {
+                builder.critical_option("source-address", source_address)?;
+            }
+            for extension in USER_CERT_EXTENSIONS {
+                builder.extension(extension, "")?;
+            }
+        }
+        SshCertificateType::Host => {
+            // OpenSSH only knows critical options for user certificates
+            if options.force_command.is_some() || options.source_address.is_some() {
+                return Err(ApiError::BadRequest("force_command and source_address are only supported for user certificates".to_string()))
+            }
+            builder.cert_type(CertType::Host)?;
+        }
+    }
+
+    let certificate = builder.sign(&ca_key)?;
+
+    Ok(SshCertificate {
+        name: name.to_string(),
+        created_on: now.as_millis() as i64,
+        valid_until: (valid_before * 1000) as i64,
+        certificate_type: options.certificate_type,
+        user_id,
+        serial: serial.to_string(),
+        principals: options.principals.clone(),
+        certificate: certificate.to_openssh()?,
+        ssh_ca_id: ca.id,
+        ..Default::default()
+    })
+}
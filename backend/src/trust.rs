@@ -0,0 +1,100 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::hash::MessageDigest;
+use openssl::x509::X509;
+use serde::Serialize;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::prelude::FromDer;
+use crate::ApiError;
+use crate::cert::{asn1_time_to_millis, Certificate, CertificateAuthority};
+use crate::data::enums::CaPurpose;
+
+/// External CA certificate that is published in the trust bundle next to the VaulTLS CAs,
+/// such as a partner CA or an old CA that is being retired.
+#[derive(Default, Clone, Serialize)]
+pub(crate) struct TrustAnchor {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    pub(crate) created_on: i64,
+    pub(crate) valid_until: i64,
+    pub(crate) subject: String,
+    /// SHA-256 fingerprint of the certificate as colon separated uppercase hex
+    pub(crate) fingerprint: String,
+    pub(crate) purpose: CaPurpose,
+    pub(crate) enabled: bool,
+    #[serde(skip)]
+    pub(crate) certificate: Vec<u8>,
+}
+
+/// Parses an uploaded PEM CA certificate into an enabled trust anchor.
+pub(crate) fn create_trust_anchor(name: &str, pem: &str, purpose: CaPurpose) -> Result<TrustAnchor, ApiError> {
+    if name.trim().is_empty() {
+        return Err(ApiError::BadRequest("Trust anchor name is required".to_string()))
+    }
+    let certs = X509::stack_from_pem(pem.trim().as_bytes())
+        .map_err(|_| ApiError::BadRequest("Certificate is not a valid PEM certificate".to_string()))?;
+    let [cert] = certs.as_slice() else {
+        return Err(ApiError::BadRequest("Exactly one certificate has to be uploaded per trust anchor".to_string()))
+    };
+
+    let der = cert.to_der()?;
+    let (_, parsed) = X509Certificate::from_der(&der)
+        .map_err(|e| ApiError::BadRequest(format!("Failed to decode certificate: {}", e)))?;
+    let is_ca = parsed.basic_constraints().ok().flatten().is_some_and(|constraints| constraints.value.ca);
+    if !is_ca {
+        return Err(ApiError::BadRequest("Certificate is not a CA certificate".to_string()))
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let valid_until = asn1_time_to_millis(cert.not_after())?;
+    if valid_until < now {
+        return Err(ApiError::BadRequest("Certificate has already expired".to_string()))
+    }
+
+    Ok(TrustAnchor {
+        name: name.trim().to_string(),
+        created_on: now,
+        valid_until,
+        subject: parsed.subject().to_string(),
+        fingerprint: get_fingerprint(cert)?,
+        purpose,
+        enabled: true,
+        certificate: der,
+        ..Default::default()
+    })
+}
+
+/// Creates a PEM bundle of all VaulTLS CAs and enabled trust anchors that have not expired.
+/// If a purpose is requested, only CAs of that purpose and general purpose CAs are included.
+pub(crate) fn create_trust_bundle(
+    authorities: &[CertificateAuthority],
+    cas: &[Certificate],
+    anchors: &[TrustAnchor],
+    purpose: Option<CaPurpose>
+) -> Result<String, ApiError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let included = |ca_purpose: CaPurpose| purpose.is_none_or(|purpose| ca_purpose == purpose || ca_purpose == CaPurpose::General);
+
+    let mut bundle = String::new();
+    for authority in authorities {
+        if authority.valid_until < now || !included(authority.purpose) { continue }
+        let Some(ca) = cas.iter().find(|ca| ca.id == authority.id) else { continue };
+        append_certificate(&mut bundle, &format!("VaulTLS CA {}", authority.name), &ca.cert)?;
+    }
+    for anchor in anchors {
+        if !anchor.enabled || anchor.valid_until < now || !included(anchor.purpose) { continue }
+        append_certificate(&mut bundle, &format!("Trust anchor {}", anchor.name), &anchor.certificate)?;
+    }
+    Ok(bundle)
+}
+
+fn append_certificate(bundle: &mut String, label: &str, der: &[u8]) -> Result<(), ApiError> {
+    let pem = X509::from_der(der)?.to_pem()?;
+    bundle.push_str(&format!("# {}\n", label.replace('\n', " ")));
+    bundle.push_str(&String::from_utf8_lossy(&pem));
+    Ok(())
+}
+
+fn get_fingerprint(cert: &X509) -> Result<String, ApiError> {
+    let digest = cert.digest(MessageDigest::sha256())?;
+    Ok(digest.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<String>>().join(":"))
+}
@@ -0,0 +1,238 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use cms::cert::{CertificateChoices, IssuerAndSerialNumber};
+use cms::content_info::{CmsVersion, ContentInfo};
+use cms::signed_data::{CertificateSet, EncapsulatedContentInfo, SignedData, SignerIdentifier, SignerInfo, SignerInfos};
+use der::asn1::{Any, BitString, GeneralizedTime, Int, ObjectIdentifier, OctetString, SetOfVec};
+use der::oid::db::rfc5280::ANY_POLICY;
+use der::oid::db::rfc5911::{ID_AA_SIGNING_CERTIFICATE_V_2, ID_CONTENT_TYPE, ID_MESSAGE_DIGEST, ID_SIGNED_DATA};
+use der::oid::db::rfc5912::{ID_SHA_256, ID_SHA_384, ID_SHA_512};
+use der::{Decode, Encode, Sequence};
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{PKey, Private};
+use openssl::rand::rand_bytes;
+use x509_cert::attr::Attribute;
+use x509_cert::ext::Extensions;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::AlgorithmIdentifierOwned;
+use crate::ApiError;
+use crate::data::enums::CertificateType;
+use crate::db::VaulTLSDB;
+use crate::ocsp;
+
+/// Content type of a timestamp token (id-ct-TSTInfo).
+const ID_CT_TST_INFO: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.1.4");
+/// VaulTLS does not define its own TSA policy, so timestamps are issued under anyPolicy.
+const TSA_POLICY: ObjectIdentifier = ANY_POLICY;
+
+/// Timestamp request and response structures as defined in RFC 3161.
+#[derive(Sequence)]
+struct TimeStampReq {
+    version: u8,
+    message_imprint: MessageImprint,
+    req_policy: Option<ObjectIdentifier>,
+    nonce: Option<Int>,
+    #[asn1(default = "Default::default")]
+    cert_req: bool,
+    #[asn1(context_specific = "0", tag_mode = "IMPLICIT", optional = "true")]
+    extensions: Option<Extensions>,
+}
+
+#[derive(Sequence, Clone)]
+struct MessageImprint {
+    hash_algorithm: AlgorithmIdentifierOwned,
+    hashed_message: OctetString,
+}
+
+#[derive(Sequence)]
+struct TimeStampResp {
+    status: PkiStatusInfo,
+    time_stamp_token: Option<ContentInfo>,
+}
+
+#[derive(Sequence)]
+struct PkiStatusInfo {
+    status: u8,
+    status_string: Option<Vec<String>>,
+    fail_info: Option<BitString>,
+}
+
+#[derive(Sequence)]
+struct TstInfo {
+    version: u8,
+    policy: ObjectIdentifier,
+    message_imprint: MessageImprint,
+    serial_number: SerialNumber,
+    gen_time: GeneralizedTime,
+    accuracy: Option<Accuracy>,
+    nonce: Option<Int>,
+}
+
+#[derive(Sequence)]
+struct Accuracy {
+    seconds: Option<u32>,
+}
+
+/// Signing certificate attribute as defined in RFC 5035, the hash algorithm defaults to SHA-256.
+#[derive(Sequence)]
+struct SigningCertificateV2 {
+    certs: Vec<EssCertIdV2>,
+}
+
+#[derive(Sequence)]
+struct EssCertIdV2 {
+    cert_hash: OctetString,
+}
+
+/// Reasons for rejecting a timestamp request, the value is the bit of the PKIFailureInfo.
+#[derive(Clone, Copy, Debug)]
+enum FailureInfo {
+    BadAlg = 0,
+    BadDataFormat = 5,
+    UnacceptedPolicy = 15,
+    UnacceptedExtension = 16,
+    SystemFailure = 25,
+}
+
+/// Answers a DER encoded timestamp request.
+/// Errors are reported through the PKI status, so this always returns a response.
+pub(crate) fn respond(db: &VaulTLSDB, request: &[u8]) -> Vec<u8> {
+    let response = match create_token(db, request) {
+        Ok(token) => TimeStampResp {
+            status: PkiStatusInfo { status: 0, status_string: None, fail_info: None },
+            time_stamp_token: Some(token),
+        },
+        Err((failure, message)) => TimeStampResp {
+            status: PkiStatusInfo {
+                status: 2,
+                status_string: Some(vec![message]),
+                fail_info: create_failure_bits(failure),
+            },
+            time_stamp_token: None,
+        }
+    };
+    response.to_der().unwrap_or_default()
+}
+
+fn create_token(db: &VaulTLSDB, request: &[u8]) -> Result<ContentInfo, (FailureInfo, String)> {
+    let request = TimeStampReq::from_der(request)
+        .map_err(|e| (FailureInfo::BadDataFormat, format!("Malformed request: {}", e)))?;
+    if request.version != 1 {
+        return Err((FailureInfo::BadDataFormat, "Unsupported request version".to_string()))
+    }
+    if request.req_policy.is_some_and(|policy| policy != TSA_POLICY) {
+        return Err((FailureInfo::UnacceptedPolicy, "Requested policy is not supported".to_string()))
+    }
+    if request.extensions.is_some() {
+        return Err((FailureInfo::UnacceptedExtension, "Request extensions are not supported".to_string()))
+    }
+
+    let digest_length = match request.message_imprint.hash_algorithm.oid {
+        ID_SHA_256 => 32,
+        ID_SHA_384 => 48,
+        ID_SHA_512 => 64,
+        _ => return Err((FailureInfo::BadAlg, "Unsupported hash algorithm".to_string()))
+    };
+    if request.message_imprint.hashed_message.as_bytes().len() != digest_length {
+        return Err((FailureInfo::BadDataFormat, "Message imprint does not match the hash algorithm".to_string()))
+    }
+
+    let system_failure = |e: ApiError| (FailureInfo::SystemFailure, e.to_string());
+    let (tsa_cert, tsa_key) = get_tsa_certificate(db).map_err(system_failure)?;
+    sign_token(&request, &tsa_cert, &tsa_key).map_err(system_failure)
+}
+
+/// Returns the most recent valid timestamping certificate and its key.
+fn get_tsa_certificate(db: &VaulTLSDB) -> Result<(x509_cert::Certificate, PKey<Private>), ApiError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let cert = db.get_all_user_cert(None)?
+        .into_iter()
+        .filter(|cert| cert.certificate_type == CertificateType::Timestamping && cert.revoked_on.is_none() && cert.valid_from <= now && cert.valid_until > now)
+        .max_by_key(|cert| cert.created_on)
+        .ok_or(ApiError::Other("No timestamping certificate available".to_string()))?;
+
+    let parsed = Pkcs12::from_der(&cert.pkcs12)?.parse2(&cert.pkcs12_password)?;
+    let (Some(tsa_cert), Some(tsa_key)) = (parsed.cert, parsed.pkey) else {
+        return Err(ApiError::Other("PKCS#12 does not contain a certificate and key".to_string()))
+    };
+    Ok((x509_cert::Certificate::from_der(&tsa_cert.to_der()?)?, tsa_key))
+}
+
+/// Creates the CMS signed timestamp token for the request.
+fn sign_token(request: &TimeStampReq, tsa_cert: &x509_cert::Certificate, tsa_key: &PKey<Private>) -> Result<ContentInfo, ApiError> {
+    let mut serial = [0u8; 16];
+    rand_bytes(&mut serial)?;
+    serial[0] &= 0x7f;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let tst_info = TstInfo {
+        version: 1,
+        policy: TSA_POLICY,
+        message_imprint: request.message_imprint.clone(),
+        serial_number: SerialNumber::new(&serial)?,
+        gen_time: GeneralizedTime::from_unix_duration(Duration::from_secs(now.as_secs()))?,
+        accuracy: Some(Accuracy { seconds: Some(1) }),
+        nonce: request.nonce.clone(),
+    }.to_der()?;
+
+    let sha256 = AlgorithmIdentifierOwned { oid: ID_SHA_256, parameters: None };
+    let message_digest = hash(MessageDigest::sha256(), &tst_info)?;
+    let cert_hash = hash(MessageDigest::sha256(), &tsa_cert.to_der()?)?;
+    let signing_certificate = SigningCertificateV2 {
+        certs: vec![EssCertIdV2 { cert_hash: OctetString::new(cert_hash.to_vec())? }]
+    };
+
+    let signed_attrs = SetOfVec::try_from(vec![
+        create_attribute(ID_CONTENT_TYPE, Any::encode_from(&ID_CT_TST_INFO)?)?,
+        create_attribute(ID_MESSAGE_DIGEST, Any::encode_from(&OctetString::new(message_digest.to_vec())?)?)?,
+        create_attribute(ID_AA_SIGNING_CERTIFICATE_V_2, Any::encode_from(&signing_certificate)?)?,
+    ])?;
+    let (signature_algorithm, signature) = ocsp::sign(tsa_key, &signed_attrs.to_der()?)?;
+
+    let signer_info = SignerInfo {
+        version: CmsVersion::V1,
+        sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+            issuer: tsa_cert.tbs_certificate.issuer.clone(),
+            serial_number: tsa_cert.tbs_certificate.serial_number.clone(),
+        }),
+        digest_alg: sha256.clone(),
+        signed_attrs: Some(signed_attrs),
+        signature_algorithm,
+        signature: OctetString::new(signature)?,
+        unsigned_attrs: None,
+    };
+
+    let certificates = match request.cert_req {
+        true => Some(CertificateSet(SetOfVec::try_from(vec![CertificateChoices::Certificate(tsa_cert.clone())])?)),
+        false => None
+    };
+
+    let signed_data = SignedData {
+        version: CmsVersion::V3,
+        digest_algorithms: SetOfVec::try_from(vec![sha256])?,
+        encap_content_info: EncapsulatedContentInfo {
+            econtent_type: ID_CT_TST_INFO,
+            econtent: Some(Any::encode_from(&OctetString::new(tst_info)?)?),
+        },
+        certificates,
+        crls: None,
+        signer_infos: SignerInfos(SetOfVec::try_from(vec![signer_info])?),
+    };
+
+    Ok(ContentInfo {
+        content_type: ID_SIGNED_DATA,
+        content: Any::encode_from(&signed_data)?,
+    })
+}
+
+fn create_attribute(oid: ObjectIdentifier, value: Any) -> Result<Attribute, ApiError> {
+    Ok(Attribute { oid, values: SetOfVec::try_from(vec![value])? })
+}
+
+/// Encodes the failure as named bit string with trailing zero bits removed, as required by DER.
+fn create_failure_bits(failure: FailureInfo) -> Option<BitString> {
+    let bit = failure as usize;
+    let mut bytes = vec![0u8; bit / 8 + 1];
+    bytes[bit / 8] = 0x80 >> (bit % 8);
+    BitString::new((7 - bit % 8) as u8, bytes).ok()
+}
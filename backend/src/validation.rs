@@ -0,0 +1,179 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use openidconnect::reqwest;
+use openssl::rand::rand_bytes;
+use serde::Serialize;
+use crate::ApiError;
+use crate::constants::DOMAIN_VALIDATION_VALIDITY_SECS;
+use crate::data::api::{CreateServerCertificateRequest, CreateUserCertificateRequest};
+use crate::data::enums::{CertificateType, KeyAlgorithm, ValidationMethod, ValidationStatus};
+use crate::dns;
+use crate::lint::is_valid_dns_name;
+
+/// DNS label below the validated name holding the TXT record.
+const DNS_CHALLENGE_LABEL: &str = "_vaultls-challenge";
+/// HTTP path on the validated name serving the token.
+const HTTP_CHALLENGE_PATH: &str = "/.well-known/vaultls-challenge/";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest accepted HTTP challenge response.
+const HTTP_MAX_RESPONSE: usize = 1024;
+
+/// Server certificate requested by a user. It is only issued once control over every DNS name has been proven.
+#[derive(Serialize, Clone, Default)]
+pub(crate) struct ServerCertificateRequest {
+    pub(crate) id: i64,
+    pub(crate) created_on: i64,
+    /// Pending requests cannot be validated or issued after this
+    pub(crate) expires_on: i64,
+    pub(crate) name: String,
+    pub(crate) validity_in_years: Option<u64>,
+    pub(crate) key_algorithm: Option<KeyAlgorithm>,
+    pub(crate) status: ValidationStatus,
+    pub(crate) certificate_id: Option<i64>,
+    pub(crate) user_id: i64,
+    pub(crate) validations: Vec<DomainValidation>,
+    #[serde(skip)]
+    pub(crate) system_generated_password: bool,
+    #[serde(skip)]
+    pub(crate) pkcs12_password: Option<String>,
+}
+
+/// Proof of control over a single DNS name of a server certificate request.
+#[derive(Serialize, Clone, Default)]
+pub(crate) struct DomainValidation {
+    pub(crate) id: i64,
+    pub(crate) dns_name: String,
+    pub(crate) method: ValidationMethod,
+    pub(crate) token: String,
+    /// TXT record name or URL where the token has to be published
+    pub(crate) challenge: String,
+    pub(crate) status: ValidationStatus,
+    pub(crate) validated_on: Option<i64>,
+    /// Reason the last check failed
+    pub(crate) error: Option<String>,
+}
+
+impl ServerCertificateRequest {
+    /// The certificate request as issued once all DNS names are validated.
+    pub(crate) fn to_certificate_request(&self) -> CreateUserCertificateRequest {
+        CreateUserCertificateRequest {
+            cert_name: self.name.clone(),
+            validity_in_years: self.validity_in_years,
+            user_id: self.user_id,
+            notify_user: None,
+            system_generated_password: self.system_generated_password,
+            pkcs12_password: self.pkcs12_password.clone(),
+            cert_type: Some(CertificateType::Server),
+            dns_names: Some(self.validations.iter().map(|validation| validation.dns_name.clone()).collect()),
+            key_algorithm: self.key_algorithm,
+            spiffe_path: None,
+            profile: None,
+            upn: None,
+            csr: None,
+            ca_id: None,
+            not_before: None,
+        }
+    }
+
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires_on < now()
+    }
+}
+
+impl DomainValidation {
+    pub(crate) fn new(dns_name: String, method: ValidationMethod, token: String) -> Self {
+        let challenge = get_challenge(&dns_name, method, &token);
+        DomainValidation { dns_name, method, token, challenge, ..Default::default() }
+    }
+}
+
+/// Creates a pending request with a random token for every DNS name.
+pub(crate) fn create_request(user_id: i64, payload: &CreateServerCertificateRequest) -> Result<ServerCertificateRequest, ApiError> {
+    if payload.dns_names.is_empty() {
+        return Err(ApiError::BadRequest("At least one DNS name is required".to_string()))
+    }
+
+    let mut validations: Vec<DomainValidation> = Vec::new();
+    for dns_name in &payload.dns_names {
+        let dns_name = dns_name.trim().trim_end_matches('.').to_lowercase();
+        if !is_valid_dns_name(&dns_name) {
+            return Err(ApiError::BadRequest(format!("DNS name {} is not a valid host name", dns_name)))
+        }
+        if dns_name.starts_with("*.") && payload.validation_method != ValidationMethod::DnsTxt {
+            return Err(ApiError::BadRequest(format!("Wildcard name {} can only be validated with DNS", dns_name)))
+        }
+        if validations.iter().any(|validation| validation.dns_name == dns_name) {
+            continue
+        }
+
+        let mut token = [0u8; 16];
+        rand_bytes(&mut token)?;
+        let token = token.iter().map(|byte| format!("{:02x}", byte)).collect();
+        validations.push(DomainValidation::new(dns_name, payload.validation_method, token));
+    }
+
+    let created_on = now();
+    Ok(ServerCertificateRequest {
+        created_on,
+        expires_on: created_on + DOMAIN_VALIDATION_VALIDITY_SECS * 1000,
+        name: payload.cert_name.clone(),
+        validity_in_years: payload.validity_in_years,
+        key_algorithm: payload.key_algorithm,
+        user_id,
+        validations,
+        system_generated_password: payload.system_generated_password,
+        pkcs12_password: payload.pkcs12_password.clone(),
+        ..Default::default()
+    })
+}
+
+/// Returns where the token of a DNS name has to be published.
+/// Wildcard names are validated at the name below the wildcard.
+fn get_challenge(dns_name: &str, method: ValidationMethod, token: &str) -> String {
+    match method {
+        ValidationMethod::DnsTxt => format!("{}.{}", DNS_CHALLENGE_LABEL, dns_name.strip_prefix("*.").unwrap_or(dns_name)),
+        ValidationMethod::Http => format!("http://{}{}{}", dns_name, HTTP_CHALLENGE_PATH, token)
+    }
+}
+
+/// Checks that the token is published for the DNS name. Returns the reason if it is not.
+pub(crate) async fn validate(validation: &DomainValidation) -> Result<(), String> {
+    match validation.method {
+        ValidationMethod::DnsTxt => {
+            let records = dns::lookup_txt(&validation.challenge).await.map_err(|e| e.to_string())?;
+            if !records.iter().any(|record| record.trim() == validation.token) {
+                return Err(format!("No TXT record at {} contains the token", validation.challenge))
+            }
+        }
+        ValidationMethod::Http => {
+            let response = fetch_http_challenge(&validation.challenge).await
+                .map_err(|e| format!("Failed to fetch {}: {}", validation.challenge, e))?;
+            if response.trim() != validation.token {
+                return Err(format!("{} does not return the token", validation.challenge))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the HTTP challenge, redirects are followed and only the beginning of the body is read.
+async fn fetch_http_challenge(url: &str) -> Result<String, reqwest::Error> {
+    let client = reqwest::ClientBuilder::new()
+        .redirect(reqwest::redirect::Policy::limited(5))
+        .timeout(HTTP_TIMEOUT)
+        .build()?;
+    let mut response = client.get(url).send().await?.error_for_status()?;
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > HTTP_MAX_RESPONSE {
+            break
+        }
+    }
+    body.truncate(HTTP_MAX_RESPONSE);
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
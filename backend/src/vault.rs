@@ -0,0 +1,274 @@
+use openssl::bn::BigNum;
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::Id;
+use openssl::x509::{X509Req, X509};
+use x509_parser::certification_request::X509CertificationRequest;
+use x509_parser::extensions::{GeneralName, ParsedExtension};
+use x509_parser::prelude::FromDer;
+use crate::ApiError;
+use crate::cert::{asn1_time_to_millis, get_pkcs12_certificate, Certificate};
+use crate::data::api::{CreateUserCertificateRequest, VaultCertificateData, VaultCertificateRequest, VaultResponse, VaultTtl};
+use crate::data::enums::{CertificateProfile, CertificateType};
+use crate::db::VaulTLSDB;
+use crate::helper::random_uuid;
+
+/// Vault roles and the certificate type and profile they issue, e.g. `pki/issue/server`.
+const ROLES: [(&str, CertificateType, CertificateProfile); 4] = [
+    ("client", CertificateType::Client, CertificateProfile::Default),
+    ("server", CertificateType::Server, CertificateProfile::Default),
+    ("eap-tls-client", CertificateType::Client, CertificateProfile::EapTls),
+    ("eap-tls-server", CertificateType::Server, CertificateProfile::EapTls),
+];
+const YEAR_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Maps a Vault issue or sign request onto a certificate request for the user.
+/// For sign requests, the common name and DNS names default to those of the CSR, as with Vault roles.
+pub(crate) fn create_certificate_request(
+    role: &str,
+    request: &VaultCertificateRequest,
+    user_id: i64,
+    csr: Option<&str>
+) -> Result<CreateUserCertificateRequest, ApiError> {
+    let (cert_type, profile) = find_role(role)?;
+    if request.format.as_deref().is_some_and(|format| format != "pem") {
+        return Err(ApiError::BadRequest("Only the pem format is supported".to_string()))
+    }
+    if !request.ip_sans.is_empty() {
+        return Err(ApiError::BadRequest("IP SANs are not supported".to_string()))
+    }
+
+    let (csr_common_name, csr_dns_names) = match csr {
+        Some(csr) => read_csr_names(csr)?,
+        None => (None, Vec::new())
+    };
+    let common_name = match request.common_name.trim() {
+        "" => csr_common_name.ok_or(ApiError::BadRequest("common_name is required".to_string()))?,
+        common_name => common_name.to_string()
+    };
+    let alt_names = match request.alt_names.is_empty() {
+        true => csr_dns_names,
+        false => request.alt_names.clone()
+    };
+
+    let dns_names = match cert_type {
+        CertificateType::Server => {
+            let mut dns_names = Vec::new();
+            if !request.exclude_cn_from_sans {
+                dns_names.push(common_name.clone());
+            }
+            for alt_name in alt_names {
+                if !dns_names.contains(&alt_name) {
+                    dns_names.push(alt_name);
+                }
+            }
+            Some(dns_names)
+        }
+        _ if !request.alt_names.is_empty() => {
+            return Err(ApiError::BadRequest("Client certificates carry the e-mail address of the user, alt_names are not supported".to_string()))
+        }
+        _ => None
+    };
+
+    let ca_id = parse_issuer_ref(request.issuer_ref.as_deref())?;
+
+    // Certificates are issued for whole years, other TTLs are rejected instead of silently extended
+    let validity_in_years = match &request.ttl {
+        Some(ttl) => {
+            let secs = parse_ttl(ttl)?;
+            if secs == 0 || secs % YEAR_SECS != 0 {
+                return Err(ApiError::BadRequest("TTL must be a whole number of years, such as 8760h. Shorter certificates are available as short-lived certificates".to_string()))
+            }
+            Some(secs / YEAR_SECS)
+        }
+        None => None
+    };
+
+    Ok(CreateUserCertificateRequest {
+        cert_name: common_name,
+        validity_in_years,
+        user_id,
+        notify_user: None,
+        system_generated_password: true,
+        pkcs12_password: None,
+        cert_type: Some(cert_type),
+        dns_names,
+        key_algorithm: None,
+        spiffe_path: None,
+        profile: Some(profile),
+        upn: None,
+        csr: csr.map(str::to_string),
+        ca_id,
+        not_before: None,
+    })
+}
+
+/// Returns the CA whose certificate or CRL is requested, which is resolved like for issuance:
+/// the CA of the issuer_ref, otherwise the CA issuing the certificates of the role.
+/// Without either, all roles must be issued by the same CA.
+pub(crate) fn get_issuer(db: &VaulTLSDB, issuer_ref: Option<&str>, role: Option<&str>) -> Result<Certificate, ApiError> {
+    if let Some(ca_id) = parse_issuer_ref(issuer_ref)? {
+        return db.get_ca(ca_id)
+    }
+    if let Some(role) = role {
+        let (cert_type, _) = find_role(role)?;
+        return db.get_issuing_ca(None, cert_type)
+    }
+
+    let mut issuer: Option<Certificate> = None;
+    for (_, cert_type, _) in ROLES {
+        let Ok(ca) = db.get_issuing_ca(None, cert_type) else { continue };
+        match &issuer {
+            Some(issuer) if issuer.id != ca.id => {
+                return Err(ApiError::BadRequest("Roles are issued by different CAs, select one with the role parameter or an issuer_ref".to_string()))
+            }
+            Some(_) => {}
+            None => issuer = Some(ca)
+        }
+    }
+    issuer.ok_or(ApiError::BadRequest("No active CA issues certificates for the Vault roles".to_string()))
+}
+
+/// Returns the certificate as Vault response data. The private key is only included if it was generated.
+pub(crate) fn create_certificate_data(cert: &Certificate, ca: &Certificate) -> Result<VaultCertificateData, ApiError> {
+    let parsed = Pkcs12::from_der(&cert.pkcs12)?.parse2(&cert.pkcs12_password)?;
+    let leaf = get_pkcs12_certificate(&cert.pkcs12, &cert.pkcs12_password)?;
+    let issuing_ca = pem_string(&X509::from_der(&ca.cert)?)?;
+
+    let (private_key, private_key_type) = match parsed.pkey {
+        Some(key) => {
+            let key_type = match key.id() {
+                Id::RSA => "rsa",
+                _ => "ec"
+            };
+            (Some(String::from_utf8_lossy(&key.private_key_to_pem_pkcs8()?).into_owned()), Some(key_type.to_string()))
+        }
+        None => (None, None)
+    };
+
+    Ok(VaultCertificateData {
+        certificate: pem_string(&leaf)?,
+        ca_chain: vec![issuing_ca.clone()],
+        issuing_ca,
+        private_key,
+        private_key_type,
+        serial_number: format_serial(&cert.serial),
+        expiration: asn1_time_to_millis(leaf.not_after())? / 1000,
+    })
+}
+
+/// Wraps data in the Vault response envelope.
+pub(crate) fn create_response<T>(data: T, warnings: Vec<String>) -> Result<VaultResponse<T>, ApiError> {
+    Ok(VaultResponse {
+        request_id: random_uuid()?.to_lowercase(),
+        lease_id: String::new(),
+        renewable: false,
+        lease_duration: 0,
+        data,
+        wrap_info: None,
+        warnings: (!warnings.is_empty()).then_some(warnings),
+        auth: None,
+    })
+}
+
+/// Converts a Vault serial number (colon separated hex) into the VaulTLS serial (uppercase hex).
+pub(crate) fn parse_serial(serial_number: &str) -> Result<String, ApiError> {
+    let hex: String = serial_number.trim().chars().filter(|c| *c != ':' && *c != '-').collect();
+    let serial = BigNum::from_hex_str(&hex)
+        .map_err(|_| ApiError::BadRequest(format!("Invalid serial number {}", serial_number)))?;
+    Ok(serial.to_hex_str()?.to_string())
+}
+
+/// Formats a VaulTLS serial as colon separated lowercase hex bytes, e.g. 0a:1b:2c.
+fn format_serial(serial: &str) -> String {
+    let hex = match serial.len() % 2 {
+        0 => serial.to_lowercase(),
+        _ => format!("0{}", serial.to_lowercase())
+    };
+    hex.as_bytes().chunks(2)
+        .map(|byte| String::from_utf8_lossy(byte).into_owned())
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+/// Returns the certificate type and profile a role issues.
+fn find_role(role: &str) -> Result<(CertificateType, CertificateProfile), ApiError> {
+    ROLES.iter()
+        .find(|(name, _, _)| *name == role)
+        .map(|(_, cert_type, profile)| (*cert_type, *profile))
+        .ok_or(ApiError::BadRequest(format!("Unknown role {}, available roles are client, server, eap-tls-client and eap-tls-server", role)))
+}
+
+/// Returns the CA id of an issuer_ref, None for the default issuer.
+fn parse_issuer_ref(issuer_ref: Option<&str>) -> Result<Option<i64>, ApiError> {
+    match issuer_ref.map(str::trim) {
+        None | Some("") | Some("default") => Ok(None),
+        Some(issuer_ref) => Ok(Some(issuer_ref.parse::<i64>()
+            .map_err(|_| ApiError::BadRequest(format!("Invalid issuer_ref {}, expected a CA id or default", issuer_ref)))?))
+    }
+}
+
+/// Parses a TTL in seconds or as duration such as 720h, 30d or 1h30m.
+fn parse_ttl(ttl: &VaultTtl) -> Result<u64, ApiError> {
+    let ttl = match ttl {
+        VaultTtl::Seconds(secs) => return Ok(*secs),
+        VaultTtl::Duration(ttl) => ttl.trim()
+    };
+    let invalid = || ApiError::BadRequest(format!("Invalid TTL {}", ttl));
+    if let Ok(secs) = ttl.parse::<u64>() {
+        return Ok(secs)
+    }
+
+    let mut secs: u64 = 0;
+    let mut number = String::new();
+    for c in ttl.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid())
+        };
+        secs = number.parse::<u64>().ok()
+            .and_then(|number| number.checked_mul(unit))
+            .and_then(|value| value.checked_add(secs))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(invalid())
+    }
+    Ok(secs)
+}
+
+/// Returns the CN and DNS SANs requested by a CSR.
+fn read_csr_names(csr: &str) -> Result<(Option<String>, Vec<String>), ApiError> {
+    let csr = X509Req::from_pem(csr.as_bytes())
+        .map_err(|_| ApiError::BadRequest("CSR is not a valid PEM certificate request".to_string()))?;
+    let common_name = csr.subject_name().entries_by_nid(Nid::COMMONNAME).next()
+        .and_then(|entry| entry.data().to_string().ok());
+
+    let der = csr.to_der()?;
+    let (_, parsed) = X509CertificationRequest::from_der(&der)
+        .map_err(|e| ApiError::BadRequest(format!("Failed to decode CSR: {}", e)))?;
+    let dns_names = parsed.requested_extensions().into_iter().flatten()
+        .filter_map(|extension| match extension {
+            ParsedExtension::SubjectAlternativeName(san) => Some(san),
+            _ => None
+        })
+        .flat_map(|san| san.general_names.iter())
+        .filter_map(|name| match name {
+            GeneralName::DNSName(dns_name) => Some(dns_name.to_string()),
+            _ => None
+        })
+        .collect();
+    Ok((common_name, dns_names))
+}
+
+fn pem_string(cert: &X509) -> Result<String, ApiError> {
+    Ok(String::from_utf8_lossy(&cert.to_pem()?).trim_end().to_string())
+}
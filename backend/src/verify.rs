@@ -0,0 +1,339 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::DateTime;
+use openssl::base64;
+use openssl::pkcs12::Pkcs12;
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::verify::X509VerifyFlags;
+use openssl::x509::{X509NameRef, X509StoreContext, X509};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
+use crate::ApiError;
+use crate::cert::{get_serial_hex, Certificate};
+use crate::data::api::{VerificationCheck, VerificationReport, VerifyCertificateRequest};
+use crate::data::enums::{CertificateFormat, CertificateType, CheckStatus, RevocationStatus};
+use crate::db::VaulTLSDB;
+
+/// Certificates expiring within this many seconds produce a warning.
+const EXPIRY_WARNING_SECS: i64 = 60 * 60 * 24 * 30;
+
+/// A certificate submitted for verification, together with any chain that came with it.
+pub(crate) struct SubmittedCertificate {
+    format: CertificateFormat,
+    pub(crate) leaf: X509,
+    chain: Vec<X509>,
+}
+
+/// Verifies a certificate against the VaulTLS CAs and returns a diagnostic report.
+pub(crate) fn verify_certificate(
+    db: &VaulTLSDB,
+    request: &VerifyCertificateRequest
+) -> Result<VerificationReport, ApiError> {
+    let submitted = parse_submitted_certificate(&request.certificate, &request.password)?;
+    let leaf_der = submitted.leaf.to_der()?;
+    let (_, parsed) = X509Certificate::from_der(&leaf_der)
+        .map_err(|e| ApiError::BadRequest(format!("Failed to decode certificate: {}", e)))?;
+
+    let cas = db.get_all_ca()?;
+    let (chain_check, chain, ca_id) = check_chain(&submitted, &cas)?;
+
+    let serial = get_serial_hex(&submitted.leaf)?;
+    let stored = match ca_id {
+        Some(_) => db.get_user_cert_by_serial(&serial)?,
+        None => None
+    };
+    let (revocation_status, revocation_check) = check_revocation(ca_id, &stored);
+
+    let validity_check = check_validity(&parsed);
+    let purpose_check = check_purpose(&parsed, request.purpose);
+    let hostname_check = check_hostname(&parsed, request.hostname.as_deref());
+
+    let valid = [&chain_check, &validity_check, &purpose_check, &revocation_check, &hostname_check]
+        .iter()
+        .all(|check| check.status != CheckStatus::Fail);
+
+    Ok(VerificationReport {
+        valid,
+        format: submitted.format,
+        subject: name_to_string(submitted.leaf.subject_name()),
+        issuer: name_to_string(submitted.leaf.issuer_name()),
+        serial,
+        not_before: parsed.validity().not_before.timestamp() * 1000,
+        not_after: parsed.validity().not_after.timestamp() * 1000,
+        subject_alt_names: get_subject_alt_names(&parsed),
+        extended_key_usage: get_extended_key_usage(&parsed),
+        chain,
+        ca_id,
+        certificate_id: stored.map(|cert| cert.id),
+        revocation_status,
+        chain_check,
+        validity_check,
+        purpose_check,
+        revocation_check,
+        hostname_check,
+    })
+}
+
+/// Parses a PEM, base64 DER or base64 PKCS#12 encoded certificate.
+pub(crate) fn parse_submitted_certificate(input: &str, password: &Option<String>) -> Result<SubmittedCertificate, ApiError> {
+    let input = input.trim();
+
+    if input.starts_with("-----BEGIN") {
+        let mut certs = X509::stack_from_pem(input.as_bytes())
+            .map_err(|_| ApiError::BadRequest("Failed to parse PEM certificate".to_string()))?;
+        if certs.is_empty() {
+            return Err(ApiError::BadRequest("No certificate found in PEM input".to_string()))
+        }
+        let leaf = certs.remove(0);
+        return Ok(SubmittedCertificate { format: CertificateFormat::Pem, leaf, chain: certs })
+    }
+
+    let stripped: String = input.split_whitespace().collect();
+    let der = base64::decode_block(&stripped)
+        .map_err(|_| ApiError::BadRequest("Certificate is neither PEM nor base64 encoded".to_string()))?;
+
+    if let Ok(leaf) = X509::from_der(&der) {
+        return Ok(SubmittedCertificate { format: CertificateFormat::Der, leaf, chain: Vec::new() })
+    }
+
+    let pkcs12 = Pkcs12::from_der(&der)
+        .map_err(|_| ApiError::BadRequest("Certificate is neither DER encoded X.509 nor PKCS#12".to_string()))?;
+    let parsed = pkcs12.parse2(password.as_deref().unwrap_or(""))
+        .map_err(|_| ApiError::BadRequest("Failed to open PKCS#12, is the password correct?".to_string()))?;
+    let leaf = parsed.cert
+        .ok_or(ApiError::BadRequest("PKCS#12 does not contain a certificate".to_string()))?;
+    let chain = parsed.ca.map(|ca| ca.into_iter().collect()).unwrap_or_default();
+
+    Ok(SubmittedCertificate { format: CertificateFormat::Pkcs12, leaf, chain })
+}
+
+/// Builds and verifies the chain against all VaulTLS CAs.
+/// Returns the check result, the subjects along the chain and the id of the anchoring CA.
+fn check_chain(
+    submitted: &SubmittedCertificate,
+    cas: &[Certificate]
+) -> Result<(VerificationCheck, Vec<String>, Option<i64>), ApiError> {
+    if cas.is_empty() {
+        return Ok((VerificationCheck::new(CheckStatus::Fail, "VaulTLS has not been set-up yet"), Vec::new(), None))
+    }
+
+    let mut store_builder = X509StoreBuilder::new()?;
+    let mut ca_ders = Vec::new();
+    for ca in cas {
+        store_builder.add_cert(X509::from_der(&ca.cert)?)?;
+        ca_ders.push((ca.id, ca.cert.clone()));
+    }
+    // Validity dates are reported by their own check
+    store_builder.set_flags(X509VerifyFlags::NO_CHECK_TIME)?;
+    let store = store_builder.build();
+
+    let mut untrusted = Stack::new()?;
+    for cert in &submitted.chain {
+        untrusted.push(cert.clone())?;
+    }
+
+    let mut context = X509StoreContext::new()?;
+    let (verified, error, chain, root) = context.init(&store, &submitted.leaf, &untrusted, |ctx| {
+        let verified = ctx.verify_cert()?;
+        let error = ctx.error().error_string().to_string();
+        let chain = ctx.chain()
+            .map(|chain| chain.iter().map(|cert| name_to_string(cert.subject_name())).collect())
+            .unwrap_or_default();
+        let root = ctx.chain()
+            .and_then(|chain| chain.iter().last())
+            .map(|cert| cert.to_der())
+            .transpose()?;
+        Ok((verified, error, chain, root))
+    })?;
+
+    if !verified {
+        let check = VerificationCheck::new(CheckStatus::Fail, format!("Chain verification failed: {}", error));
+        return Ok((check, chain, None))
+    }
+
+    let ca_id = root.and_then(|root| ca_ders.iter().find(|(_, der)| *der == root).map(|(id, _)| *id));
+    let anchor = chain.last().cloned().unwrap_or_default();
+    let check = VerificationCheck::new(CheckStatus::Pass, format!("Chain verified up to CA {}", anchor));
+    Ok((check, chain, ca_id))
+}
+
+/// Checks the validity dates against the current time.
+fn check_validity(cert: &X509Certificate) -> VerificationCheck {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let not_before = cert.validity().not_before.timestamp();
+    let not_after = cert.validity().not_after.timestamp();
+
+    if now < not_before {
+        VerificationCheck::new(CheckStatus::Fail, format!("Certificate is not valid before {}", format_timestamp(not_before)))
+    } else if now > not_after {
+        VerificationCheck::new(CheckStatus::Fail, format!("Certificate expired on {}", format_timestamp(not_after)))
+    } else if not_after - now < EXPIRY_WARNING_SECS {
+        let days = (not_after - now) / (60 * 60 * 24);
+        VerificationCheck::new(CheckStatus::Warning, format!("Certificate expires in {} days", days))
+    } else {
+        VerificationCheck::new(CheckStatus::Pass, format!("Certificate is valid until {}", format_timestamp(not_after)))
+    }
+}
+
+/// Checks that key usage and extended key usage allow the intended purpose.
+fn check_purpose(cert: &X509Certificate, purpose: Option<CertificateType>) -> VerificationCheck {
+    let Some(purpose) = purpose else {
+        return VerificationCheck::new(CheckStatus::Skipped, "No intended purpose given")
+    };
+
+    if purpose == CertificateType::CA {
+        return match cert.is_ca() {
+            true => VerificationCheck::new(CheckStatus::Pass, "Certificate is a CA"),
+            false => VerificationCheck::new(CheckStatus::Fail, "Certificate is not a CA")
+        }
+    }
+
+    let (allowed, label) = match cert.extended_key_usage() {
+        Ok(eku) => match purpose {
+            CertificateType::Server => (eku.map(|eku| eku.value.any || eku.value.server_auth), "TLS server authentication"),
+            CertificateType::Timestamping => (eku.map(|eku| eku.value.time_stamping), "time stamping"),
+            _ => (eku.map(|eku| eku.value.any || eku.value.client_auth), "TLS client authentication")
+        },
+        Err(e) => return VerificationCheck::new(CheckStatus::Fail, format!("Malformed extended key usage: {}", e))
+    };
+
+    match cert.key_usage() {
+        Ok(Some(key_usage)) if !key_usage.value.digital_signature() => {
+            return VerificationCheck::new(CheckStatus::Fail, "Key usage does not include digitalSignature")
+        }
+        Err(e) => return VerificationCheck::new(CheckStatus::Fail, format!("Malformed key usage: {}", e)),
+        _ => {}
+    }
+
+    match allowed {
+        Some(true) => VerificationCheck::new(CheckStatus::Pass, format!("Extended key usage allows {}", label)),
+        Some(false) => VerificationCheck::new(CheckStatus::Fail, format!("Extended key usage does not allow {}", label)),
+        None => VerificationCheck::new(CheckStatus::Warning, "No extended key usage present, most clients accept any purpose")
+    }
+}
+
+/// Looks up the certificate and its revocation state in the VaulTLS database.
+fn check_revocation(ca_id: Option<i64>, stored: &Option<Certificate>) -> (RevocationStatus, VerificationCheck) {
+    match (ca_id, stored) {
+        (None, _) => (
+            RevocationStatus::Unknown,
+            VerificationCheck::new(CheckStatus::Skipped, "Certificate was not issued by a VaulTLS CA")
+        ),
+        (Some(_), None) => (
+            RevocationStatus::Unknown,
+            VerificationCheck::new(CheckStatus::Warning, "Certificate is not on record, it may have been deleted")
+        ),
+        (Some(_), Some(cert)) if cert.revoked_on.is_some() => (
+            RevocationStatus::Revoked,
+            VerificationCheck::new(CheckStatus::Fail, format!("Certificate '{}' was revoked on {}", cert.name, format_timestamp(cert.revoked_on.unwrap_or_default() / 1000)))
+        ),
+        (Some(_), Some(cert)) => (
+            RevocationStatus::Good,
+            VerificationCheck::new(CheckStatus::Pass, format!("Certificate is on record as '{}'", cert.name))
+        )
+    }
+}
+
+/// Matches the hostname against the DNS and IP subject alternative names.
+fn check_hostname(cert: &X509Certificate, hostname: Option<&str>) -> VerificationCheck {
+    let Some(hostname) = hostname.map(str::trim).filter(|hostname| !hostname.is_empty()) else {
+        return VerificationCheck::new(CheckStatus::Skipped, "No hostname given")
+    };
+
+    let names = match cert.subject_alternative_name() {
+        Ok(san) => san.map(|san| san.value.general_names.clone()).unwrap_or_default(),
+        Err(e) => return VerificationCheck::new(CheckStatus::Fail, format!("Malformed subject alternative name: {}", e))
+    };
+
+    let matched = match hostname.parse::<IpAddr>() {
+        Ok(ip) => names.iter().any(|name| matches!(name, GeneralName::IPAddress(bytes) if parse_ip(bytes) == Some(ip))),
+        Err(_) => names.iter().any(|name| matches!(name, GeneralName::DNSName(pattern) if matches_dns_name(pattern, hostname)))
+    };
+    if matched {
+        return VerificationCheck::new(CheckStatus::Pass, format!("{} matches a subject alternative name", hostname))
+    }
+
+    let has_dns_names = names.iter().any(|name| matches!(name, GeneralName::DNSName(_)));
+    let cn_matches = cert.subject().iter_common_name()
+        .filter_map(|cn| cn.as_str().ok())
+        .any(|cn| matches_dns_name(cn, hostname));
+    if !has_dns_names && cn_matches {
+        return VerificationCheck::new(CheckStatus::Warning, format!("{} only matches the common name, which most clients ignore", hostname))
+    }
+
+    VerificationCheck::new(CheckStatus::Fail, format!("{} does not match any subject alternative name", hostname))
+}
+
+/// Compares a DNS name against a SAN entry, supporting a single leftmost wildcard label.
+fn matches_dns_name(pattern: &str, hostname: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.').to_ascii_lowercase();
+    let hostname = hostname.trim_end_matches('.').to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => hostname
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest == suffix),
+        None => pattern == hostname
+    }
+}
+
+fn parse_ip(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?))),
+        16 => Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?))),
+        _ => None
+    }
+}
+
+pub(crate) fn get_subject_alt_names(cert: &X509Certificate) -> Vec<String> {
+    let Ok(Some(san)) = cert.subject_alternative_name() else { return Vec::new() };
+    san.value.general_names.iter().map(format_general_name).collect()
+}
+
+pub(crate) fn format_general_name(name: &GeneralName) -> String {
+    match name {
+        GeneralName::DNSName(dns) => format!("DNS:{}", dns),
+        GeneralName::RFC822Name(email) => format!("email:{}", email),
+        GeneralName::URI(uri) => format!("URI:{}", uri),
+        GeneralName::IPAddress(bytes) => match parse_ip(bytes) {
+            Some(ip) => format!("IP:{}", ip),
+            None => "IP:<invalid>".to_string()
+        },
+        other => format!("{:?}", other)
+    }
+}
+
+pub(crate) fn get_extended_key_usage(cert: &X509Certificate) -> Vec<String> {
+    let Ok(Some(eku)) = cert.extended_key_usage() else { return Vec::new() };
+    let eku = eku.value;
+    let mut usages: Vec<String> = [
+        (eku.any, "anyExtendedKeyUsage"),
+        (eku.server_auth, "serverAuth"),
+        (eku.client_auth, "clientAuth"),
+        (eku.code_signing, "codeSigning"),
+        (eku.email_protection, "emailProtection"),
+        (eku.time_stamping, "timeStamping"),
+        (eku.ocsp_signing, "OCSPSigning"),
+    ].iter().filter(|(set, _)| *set).map(|(_, name)| name.to_string()).collect();
+    usages.extend(eku.other.iter().map(|oid| oid.to_id_string()));
+    usages
+}
+
+/// Formats an X.509 name as comma separated short names, e.g. "CN=VaulTLS".
+pub(crate) fn name_to_string(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let field = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().to_string().unwrap_or_default();
+            format!("{}={}", field, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_timestamp(unix_secs: i64) -> String {
+    DateTime::from_timestamp(unix_secs, 0)
+        .map(|datetime| datetime.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
@@ -1,5 +1,5 @@
 import ApiClient from './ApiClient';
-import type {Certificate} from '@/types/Certificate';
+import type {Certificate, CertificateAuthority} from '@/types/Certificate';
 import type {CertificateRequirements} from "@/types/CertificateRequirements.ts";
 
 export const fetchCertificates = async (): Promise<Certificate[]> => {
@@ -23,6 +23,13 @@ export const deleteCertificate = async (id: number): Promise<void> => {
     await ApiClient.delete<void>(`/certificates/${id}`);
 };
 
-export const downloadCA = async (): Promise<void> => {
+export const fetchCertificateAuthorities = async (): Promise<CertificateAuthority[]> => {
+    return await ApiClient.get<CertificateAuthority[]>('/certificates/ca');
+};
+
+export const downloadCA = async (id?: number): Promise<void> => {
+    if (id !== undefined) {
+        return await ApiClient.download(`/certificates/ca/${id}/download`);
+    }
     return await ApiClient.download('/certificates/ca/download');
 };
@@ -1,15 +1,40 @@
 export enum CertificateType {
     Client = 0,
     Server = 1,
-    CA = 2
+    CA = 2,
+    SVID = 3,
+    Timestamping = 4
+}
+
+export enum CertificateProfile {
+    Default = 0,
+    EapTls = 1
+}
+
+export enum CaPurpose {
+    General = 0,
+    Client = 1,
+    Server = 2
+}
+
+export interface CertificateAuthority {
+    id: number;                         // Unique identifier for the CA
+    name: string;                       // CA name
+    created_on: string;                 // Date when the CA was created (UNIX timestamp in ms)
+    valid_until: string;                // Expiration date of the CA (UNIX timestamp in ms)
+    purpose: CaPurpose;                 // Certificate types the CA issues
+    active: boolean;                    // Whether the CA issues new certificates
 }
 
 export interface Certificate {
     id: number;                         // Unique identifier for the certificate
     name: string;                       // Certificate name
     created_on: string;                 // Date when the certificate was created (UNIX timestamp in ms)
+    valid_from: string;                 // Start of the validity, after created_on if scheduled (UNIX timestamp in ms)
     pkcs12_password: string;            // PKCS12 decryption password
     valid_until: string;                // Expiration date of the certificate (UNIX timestamp in ms)
     certificate_type: CertificateType   // Type of the certificate
     user_id: number;                    // User ID who owns the certificate
+    revoked_on: string | null;          // Revocation date (UNIX timestamp in ms)
+    superseded_by: number | null;       // ID of the certificate that replaced this one after a re-key or renewal
 }
@@ -1,4 +1,4 @@
-import type {CertificateType} from "@/types/Certificate.ts";
+import type {CertificateProfile, CertificateType} from "@/types/Certificate.ts";
 
 export interface CertificateRequirements {
     cert_name: string;
@@ -9,4 +9,9 @@ export interface CertificateRequirements {
     notify_user: boolean;
     cert_type: CertificateType;
     dns_names: string[];
+    spiffe_path?: string;
+    profile?: CertificateProfile;
+    upn?: string;
+    ca_id?: number;
+    not_before?: number;
 }
@@ -8,11 +8,20 @@ export enum PasswordRule {
     Required = 1,
     System = 2
 }
+export enum OpenVpnProtocol {
+    UDP = 0,
+    TCP = 1
+}
 export interface Settings {
     common: {
         password_enabled: boolean;
         password_rule: PasswordRule;
         vaultls_url: string;
+        pki_url: string;
+        spiffe_trust_domain: string;
+        lint_blocking: boolean;
+        key_pool?: { key_algorithm: number; size: number }[] | null;
+        profile_signing_certificate_id?: number | null;
     },
     mail: {
         smtp_host: string,
@@ -27,5 +36,12 @@ export interface Settings {
         secret: string,
         auth_url: string,
         callback_url: string;
+    };
+    openvpn: {
+        remote: string,
+        port: number,
+        protocol: OpenVpnProtocol,
+        cipher: string,
+        tls_crypt_key?: string;
     }
 }